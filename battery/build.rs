@@ -1,4 +1,5 @@
 use dbc_codegen::{Config, FeatureConfig};
+use heck::ToUpperCamelCase;
 
 use anyhow::{Context, Result};
 
@@ -24,5 +25,181 @@ fn main() -> Result<()> {
     }
     let mut out = std::io::BufWriter::new(std::fs::File::create(&messages_path).unwrap());
     dbc_codegen::codegen(config, &mut out).context("dbc-codegen failed")?;
+
+    let module_messages_path = String::from("src/abs_alliance_module_messages.rs");
+    let module_messages = generate_module_messages(&String::from_utf8_lossy(&dbc_file))
+        .context("failed to generate module messages")?;
+    std::fs::write(&module_messages_path, module_messages)
+        .with_context(|| format!("failed to write {module_messages_path}"))?;
+
     Ok(())
 }
+
+// Just enough of a DBC message to tell which messages are copies of
+// each other.
+struct Signal {
+    name: String,
+    // Everything after the name: bit layout, scaling, range, unit and
+    // receivers.
+    definition: String,
+}
+
+struct Message {
+    name: String,
+    size: usize,
+    signals: Vec<Signal>,
+}
+
+fn parse_dbc(dbc: &str) -> Result<Vec<Message>> {
+    let mut messages: Vec<Message> = vec![];
+
+    for line in dbc.lines() {
+        if let Some(rest) = line.strip_prefix("BO_ ") {
+            // BO_ 2671771653 BATT_boardADC_1_5: 6 Battery
+            let fields: Vec<&str> = rest.split_whitespace().collect();
+            if fields.len() < 3 {
+                anyhow::bail!("can't parse message: {line}");
+            }
+            messages.push(Message {
+                name: String::from(fields[1].trim_end_matches(':')),
+                size: fields[2].parse().context(format!("bad size: {line}"))?,
+                signals: vec![],
+            });
+        } else if let Some(rest) = line.trim_start().strip_prefix("SG_ ") {
+            //  SG_ BATT_boardADC_LOAD_5 : 32|16@1- (0.01,0) [0|100] "V" Host
+            let (name, definition) = rest
+                .split_once(':')
+                .context(format!("bad signal: {line}"))?;
+            let name = name.trim();
+            if name.contains(' ') {
+                // Multiplexed signal, we don't handle these.
+                anyhow::bail!("multiplexed signals are not supported: {line}");
+            }
+            let message = messages
+                .last_mut()
+                .context(format!("signal outside of a message: {line}"))?;
+            message.signals.push(Signal {
+                name: String::from(name),
+                definition: String::from(definition.trim()),
+            });
+        }
+    }
+
+    Ok(messages)
+}
+
+// Splits "BATT_modHvStatus_5" into ("BATT_modHvStatus", 5).
+fn split_module_suffix(name: &str) -> Option<(&str, usize)> {
+    let (base, index) = name.rsplit_once('_')?;
+    if index.is_empty() || !index.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((base, index.parse().ok()?))
+}
+
+// The battery modules each send their own copy of most messages, named
+// `BATT_something_n` with signals named `BATT_signal_n`, where n is the
+// module index.  The copies only differ in their ids and names, so this
+// generates a function that maps the dbc-codegen message for any module
+// to its module index and the first module's copy of the message, made
+// from the same payload.  That way the accessors are all dbc-codegen's
+// own.
+fn generate_module_messages(dbc: &str) -> Result<String> {
+    let messages = parse_dbc(dbc)?;
+
+    // Group the per-module messages into families, keeping the DBC order.
+    let mut families: Vec<(String, Vec<(usize, &Message)>)> = vec![];
+    for message in &messages {
+        let Some((base, index)) = split_module_suffix(&message.name) else {
+            continue;
+        };
+        if !message.name.starts_with("BATT_") {
+            continue;
+        }
+        let suffix = format!("_{index}");
+        if !message.signals.iter().all(|s| s.name.ends_with(&suffix)) {
+            continue;
+        }
+        match families.iter_mut().find(|(name, _)| name == base) {
+            Some((_, members)) => members.push((index, message)),
+            None => families.push((String::from(base), vec![(index, message)])),
+        }
+    }
+    // A family of one is just a message whose name ends in a number.
+    families.retain(|(_, members)| members.len() > 1);
+
+    let mut variants = String::new();
+    let mut arms = String::new();
+    let mut num_modules = 0;
+
+    for (family, members) in &families {
+        let variant_name = family.to_upper_camel_case();
+        let (template_index, template) = members[0];
+        let template_type = template.name.to_upper_camel_case();
+
+        variants += &format!(
+            "    /// {family}_n, decoded as {}.\n    {variant_name}(abs_alliance_can_messages::{template_type}),\n",
+            template.name
+        );
+
+        // Decoding one module's payload as another module's message is
+        // only right if they lay out their signals the same way.
+        let signals = |index: usize, message: &Message| -> Vec<(String, String)> {
+            let suffix = format!("_{index}");
+            message
+                .signals
+                .iter()
+                .map(|s| {
+                    (
+                        String::from(s.name.strip_suffix(&suffix).unwrap()),
+                        s.definition.clone(),
+                    )
+                })
+                .collect()
+        };
+        let template_signals = signals(template_index, template);
+
+        for (index, message) in members {
+            if signals(*index, message) != template_signals || message.size != template.size {
+                anyhow::bail!("{} doesn't match {}", message.name, template.name);
+            }
+
+            arms += &format!(
+                "        abs_alliance_can_messages::Messages::{}(m) => Some((\n            {index},\n            ModuleMessage::{variant_name}(\n                abs_alliance_can_messages::{template_type}::try_from(&m.raw()[..]).ok()?,\n            ),\n        )),\n",
+                message.name.to_upper_camel_case()
+            );
+
+            num_modules = num_modules.max(index + 1);
+        }
+    }
+
+    Ok(format!(
+        "// Generated by build.rs from powertrain_multimod_v78.00.007.dbc, don't edit.
+//
+// Every battery module sends its own copy of the BATT_something_n
+// messages, where n is the module index.  The copies only differ in
+// their ids and signal names, so `split_module_message()` maps any of
+// them to the module index and the first module's copy, and the
+// per-module signals only need to be handled once.
+
+use crate::abs_alliance_can_messages;
+
+/// The number of modules the DBC has per-module messages for.
+pub const NUM_MODULES: usize = {num_modules};
+
+#[derive(Clone, Copy, Debug)]
+pub enum ModuleMessage {{
+{variants}}}
+
+/// If `msg` is one of the per-module messages, returns the index of the
+/// module that sent it and its payload as the first module's message.
+pub fn split_module_message(
+    msg: &abs_alliance_can_messages::Messages,
+) -> Option<(usize, ModuleMessage)> {{
+    match msg {{
+{arms}        _ => None,
+    }}
+}}
+"
+    ))
+}
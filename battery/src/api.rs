@@ -0,0 +1,569 @@
+// A small HTTP/JSON API for reading and controlling the pack from other
+// programs on the LAN.
+//
+//     GET  /pack              pack-level telemetry and the commanded mode
+//     GET  /modules/{n}       everything we know about module n
+//     GET  /charge-request    the pack's BATT_chargerControl request
+//     POST /mode              {"mode": "None" | "Drive" | "Charge" | "Sleep"}
+//     GET  /metrics           telemetry for Prometheus
+//
+// Every telemetry value is either null (never received) or
+// {"value": ..., "age_s": 0.25, "stale": false}.
+//
+// The handlers don't touch the pack model directly, they send requests
+// to the app over the same channel as the control socket and the app
+// answers with JSON.
+
+use axum::response::IntoResponse;
+
+use battery::abs_alliance_can_messages;
+use battery::model;
+
+use crate::control;
+
+const JSON: &str = "application/json";
+
+#[derive(Clone)]
+struct ApiState {
+    request_tx: tokio::sync::mpsc::Sender<control::Request>,
+}
+
+#[derive(serde::Deserialize)]
+struct ModeRequest {
+    mode: String,
+}
+
+/// Serve the API on `listener` until the app goes away.  If the server
+/// fails, that's logged to stdout if `log_errors` (it would mess up the
+/// TUI).
+pub async fn serve(
+    listener: tokio::net::TcpListener,
+    request_tx: tokio::sync::mpsc::Sender<control::Request>,
+    log_errors: bool,
+) {
+    let router = axum::Router::new()
+        .route("/pack", axum::routing::get(get_pack))
+        .route("/modules/{n}", axum::routing::get(get_module))
+        .route("/charge-request", axum::routing::get(get_charge_request))
+        .route("/mode", axum::routing::post(post_mode))
+        .route("/metrics", axum::routing::get(get_metrics))
+        .with_state(ApiState { request_tx });
+    if let Err(e) = axum::serve(listener, router).await {
+        if log_errors {
+            println!("event kind=http_error error={:?}", e.to_string());
+        }
+    }
+}
+
+async fn get_pack(
+    axum::extract::State(state): axum::extract::State<ApiState>,
+) -> axum::response::Response {
+    ask(&state, control::Command::Pack, JSON).await
+}
+
+async fn get_module(
+    axum::extract::State(state): axum::extract::State<ApiState>,
+    axum::extract::Path(n): axum::extract::Path<usize>,
+) -> axum::response::Response {
+    if n >= model::NUM_MODULES {
+        return error(
+            axum::http::StatusCode::NOT_FOUND,
+            &format!("no module {n}, modules are 0 to {}", model::NUM_MODULES - 1),
+        );
+    }
+    ask(&state, control::Command::Module(n), JSON).await
+}
+
+async fn get_charge_request(
+    axum::extract::State(state): axum::extract::State<ApiState>,
+) -> axum::response::Response {
+    ask(&state, control::Command::ChargeRequest, JSON).await
+}
+
+async fn get_metrics(
+    axum::extract::State(state): axum::extract::State<ApiState>,
+) -> axum::response::Response {
+    ask(
+        &state,
+        control::Command::Metrics,
+        battery::prometheus::CONTENT_TYPE,
+    )
+    .await
+}
+
+async fn post_mode(
+    axum::extract::State(state): axum::extract::State<ApiState>,
+    axum::Json(request): axum::Json<ModeRequest>,
+) -> axum::response::Response {
+    match control::parse_mode(&request.mode) {
+        Some(mode) => match send(&state, control::Command::SetMode(mode)).await {
+            Ok(_) => axum::Json(mode_json(mode)).into_response(),
+            Err(response) => response,
+        },
+        None => error(
+            axum::http::StatusCode::BAD_REQUEST,
+            &format!(
+                "unknown mode {:?}, expected None, Drive, Charge or Sleep",
+                request.mode
+            ),
+        ),
+    }
+}
+
+// Pass a command to the app and wait for its answer.
+async fn send(
+    state: &ApiState,
+    command: control::Command,
+) -> Result<String, axum::response::Response> {
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+    let request = control::Request {
+        command,
+        reply: reply_tx,
+    };
+    let shutting_down = || {
+        error(
+            axum::http::StatusCode::SERVICE_UNAVAILABLE,
+            "battery app is shutting down",
+        )
+    };
+    if state.request_tx.send(request).await.is_err() {
+        return Err(shutting_down());
+    }
+    match reply_rx.await {
+        Ok(Ok(answer)) => Ok(answer),
+        Ok(Err(e)) => Err(error(axum::http::StatusCode::INTERNAL_SERVER_ERROR, &e)),
+        Err(_) => Err(shutting_down()),
+    }
+}
+
+// Pass a command to the app, and return its answer as `content_type`.
+async fn ask(
+    state: &ApiState,
+    command: control::Command,
+    content_type: &'static str,
+) -> axum::response::Response {
+    match send(state, command).await {
+        Ok(answer) => ([(axum::http::header::CONTENT_TYPE, content_type)], answer).into_response(),
+        Err(response) => response,
+    }
+}
+
+fn error(status: axum::http::StatusCode, message: &str) -> axum::response::Response {
+    (status, axum::Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+fn timestamped<T: Copy>(
+    value: &model::Timestamped<T>,
+    now: std::time::Instant,
+    f: impl Fn(T) -> serde_json::Value,
+) -> serde_json::Value {
+    match (value.get(), value.age(now)) {
+        (Some(v), Some(age)) => serde_json::json!({
+            "value": f(v),
+            "age_s": age.as_secs_f64(),
+            "stale": value.is_stale(now),
+        }),
+        _ => serde_json::Value::Null,
+    }
+}
+
+fn mode_json(
+    mode: abs_alliance_can_messages::HostBatteryRequestHostStateRequest,
+) -> serde_json::Value {
+    serde_json::json!({ "mode": format!("{mode:?}") })
+}
+
+pub fn pack_json(
+    battery_pack: &model::BatteryPack,
+    mode: abs_alliance_can_messages::HostBatteryRequestHostStateRequest,
+    now: std::time::Instant,
+) -> serde_json::Value {
+    let modules_present: Vec<usize> = battery_pack
+        .modules
+        .iter()
+        .enumerate()
+        .filter(|(_, battery_module)| battery_module.is_present())
+        .map(|(module_index, _)| module_index)
+        .collect();
+
+    serde_json::json!({
+        "mode": format!("{mode:?}"),
+        "modules_present": modules_present,
+        "status": timestamped(&battery_pack.pack_status, now, |status| serde_json::json!({
+            "state": status.state_name(),
+            "num_modules_on_hv_bus": status.num_modules_on_hv_bus,
+            "num_modules_configured": status.num_modules_configured,
+            "num_modules_on_network": status.num_modules_on_network,
+            "module_com_status_ok": status.module_com_status_ok,
+            "dsg_fets_welded": status.dsg_fets_welded,
+            "chg_fets_welded": status.chg_fets_welded,
+        })),
+        "diagnostic_connect": timestamped(&battery_pack.pack_diagnostic_connect, now, |connect| serde_json::json!({
+            "num_modules_connected_for_charge": connect.num_modules_connected_for_charge,
+            "num_modules_connected_for_drive": connect.num_modules_connected_for_drive,
+            "num_modules_standby_for_charge": connect.num_modules_standby_for_charge,
+            "num_modules_standby_for_drive": connect.num_modules_standby_for_drive,
+        })),
+        "hv_status": timestamped(&battery_pack.pack_hv_status, now, |hv_status| serde_json::json!({
+            "voltage": hv_status.voltage,
+            "current": hv_status.current,
+        })),
+        "soc": timestamped(&battery_pack.pack_soc, now, |soc| serde_json::json!({
+            "soc": soc.soc,
+            "user_soc": soc.user_soc,
+        })),
+        "charge_limits": timestamped(&battery_pack.pack_chg_limits, now, |limits| serde_json::json!({
+            "current_inst": limits.current_inst,
+            "current_10s": limits.current_10s,
+            "current_cont": limits.current_cont,
+            "bus_voltage_max": limits.bus_voltage_max,
+        })),
+        "discharge_limits": timestamped(&battery_pack.pack_dch_limits, now, |limits| serde_json::json!({
+            "current_inst": limits.current_inst,
+            "current_10s": limits.current_10s,
+            "current_cont": limits.current_cont,
+            "bus_voltage_min": limits.bus_voltage_min,
+        })),
+        "power_available": timestamped(&battery_pack.pack_pwr_available, now, |power| serde_json::json!({
+            "charge_kw": power.charge_kw,
+            "discharge_kw": power.discharge_kw,
+        })),
+    })
+}
+
+pub fn module_json(
+    module_index: usize,
+    battery_module: &model::BatteryModule,
+    now: std::time::Instant,
+) -> serde_json::Value {
+    let faults: Vec<serde_json::Value> = battery_module
+        .faults
+        .active()
+        .map(|(fault_info, active_fault)| {
+            serde_json::json!({
+                "name": fault_info.name,
+                "severity": fault_info.severity().name(),
+                "first_seen_s_ago": now.saturating_duration_since(active_fault.first_seen).as_secs_f64(),
+                "last_seen_s_ago": now.saturating_duration_since(active_fault.last_seen).as_secs_f64(),
+            })
+        })
+        .collect();
+    let v_bricks: Vec<serde_json::Value> = battery_module
+        .v_bricks
+        .iter()
+        .map(|v_brick| timestamped(v_brick, now, |v| serde_json::json!(v)))
+        .collect();
+
+    serde_json::json!({
+        "index": module_index,
+        "present": battery_module.is_present(),
+        "last_seen_s_ago": battery_module
+            .last_seen
+            .map(|last_seen| now.saturating_duration_since(last_seen).as_secs_f64()),
+        "serial_number": timestamped(&battery_module.serial_number, now, |serial| serde_json::json!(serial)),
+        "adc1": timestamped(&battery_module.adc1, now, |adc1| serde_json::json!({
+            "pack_voltage": adc1.pack_voltage,
+            "common_drain_voltage": adc1.common_drain_voltage,
+            "load_voltage": adc1.load_voltage,
+        })),
+        "adc2": timestamped(&battery_module.adc2, now, |adc2| serde_json::json!({
+            "rail_5v": adc2.rail_5v,
+            "rail_12v": adc2.rail_12v,
+            "rail_3v3": adc2.rail_3v3,
+        })),
+        "hv_status": timestamped(&battery_module.hv_status, now, |hv_status| serde_json::json!({
+            "voltage": hv_status.voltage,
+            "current": hv_status.current,
+        })),
+        "charge_limit": timestamped(&battery_module.charge_limit, now, |charge_limit| serde_json::json!({
+            "voltage": charge_limit.voltage,
+            "current": charge_limit.current,
+        })),
+        "soc": timestamped(&battery_module.soc, now, |soc| serde_json::json!({
+            "soc": soc.soc,
+            "soh": soc.soh,
+        })),
+        "temperatures_a": timestamped(&battery_module.temperatures_a, now, |temperatures| serde_json::json!({
+            "ambient": temperatures.ambient,
+            "module1": temperatures.module1,
+            "module2": temperatures.module2,
+        })),
+        "temperatures_b": timestamped(&battery_module.temperatures_b, now, |temperatures| serde_json::json!({
+            "fet": temperatures.fet,
+            "shunt": temperatures.shunt,
+        })),
+        "v_bricks": v_bricks,
+        "balancing": timestamped(&battery_module.balancing, now, |balancing| serde_json::json!(balancing)),
+        "faults": faults,
+    })
+}
+
+pub fn charge_request_json(
+    battery_pack: &model::BatteryPack,
+    now: std::time::Instant,
+) -> serde_json::Value {
+    timestamped(&battery_pack.charge_request, now, |charge_request| {
+        serde_json::json!({
+            "voltage": charge_request.voltage,
+            "current": charge_request.current,
+            "enable": charge_request.enable,
+        })
+    })
+}
+
+/// Everything worth graphing, in the Prometheus text format.  Module
+/// metrics are labeled with the module's serial number as well as its
+/// index, so dashboards keep following a module if the pack is
+/// rewired.  Bricks are numbered from 1, the same as in the TUI and the
+/// alarm events.  Stale values are left out rather than repeated.
+pub fn pack_metrics(
+    battery_pack: &model::BatteryPack,
+    mode: abs_alliance_can_messages::HostBatteryRequestHostStateRequest,
+    now: std::time::Instant,
+) -> String {
+    let mut metrics = battery::prometheus::Metrics::default();
+
+    for commanded in [
+        abs_alliance_can_messages::HostBatteryRequestHostStateRequest::None,
+        abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Drive,
+        abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Charge,
+        abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Sleep,
+    ] {
+        metrics.flag(
+            "battery_mode_commanded",
+            "1 for the mode we're commanding the pack to.",
+            &[("mode", &format!("{commanded:?}"))],
+            commanded == mode,
+        );
+    }
+
+    if let Some(hv_status) = battery_pack.pack_hv_status.fresh(now) {
+        metrics.gauge(
+            "battery_pack_voltage_volts",
+            "Pack voltage.",
+            &[],
+            hv_status.voltage.into(),
+        );
+        metrics.gauge(
+            "battery_pack_current_amperes",
+            "Pack current.",
+            &[],
+            hv_status.current.into(),
+        );
+    }
+    if let Some(soc) = battery_pack.pack_soc.fresh(now) {
+        metrics.gauge(
+            "battery_pack_soc_percent",
+            "Pack state of charge.",
+            &[],
+            soc.soc.into(),
+        );
+        metrics.gauge(
+            "battery_pack_user_soc_percent",
+            "Pack state of charge as shown to the user.",
+            &[],
+            soc.user_soc.into(),
+        );
+    }
+    if let Some(chg_limits) = battery_pack.pack_chg_limits.fresh(now) {
+        for (window, current) in [
+            ("inst", chg_limits.current_inst),
+            ("10s", chg_limits.current_10s),
+            ("cont", chg_limits.current_cont),
+        ] {
+            metrics.gauge(
+                "battery_pack_charge_limit_current_amperes",
+                "Pack charge current limit, instantaneous, for 10s, and continuous.",
+                &[("window", window)],
+                current.into(),
+            );
+        }
+        metrics.gauge(
+            "battery_pack_bus_voltage_max_volts",
+            "Highest bus voltage the pack allows.",
+            &[],
+            chg_limits.bus_voltage_max.into(),
+        );
+    }
+    if let Some(dch_limits) = battery_pack.pack_dch_limits.fresh(now) {
+        for (window, current) in [
+            ("inst", dch_limits.current_inst),
+            ("10s", dch_limits.current_10s),
+            ("cont", dch_limits.current_cont),
+        ] {
+            metrics.gauge(
+                "battery_pack_discharge_limit_current_amperes",
+                "Pack discharge current limit, instantaneous, for 10s, and continuous.",
+                &[("window", window)],
+                current.into(),
+            );
+        }
+        metrics.gauge(
+            "battery_pack_bus_voltage_min_volts",
+            "Lowest bus voltage the pack allows.",
+            &[],
+            dch_limits.bus_voltage_min.into(),
+        );
+    }
+    if let Some(pwr_available) = battery_pack.pack_pwr_available.fresh(now) {
+        for (direction, kw) in [
+            ("charge", pwr_available.charge_kw),
+            ("discharge", pwr_available.discharge_kw),
+        ] {
+            metrics.gauge(
+                "battery_pack_power_available_watts",
+                "Power the pack can take or give.",
+                &[("direction", direction)],
+                f64::from(kw) * 1000.0,
+            );
+        }
+    }
+    if let Some(charge_request) = battery_pack.charge_request.get() {
+        metrics.gauge(
+            "battery_charge_request_voltage_volts",
+            "Charge voltage requested by the pack.",
+            &[],
+            charge_request.voltage.into(),
+        );
+        metrics.gauge(
+            "battery_charge_request_current_amperes",
+            "Charge current requested by the pack.",
+            &[],
+            charge_request.current.into(),
+        );
+        metrics.flag(
+            "battery_charge_request_enabled",
+            "1 if the pack is asking to be charged.",
+            &[],
+            charge_request.enable,
+        );
+    }
+
+    for (module_index, battery_module) in battery_pack.modules.iter().enumerate() {
+        // Leave out slots that have never had a module in them.
+        let Some(last_seen) = battery_module.last_seen else {
+            continue;
+        };
+        let index = module_index.to_string();
+        let serial = battery_module
+            .serial_number
+            .get()
+            .map_or(String::new(), |serial| serial.to_string());
+        let labels = [("module", index.as_str()), ("serial", serial.as_str())];
+
+        metrics.flag(
+            "battery_module_present",
+            "1 if the module has been heard from recently.",
+            &labels,
+            battery_module.is_present(),
+        );
+        metrics.gauge(
+            "battery_module_last_seen_seconds",
+            "Time since the module was last heard from.",
+            &labels,
+            now.saturating_duration_since(last_seen).as_secs_f64(),
+        );
+
+        if let Some(hv_status) = battery_module.hv_status.fresh(now) {
+            metrics.gauge(
+                "battery_module_voltage_volts",
+                "Module voltage.",
+                &labels,
+                hv_status.voltage.into(),
+            );
+            metrics.gauge(
+                "battery_module_current_amperes",
+                "Module current.",
+                &labels,
+                hv_status.current.into(),
+            );
+        }
+        if let Some(soc) = battery_module.soc.fresh(now) {
+            metrics.gauge(
+                "battery_module_soc_percent",
+                "Module state of charge.",
+                &labels,
+                soc.soc.into(),
+            );
+            metrics.gauge(
+                "battery_module_soh_percent",
+                "Module state of health.",
+                &labels,
+                soc.soh.into(),
+            );
+        }
+        if let Some(charge_limit) = battery_module.charge_limit.fresh(now) {
+            metrics.gauge(
+                "battery_module_charge_limit_voltage_volts",
+                "Module charge voltage limit.",
+                &labels,
+                charge_limit.voltage.into(),
+            );
+            metrics.gauge(
+                "battery_module_charge_limit_current_amperes",
+                "Module continuous charge current limit.",
+                &labels,
+                charge_limit.current.into(),
+            );
+        }
+
+        let mut temperatures = vec![];
+        if let Some(temperatures_a) = battery_module.temperatures_a.fresh(now) {
+            temperatures.push(("ambient", temperatures_a.ambient));
+            temperatures.push(("module1", temperatures_a.module1));
+            temperatures.push(("module2", temperatures_a.module2));
+        }
+        if let Some(temperatures_b) = battery_module.temperatures_b.fresh(now) {
+            temperatures.push(("fet", temperatures_b.fet));
+            temperatures.push(("shunt", temperatures_b.shunt));
+        }
+        for (sensor, temperature) in temperatures {
+            metrics.gauge(
+                "battery_module_temperature_celsius",
+                "Module temperatures.",
+                &[labels[0], labels[1], ("sensor", sensor)],
+                temperature.into(),
+            );
+        }
+
+        if let Some(adc2) = battery_module.adc2.fresh(now) {
+            for (rail, voltage) in [
+                ("5v", adc2.rail_5v),
+                ("12v", adc2.rail_12v),
+                ("3v3", adc2.rail_3v3),
+            ] {
+                metrics.gauge(
+                    "battery_module_rail_voltage_volts",
+                    "Module board supply rails.",
+                    &[labels[0], labels[1], ("rail", rail)],
+                    voltage.into(),
+                );
+            }
+        }
+
+        for (brick_index, v_brick) in battery_module.v_bricks.iter().enumerate() {
+            if let Some(voltage) = v_brick.fresh(now) {
+                let brick = (brick_index + 1).to_string();
+                metrics.gauge(
+                    "battery_brick_voltage_volts",
+                    "Brick (parallel cell group) voltage.",
+                    &[labels[0], labels[1], ("brick", brick.as_str())],
+                    voltage.into(),
+                );
+            }
+        }
+        if let Some(balancing) = battery_module.balancing.fresh(now) {
+            for (brick_index, balancing) in balancing.iter().enumerate() {
+                let brick = (brick_index + 1).to_string();
+                metrics.flag(
+                    "battery_brick_balancing",
+                    "1 if the brick's balancing resistor is on.",
+                    &[labels[0], labels[1], ("brick", brick.as_str())],
+                    *balancing,
+                );
+            }
+        }
+    }
+
+    metrics.render()
+}
@@ -1,197 +1,165 @@
-use embedded_can::Frame;
 use futures::future::FusedFuture;
 use futures::FutureExt;
 use futures_util::stream::StreamExt;
 
 use ratatui::style::Stylize;
+use ratatui::widgets::Widget;
 
 use battery::abs_alliance_can_messages;
+use battery::history;
+use battery::host;
+use battery::model;
+use battery::recorder;
+use battery::thresholds;
+use battery::transport;
 use battery::tui;
 
-// BATT_boardADC_1_5(
-//     BATT_boardADC_PACK_5: 45.59 V,
-//     BATT_boardADC_COMMON_DRAIN_5: 45.62 V,
-//     BATT_boardADC_LOAD_5: 45.57 V
-// )
-#[derive(Clone, Copy, Debug, Default)]
-struct Adc1 {
-    pack_voltage: f32,
-    common_drain_voltage: f32,
-    load_voltage: f32,
-}
+use crate::api;
+use crate::control;
+use crate::events;
+use crate::replay;
 
-// BATT_boardADC_2_5(
-//     BATT_boardADC_5V_5: 4.97 V,
-//     BATT_boardADC_12V_5: 12.47 V,
-//     BATT_boardADC_3V3_5: 3.3000000000000003 V
-// )
-#[derive(Clone, Copy, Debug, Default)]
-struct Adc2 {
-    rail_5v: f32,
-    rail_12v: f32,
-    rail_3v3: f32,
-}
+/// How far the arrow keys seek in a replay.
+const REPLAY_SEEK_STEP: std::time::Duration = std::time::Duration::from_secs(10);
 
-// BATT_modHvStatus_0(
-//     BATT_iModFiltered_0: 0.015 A,
-//     BATT_iMod_0: 0.085 A,
-//     BATT_vMod_0: 47.85 V,
-//     BATT_vModSumOfCells_0: 47.83 V
-// )
-#[derive(Clone, Copy, Debug, Default)]
-struct HvStatus {
-    voltage: f32,
-    current: f32,
-}
+/// Where the CAN frames come from.
+#[derive(Debug)]
+enum Source<T> {
+    Can(T),
 
-// BATT_modChgLimits_0(
-//     BATT_iModChgLimit10s_0: 50.4 A,
-//     BATT_iModChgLimitCont_0: 12.0 A,
-//     BATT_iModChgLimitInst_0: 64.8 A,
-//     BATT_vModChgLimit_0: 52.835 V
-// )
-#[derive(Clone, Copy, Debug, Default)]
-struct ChargeLimit {
-    voltage: f32,
-    current: f32,
+    /// Playing back a candump log.  We never transmit in this mode.
+    Replay(replay::Replay),
 }
 
-// BATT_modSOC_5(
-//     BATT_sohcMod_5: 91.0 %,
-//     BATT_socMod_5: 8.1 %,
-//     BATT_socLimiting_5: 8.75 %,
-//     BATT_idSOCLimiting_5: 6
-// )
-#[derive(Clone, Copy, Debug, Default)]
-struct SOC {
-    soc: f32,
-    soh: f32,
-}
+/// What the TUI is showing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum View {
+    /// The pack, its limits and faults, and the module list.
+    Overview,
 
-// BATT_modTemperaturesA_5(
-//     BATT_tAmbient_5: 25.900000000000002 deg C,
-//     BATT_tModule1_5: 23.8 deg C,
-//     BATT_tModule2_5: 25.8 deg C
-// )
-#[derive(Clone, Copy, Debug, Default)]
-struct TemperaturesA {
-    ambient: f32,
-    module1: f32,
-    module2: f32,
-}
+    /// Everything we know about the selected module.
+    ModuleDetail,
 
-// BATT_modTemperaturesB_0(
-//     BATT_tFET_0: 22.900000000000002 deg C,
-//     BATT_tShunt_0: 23.6 deg C
-// )
-#[derive(Clone, Copy, Debug, Default)]
-struct TemperaturesB {
-    fet: f32,
-    shunt: f32,
-}
+    /// Charts of the pack's recent history.
+    Charts,
 
-#[derive(Clone, Copy, Debug, Default)]
-struct BatteryModule {
-    serial_number: u64,
-
-    adc1: Adc1,
-    adc2: Adc2,
-    hv_status: HvStatus,
-    charge_limit: ChargeLimit,
-    soc: SOC,
-    temperatures_a: TemperaturesA,
-    temperatures_b: TemperaturesB,
-    v_bricks: [f32; 14],
-    balancing: [bool; 14],
-
-    last_seen: Option<std::time::Instant>,
+    /// Every brick voltage in the pack, colour-scaled.
+    Heatmap,
 }
 
-#[allow(dead_code)]
-#[derive(Clone, Copy, Debug)]
-struct ChargeRequest {
-    voltage: f32,
-    current: f32,
-    enable: bool,
-    last_seen: std::time::Instant,
+/// How far back the charts go.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ChartWindow {
+    OneMinute,
+    TenMinutes,
+    OneHour,
 }
 
-// BATT_packDiagnosticConnect(
-//     BATT_packModChgEntryConnOK_M: 3,
-//     BATT_packModDrvEntryConnOK_M: 3,
-//     BATT_packModChgStandbyConnOK_M: 0,
-//     BATT_packModDrvStandbyConnOK_M: 0
-// )
-#[derive(Clone, Copy, Debug, Default)]
-struct PackDiagnosticConnect {
-    num_modules_connected_for_charge: u16,
-    num_modules_connected_for_drive: u16,
-    num_modules_standby_for_charge: u16,
-    num_modules_standby_for_drive: u16,
-}
+impl ChartWindow {
+    fn duration(self) -> std::time::Duration {
+        match self {
+            ChartWindow::OneMinute => std::time::Duration::from_secs(60),
+            ChartWindow::TenMinutes => std::time::Duration::from_secs(10 * 60),
+            ChartWindow::OneHour => history::PACK_HISTORY_AGE,
+        }
+    }
 
-// BATT_packHvStatus(
-//     BATT_vPackLoad: 49.83 V,
-//     BATT_iPack: 8.2,
-//     BATT_vPack: 48.96 V,
-//     BATT_iPackFiltered: 8.2 A
-// )
-#[derive(Clone, Copy, Debug, Default)]
-struct PackHvStatus {
-    voltage: f32,
-    current: f32,
-}
+    fn name(self) -> &'static str {
+        match self {
+            ChartWindow::OneMinute => "1 min",
+            ChartWindow::TenMinutes => "10 min",
+            ChartWindow::OneHour => "1 h",
+        }
+    }
 
-// BATT_packSOC(
-//     BATT_packUserSOC: 20.1 %,
-//     BATT_packSOC: 22.5 %
-// )
-#[derive(Clone, Copy, Debug, Default)]
-struct PackSOC {
-    soc: f32,
-    user_soc: f32,
+    fn next(self) -> Self {
+        match self {
+            ChartWindow::OneMinute => ChartWindow::TenMinutes,
+            ChartWindow::TenMinutes => ChartWindow::OneHour,
+            ChartWindow::OneHour => ChartWindow::OneMinute,
+        }
+    }
 }
 
-#[derive(Clone, Copy, Debug)]
-struct BatteryPack {
+#[derive(Debug)]
+pub struct App<T> {
+    source: Source<T>,
     mode: abs_alliance_can_messages::HostBatteryRequestHostStateRequest,
-    modules: [BatteryModule; 10],
-    charge_request: Option<ChargeRequest>,
-    pack_diagnostic_connect: PackDiagnosticConnect,
-    pack_hv_status: PackHvStatus,
-    pack_soc: PackSOC,
+    battery_pack: model::BatteryPack,
+    recorder: Option<recorder::Recorder>,
+
+    /// Why recording stopped, if it failed.
+    recording_error: Option<String>,
+
+    /// True when we're running without a terminal and can log to
+    /// stdout.
+    headless: bool,
+
+    view: View,
+
+    /// The module highlighted in the module list, and shown in the
+    /// detail view.
+    selected_module: usize,
+
+    /// How far the module list is scrolled.  Rendering only gets
+    /// `&self`, so this is a Cell to keep the scroll position from one
+    /// redraw to the next.
+    module_list_offset: std::cell::Cell<usize>,
+
+    module_history: [history::ModuleHistory; model::NUM_MODULES],
+    pack_history: history::PackHistory,
+    chart_window: ChartWindow,
+
+    /// The limits for the TUI's colours and the alarm events.
+    thresholds: thresholds::Thresholds,
+
+    /// The alarms as of the last tick.  The next tick's check starts
+    /// from them, so they don't clear until the value's well clear of
+    /// the limit.
+    alarms: Vec<thresholds::Alarm>,
 }
 
-// Have to impl this by hand because
-// abs_alliance_can_messages::HostBatteryRequestHostStateRequest doesn't
-// impl default.
-impl Default for BatteryPack {
-    fn default() -> Self {
-        BatteryPack {
+impl<T: transport::Transport> App<T> {
+    pub fn new(
+        transport: T,
+        recorder: Option<recorder::Recorder>,
+        thresholds: thresholds::Thresholds,
+    ) -> Self {
+        Self {
+            source: Source::Can(transport),
             mode: abs_alliance_can_messages::HostBatteryRequestHostStateRequest::None,
-            modules: [BatteryModule::default(); 10],
-            charge_request: None,
-            pack_diagnostic_connect: PackDiagnosticConnect::default(),
-            pack_hv_status: PackHvStatus::default(),
-            pack_soc: PackSOC::default(),
+            battery_pack: model::BatteryPack::default(),
+            recorder,
+            recording_error: None,
+            headless: false,
+            view: View::Overview,
+            selected_module: 0,
+            module_list_offset: std::cell::Cell::new(0),
+            module_history: Default::default(),
+            pack_history: history::PackHistory::default(),
+            chart_window: ChartWindow::TenMinutes,
+            thresholds,
+            alarms: vec![],
         }
     }
-}
 
-#[derive(Debug)]
-pub struct App {
-    can_socket_rx: tokio_socketcan::CANSocket,
-    can_socket_tx: tokio_socketcan::CANSocket,
-    battery_pack: BatteryPack,
-}
-
-impl App {
-    pub fn new(can_interface: &str) -> Result<Self, eyre::Report> {
-        Ok(Self {
-            can_socket_rx: tokio_socketcan::CANSocket::open(can_interface)?,
-            can_socket_tx: tokio_socketcan::CANSocket::open(can_interface)?,
-            battery_pack: BatteryPack::default(),
-        })
+    pub fn replay(replay: replay::Replay, thresholds: thresholds::Thresholds) -> Self {
+        Self {
+            source: Source::Replay(replay),
+            mode: abs_alliance_can_messages::HostBatteryRequestHostStateRequest::None,
+            battery_pack: model::BatteryPack::default(),
+            recorder: None,
+            recording_error: None,
+            headless: false,
+            view: View::Overview,
+            selected_module: 0,
+            module_list_offset: std::cell::Cell::new(0),
+            module_history: Default::default(),
+            pack_history: history::PackHistory::default(),
+            chart_window: ChartWindow::TenMinutes,
+            thresholds,
+            alarms: vec![],
+        }
     }
 
     pub async fn sleep(&mut self) -> Result<(), eyre::Report> {
@@ -201,7 +169,13 @@ impl App {
         .await
     }
 
-    pub async fn run(&mut self, mut terminal: tui::Tui) -> Result<(), eyre::Report> {
+    /// Run the TUI until the user quits, also taking commands from
+    /// `control`.
+    pub async fn run(
+        &mut self,
+        mut terminal: tui::Tui,
+        mut control: tokio::sync::mpsc::Receiver<control::Request>,
+    ) -> Result<(), eyre::Report> {
         // Initial setup so it's snappy on startup.
         terminal.draw(|frame| self.render_frame(frame))?;
         let _ = self.send_mode_command().await?;
@@ -217,52 +191,48 @@ impl App {
             tokio::select! {
                 biased;
 
-                maybe_frame = self.can_socket_rx.next() => {
+                maybe_frame = next_frame(&mut self.source) => {
                     match maybe_frame {
-                        Some(Ok(frame)) => {
-                            let _ = self.handle_can_frame(frame);
+                        Some((id, data, now)) => {
+                            let _ = self.handle_frame(id, &data, now);
                             need_redraw.notify_one();
                             if timeout.is_terminated() {
                                 timeout.set(tokio::time::sleep(tokio::time::Duration::from_secs(1)).fuse());
                             }
                         }
-                        _ => ()
+                        None => ()
                     }
                 }
 
                 maybe_event = event_reader.next() => {
                     match maybe_event {
                         Some(Ok(crossterm::event::Event::Key(key))) => {
-                            match key.code {
-                                crossterm::event::KeyCode::Char('q') => {
-                                    break Ok(());
-                                }
-                                crossterm::event::KeyCode::Char('s') => {
-                                    self.battery_pack.mode = abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Sleep;
-                                    // The Sleep mode is special.  We need
-                                    // to send the Sleep command once and
-                                    // then not again, or the subsequent
-                                    // Sleep commands will wake the pack
-                                    // up briefly to respond to the Sleep.
-                                    let _ = self.sleep().await?;
-                                }
-                                crossterm::event::KeyCode::Char('c') => {
-                                    self.battery_pack.mode = abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Charge;
-                                }
-                                crossterm::event::KeyCode::Char('d') => {
-                                    self.battery_pack.mode = abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Drive;
-                                }
-                                crossterm::event::KeyCode::Char('n') => {
-                                    self.battery_pack.mode = abs_alliance_can_messages::HostBatteryRequestHostStateRequest::None;
+                            if key.code == crossterm::event::KeyCode::Char('q') {
+                                break Ok(());
+                            }
+                            if self.handle_view_key(key.code) {
+                                need_redraw.notify_one();
+                                continue;
+                            }
+                            if let Source::Replay(_) = self.source {
+                                self.handle_replay_key(key.code);
+                                if timeout.is_terminated() {
+                                    timeout.set(tokio::time::sleep(tokio::time::Duration::from_secs(1)).fuse());
                                 }
-                                _ => (),
+                                need_redraw.notify_one();
+                                continue;
+                            }
+                            let mode = match key.code {
+                                crossterm::event::KeyCode::Char('s') => Some(abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Sleep),
+                                crossterm::event::KeyCode::Char('c') => Some(abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Charge),
+                                crossterm::event::KeyCode::Char('d') => Some(abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Drive),
+                                crossterm::event::KeyCode::Char('n') => Some(abs_alliance_can_messages::HostBatteryRequestHostStateRequest::None),
+                                _ => None,
+                            };
+                            match mode {
+                                Some(mode) => self.set_mode(mode).await?,
+                                None => self.send_mode_command().await?,
                             }
-                            // Send the new mode command right away
-                            // (don't wait for the next timeout).
-                            // This makes it more responsive to user
-                            // input.  `send_mode_command()` does *not*
-                            // send a packet if we're in Sleep mode.
-                            let _ = self.send_mode_command().await?;
 
                             // If we had put the pack to Sleep and turned
                             // timeouts ticks off, restart the timout
@@ -278,42 +248,34 @@ impl App {
                     }
                 }
 
+                Some(request) = control.recv() => {
+                    self.handle_request(request).await?;
+                    if timeout.is_terminated() {
+                        timeout.set(tokio::time::sleep(tokio::time::Duration::from_secs(1)).fuse());
+                    }
+                    need_redraw.notify_one();
+                }
+
                 _ = need_redraw.notified() => {
                     terminal.draw(|frame| self.render_frame(frame))?;
                 }
 
                 _ = &mut timeout => {
-                    let _ = self.send_mode_command().await?;
-
-                    let now = std::time::Instant::now();
-
-                    // This keeps us awake if we still have things on
-                    // the screen that might need to be timed out.
-                    let mut need_to_stay_awake = false;
-
-                    // Time out battery modules.
-                    for battery_module in &mut self.battery_pack.modules {
-                        if let Some(last_seen) = battery_module.last_seen {
-                            if (now - last_seen) < std::time::Duration::from_secs(2) {
-                                // This module is still there, check again next tick.
-                                need_to_stay_awake = true;
-                            } else {
-                                battery_module.last_seen = None;
-                                need_redraw.notify_one();
-                            }
-                        }
+                    if self.tick().await? {
+                        need_redraw.notify_one();
                     }
 
-                    // Time out the pack charge request.
-                    if let Some(charge_request) = self.battery_pack.charge_request {
-                        if (now - charge_request.last_seen) < std::time::Duration::from_secs(2) {
-                            // The Charge Request is still there, check again next tick.
-                            need_to_stay_awake = true;
-                        } else {
-                            self.battery_pack.charge_request = None;
+                    // This keeps us awake if we still have things on
+                    // the screen that might need to be timed out.  A
+                    // replay always stays awake to keep its position
+                    // display moving.
+                    let need_to_stay_awake = match self.source {
+                        Source::Can(_) => host::needs_tick(&self.battery_pack, self.mode),
+                        Source::Replay(_) => {
                             need_redraw.notify_one();
+                            true
                         }
-                    }
+                    };
 
                     // Set the next tick timeout, if needed.  We disable
                     // our internal timer when we're in Sleep mode
@@ -329,974 +291,1785 @@ impl App {
     }
 }
 
-impl App {
-    fn render_frame(&self, frame: &mut ratatui::Frame) {
-        frame.render_widget(self, frame.area());
-    }
+impl<T: transport::Transport> App<T> {
+    /// Run without a terminal, taking mode commands from `control` and
+    /// logging events to stdout, until SIGTERM or SIGINT.
+    pub async fn run_headless(
+        &mut self,
+        mut control: tokio::sync::mpsc::Receiver<control::Request>,
+    ) -> Result<(), eyre::Report> {
+        let mut sigterm =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        let mut sigint = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::interrupt())?;
 
-    fn handle_can_frame(&mut self, frame: tokio_socketcan::CANFrame) -> Result<(), eyre::Report> {
-        let id: embedded_can::Id = if frame.is_extended() {
-            match embedded_can::ExtendedId::new(frame.id()) {
-                Some(id) => embedded_can::Id::Extended(id),
-                None => return Err(eyre::eyre!("invalid extended frame id {}", frame.id())),
-            }
-        } else {
-            match embedded_can::StandardId::new(frame.id() as u16) {
-                Some(id) => embedded_can::Id::Standard(id),
-                None => return Err(eyre::eyre!("invalid standard frame id {}", frame.id())),
-            }
-        };
+        self.headless = true;
+        let mut event_tracker = events::EventTracker::default();
+        self.log_event(&format!("event kind=started mode={:?}", self.mode));
+        self.send_mode_command().await?;
 
-        let msg = abs_alliance_can_messages::Messages::from_can_message(id, frame.data())?;
-        // println!("{:#?}", msg);
+        let timeout = tokio::time::sleep(tokio::time::Duration::from_secs(1)).fuse();
+        tokio::pin!(timeout);
 
-        match msg {
-            abs_alliance_can_messages::Messages::BattDeviceInfo0(m) => {
-                self.battery_pack.modules[0].serial_number = m.batt_serial_number_0();
-                self.battery_pack.modules[0].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDeviceInfo1(m) => {
-                self.battery_pack.modules[1].serial_number = m.batt_serial_number_1();
-                self.battery_pack.modules[1].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDeviceInfo2(m) => {
-                self.battery_pack.modules[2].serial_number = m.batt_serial_number_2();
-                self.battery_pack.modules[2].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDeviceInfo3(m) => {
-                self.battery_pack.modules[3].serial_number = m.batt_serial_number_3();
-                self.battery_pack.modules[3].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDeviceInfo4(m) => {
-                self.battery_pack.modules[4].serial_number = m.batt_serial_number_4();
-                self.battery_pack.modules[4].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDeviceInfo5(m) => {
-                self.battery_pack.modules[5].serial_number = m.batt_serial_number_5();
-                self.battery_pack.modules[5].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDeviceInfo6(m) => {
-                self.battery_pack.modules[6].serial_number = m.batt_serial_number_6();
-                self.battery_pack.modules[6].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDeviceInfo7(m) => {
-                self.battery_pack.modules[7].serial_number = m.batt_serial_number_7();
-                self.battery_pack.modules[7].last_seen = Some(std::time::Instant::now());
-            }
+        loop {
+            tokio::select! {
+                biased;
 
-            abs_alliance_can_messages::Messages::BattBoardAdc10(m) => {
-                self.battery_pack.modules[0].adc1.pack_voltage = m.batt_board_adc_load_0();
-                self.battery_pack.modules[0].adc1.common_drain_voltage =
-                    m.batt_board_adc_common_drain_0();
-                self.battery_pack.modules[0].adc1.load_voltage = m.batt_board_adc_load_0();
-                self.battery_pack.modules[0].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattBoardAdc11(m) => {
-                self.battery_pack.modules[1].adc1.pack_voltage = m.batt_board_adc_load_1();
-                self.battery_pack.modules[1].adc1.common_drain_voltage =
-                    m.batt_board_adc_common_drain_1();
-                self.battery_pack.modules[1].adc1.load_voltage = m.batt_board_adc_load_1();
-                self.battery_pack.modules[1].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattBoardAdc12(m) => {
-                self.battery_pack.modules[2].adc1.pack_voltage = m.batt_board_adc_load_2();
-                self.battery_pack.modules[2].adc1.common_drain_voltage =
-                    m.batt_board_adc_common_drain_2();
-                self.battery_pack.modules[2].adc1.load_voltage = m.batt_board_adc_load_2();
-                self.battery_pack.modules[2].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattBoardAdc13(m) => {
-                self.battery_pack.modules[3].adc1.pack_voltage = m.batt_board_adc_load_3();
-                self.battery_pack.modules[3].adc1.common_drain_voltage =
-                    m.batt_board_adc_common_drain_3();
-                self.battery_pack.modules[3].adc1.load_voltage = m.batt_board_adc_load_3();
-                self.battery_pack.modules[3].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattBoardAdc14(m) => {
-                self.battery_pack.modules[4].adc1.pack_voltage = m.batt_board_adc_load_4();
-                self.battery_pack.modules[4].adc1.common_drain_voltage =
-                    m.batt_board_adc_common_drain_4();
-                self.battery_pack.modules[4].adc1.load_voltage = m.batt_board_adc_load_4();
-                self.battery_pack.modules[4].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattBoardAdc15(m) => {
-                self.battery_pack.modules[5].adc1.pack_voltage = m.batt_board_adc_load_5();
-                self.battery_pack.modules[5].adc1.common_drain_voltage =
-                    m.batt_board_adc_common_drain_5();
-                self.battery_pack.modules[5].adc1.load_voltage = m.batt_board_adc_load_5();
-                self.battery_pack.modules[5].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattBoardAdc16(m) => {
-                self.battery_pack.modules[6].adc1.pack_voltage = m.batt_board_adc_load_6();
-                self.battery_pack.modules[6].adc1.common_drain_voltage =
-                    m.batt_board_adc_common_drain_6();
-                self.battery_pack.modules[6].adc1.load_voltage = m.batt_board_adc_load_6();
-                self.battery_pack.modules[6].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattBoardAdc17(m) => {
-                self.battery_pack.modules[7].adc1.pack_voltage = m.batt_board_adc_load_7();
-                self.battery_pack.modules[7].adc1.common_drain_voltage =
-                    m.batt_board_adc_common_drain_7();
-                self.battery_pack.modules[7].adc1.load_voltage = m.batt_board_adc_load_7();
-                self.battery_pack.modules[7].last_seen = Some(std::time::Instant::now());
-            }
+                _ = sigterm.recv() => {
+                    self.log_event("event kind=stopping signal=SIGTERM");
+                    break Ok(());
+                }
 
-            abs_alliance_can_messages::Messages::BattBoardAdc20(m) => {
-                self.battery_pack.modules[0].adc2.rail_5v = m.batt_board_adc_5v_0();
-                self.battery_pack.modules[0].adc2.rail_12v = m.batt_board_adc_12v_0();
-                self.battery_pack.modules[0].adc2.rail_3v3 = m.batt_board_adc_3v3_0();
-                self.battery_pack.modules[0].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattBoardAdc21(m) => {
-                self.battery_pack.modules[1].adc2.rail_5v = m.batt_board_adc_5v_1();
-                self.battery_pack.modules[1].adc2.rail_12v = m.batt_board_adc_12v_1();
-                self.battery_pack.modules[1].adc2.rail_3v3 = m.batt_board_adc_3v3_1();
-                self.battery_pack.modules[1].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattBoardAdc22(m) => {
-                self.battery_pack.modules[2].adc2.rail_5v = m.batt_board_adc_5v_2();
-                self.battery_pack.modules[2].adc2.rail_12v = m.batt_board_adc_12v_2();
-                self.battery_pack.modules[2].adc2.rail_3v3 = m.batt_board_adc_3v3_2();
-                self.battery_pack.modules[2].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattBoardAdc23(m) => {
-                self.battery_pack.modules[3].adc2.rail_5v = m.batt_board_adc_5v_3();
-                self.battery_pack.modules[3].adc2.rail_12v = m.batt_board_adc_12v_3();
-                self.battery_pack.modules[3].adc2.rail_3v3 = m.batt_board_adc_3v3_3();
-                self.battery_pack.modules[3].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattBoardAdc24(m) => {
-                self.battery_pack.modules[4].adc2.rail_5v = m.batt_board_adc_5v_4();
-                self.battery_pack.modules[4].adc2.rail_12v = m.batt_board_adc_12v_4();
-                self.battery_pack.modules[4].adc2.rail_3v3 = m.batt_board_adc_3v3_4();
-                self.battery_pack.modules[4].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattBoardAdc25(m) => {
-                self.battery_pack.modules[5].adc2.rail_5v = m.batt_board_adc_5v_5();
-                self.battery_pack.modules[5].adc2.rail_12v = m.batt_board_adc_12v_5();
-                self.battery_pack.modules[5].adc2.rail_3v3 = m.batt_board_adc_3v3_5();
-                self.battery_pack.modules[5].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattBoardAdc26(m) => {
-                self.battery_pack.modules[6].adc2.rail_5v = m.batt_board_adc_5v_6();
-                self.battery_pack.modules[6].adc2.rail_12v = m.batt_board_adc_12v_6();
-                self.battery_pack.modules[6].adc2.rail_3v3 = m.batt_board_adc_3v3_6();
-                self.battery_pack.modules[6].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattBoardAdc27(m) => {
-                self.battery_pack.modules[7].adc2.rail_5v = m.batt_board_adc_5v_7();
-                self.battery_pack.modules[7].adc2.rail_12v = m.batt_board_adc_12v_7();
-                self.battery_pack.modules[7].adc2.rail_3v3 = m.batt_board_adc_3v3_7();
-                self.battery_pack.modules[7].last_seen = Some(std::time::Instant::now());
-            }
+                _ = sigint.recv() => {
+                    self.log_event("event kind=stopping signal=SIGINT");
+                    break Ok(());
+                }
 
-            abs_alliance_can_messages::Messages::BattModHvStatus0(m) => {
-                self.battery_pack.modules[0].hv_status.voltage = m.batt_v_mod_0_raw();
-                self.battery_pack.modules[0].hv_status.current = m.batt_i_mod_filtered_0_raw();
-                self.battery_pack.modules[0].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModHvStatus1(m) => {
-                self.battery_pack.modules[1].hv_status.voltage = m.batt_v_mod_1_raw();
-                self.battery_pack.modules[1].hv_status.current = m.batt_i_mod_filtered_1_raw();
-                self.battery_pack.modules[1].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModHvStatus2(m) => {
-                self.battery_pack.modules[2].hv_status.voltage = m.batt_v_mod_2_raw();
-                self.battery_pack.modules[2].hv_status.current = m.batt_i_mod_filtered_2_raw();
-                self.battery_pack.modules[2].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModHvStatus3(m) => {
-                self.battery_pack.modules[3].hv_status.voltage = m.batt_v_mod_3_raw();
-                self.battery_pack.modules[3].hv_status.current = m.batt_i_mod_filtered_3_raw();
-                self.battery_pack.modules[3].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModHvStatus4(m) => {
-                self.battery_pack.modules[4].hv_status.voltage = m.batt_v_mod_4_raw();
-                self.battery_pack.modules[4].hv_status.current = m.batt_i_mod_filtered_4_raw();
-                self.battery_pack.modules[4].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModHvStatus5(m) => {
-                self.battery_pack.modules[5].hv_status.voltage = m.batt_v_mod_5_raw();
-                self.battery_pack.modules[5].hv_status.current = m.batt_i_mod_filtered_5_raw();
-                self.battery_pack.modules[5].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModHvStatus6(m) => {
-                self.battery_pack.modules[6].hv_status.voltage = m.batt_v_mod_6_raw();
-                self.battery_pack.modules[6].hv_status.current = m.batt_i_mod_filtered_6_raw();
-                self.battery_pack.modules[6].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModHvStatus7(m) => {
-                self.battery_pack.modules[7].hv_status.voltage = m.batt_v_mod_7_raw();
-                self.battery_pack.modules[7].hv_status.current = m.batt_i_mod_filtered_7_raw();
-                self.battery_pack.modules[7].last_seen = Some(std::time::Instant::now());
-            }
+                maybe_frame = next_frame(&mut self.source) => {
+                    if let Some((id, data, now)) = maybe_frame {
+                        let _ = self.handle_frame(id, &data, now);
+                        if timeout.is_terminated() {
+                            timeout.set(tokio::time::sleep(tokio::time::Duration::from_secs(1)).fuse());
+                        }
+                    }
+                }
 
-            abs_alliance_can_messages::Messages::BattModChgLimits0(m) => {
-                self.battery_pack.modules[0].charge_limit.voltage = m.batt_v_mod_chg_limit_0_raw();
-                self.battery_pack.modules[0].charge_limit.current =
-                    m.batt_i_mod_chg_limit_cont_0_raw();
-                self.battery_pack.modules[0].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModChgLimits1(m) => {
-                self.battery_pack.modules[1].charge_limit.voltage = m.batt_v_mod_chg_limit_1_raw();
-                self.battery_pack.modules[1].charge_limit.current =
-                    m.batt_i_mod_chg_limit_cont_1_raw();
-                self.battery_pack.modules[1].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModChgLimits2(m) => {
-                self.battery_pack.modules[2].charge_limit.voltage = m.batt_v_mod_chg_limit_2_raw();
-                self.battery_pack.modules[2].charge_limit.current =
-                    m.batt_i_mod_chg_limit_cont_2_raw();
-                self.battery_pack.modules[2].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModChgLimits3(m) => {
-                self.battery_pack.modules[3].charge_limit.voltage = m.batt_v_mod_chg_limit_3_raw();
-                self.battery_pack.modules[3].charge_limit.current =
-                    m.batt_i_mod_chg_limit_cont_3_raw();
-                self.battery_pack.modules[3].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModChgLimits4(m) => {
-                self.battery_pack.modules[4].charge_limit.voltage = m.batt_v_mod_chg_limit_4_raw();
-                self.battery_pack.modules[4].charge_limit.current =
-                    m.batt_i_mod_chg_limit_cont_4_raw();
-                self.battery_pack.modules[4].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModChgLimits5(m) => {
-                self.battery_pack.modules[5].charge_limit.voltage = m.batt_v_mod_chg_limit_5_raw();
-                self.battery_pack.modules[5].charge_limit.current =
-                    m.batt_i_mod_chg_limit_cont_5_raw();
-                self.battery_pack.modules[5].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModChgLimits6(m) => {
-                self.battery_pack.modules[6].charge_limit.voltage = m.batt_v_mod_chg_limit_6_raw();
-                self.battery_pack.modules[6].charge_limit.current =
-                    m.batt_i_mod_chg_limit_cont_6_raw();
-                self.battery_pack.modules[6].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModChgLimits7(m) => {
-                self.battery_pack.modules[7].charge_limit.voltage = m.batt_v_mod_chg_limit_7_raw();
-                self.battery_pack.modules[7].charge_limit.current =
-                    m.batt_i_mod_chg_limit_cont_7_raw();
-                self.battery_pack.modules[7].last_seen = Some(std::time::Instant::now());
-            }
+                Some(request) = control.recv() => {
+                    self.handle_request(request).await?;
 
-            abs_alliance_can_messages::Messages::BattModSoc0(m) => {
-                self.battery_pack.modules[0].soc.soc = m.batt_soc_mod_0_raw();
-                self.battery_pack.modules[0].soc.soh = m.batt_sohc_mod_0_raw();
-                self.battery_pack.modules[0].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModSoc1(m) => {
-                self.battery_pack.modules[1].soc.soc = m.batt_soc_mod_1_raw();
-                self.battery_pack.modules[1].soc.soh = m.batt_sohc_mod_1_raw();
-                self.battery_pack.modules[1].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModSoc2(m) => {
-                self.battery_pack.modules[2].soc.soc = m.batt_soc_mod_2_raw();
-                self.battery_pack.modules[2].soc.soh = m.batt_sohc_mod_2_raw();
-                self.battery_pack.modules[2].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModSoc3(m) => {
-                self.battery_pack.modules[3].soc.soc = m.batt_soc_mod_3_raw();
-                self.battery_pack.modules[3].soc.soh = m.batt_sohc_mod_3_raw();
-                self.battery_pack.modules[3].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModSoc4(m) => {
-                self.battery_pack.modules[4].soc.soc = m.batt_soc_mod_4_raw();
-                self.battery_pack.modules[4].soc.soh = m.batt_sohc_mod_4_raw();
-                self.battery_pack.modules[4].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModSoc5(m) => {
-                self.battery_pack.modules[5].soc.soc = m.batt_soc_mod_5_raw();
-                self.battery_pack.modules[5].soc.soh = m.batt_sohc_mod_5_raw();
-                self.battery_pack.modules[5].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModSoc6(m) => {
-                self.battery_pack.modules[6].soc.soc = m.batt_soc_mod_6_raw();
-                self.battery_pack.modules[6].soc.soh = m.batt_sohc_mod_6_raw();
-                self.battery_pack.modules[6].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModSoc7(m) => {
-                self.battery_pack.modules[7].soc.soc = m.batt_soc_mod_7_raw();
-                self.battery_pack.modules[7].soc.soh = m.batt_sohc_mod_7_raw();
-                self.battery_pack.modules[7].last_seen = Some(std::time::Instant::now());
-            }
+                    // If we had put the pack to Sleep and turned
+                    // timeouts ticks off, restart the timout
+                    // future now.
+                    if timeout.is_terminated() {
+                        timeout.set(tokio::time::sleep(tokio::time::Duration::from_secs(1)).fuse());
+                    }
+                }
 
-            abs_alliance_can_messages::Messages::BattModTemperaturesA0(m) => {
-                self.battery_pack.modules[0].temperatures_a.ambient = m.batt_t_ambient_0_raw();
-                self.battery_pack.modules[0].temperatures_a.module1 = m.batt_t_module1_0_raw();
-                self.battery_pack.modules[0].temperatures_a.module2 = m.batt_t_module2_0_raw();
-                self.battery_pack.modules[0].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModTemperaturesA1(m) => {
-                self.battery_pack.modules[1].temperatures_a.ambient = m.batt_t_ambient_1_raw();
-                self.battery_pack.modules[1].temperatures_a.module1 = m.batt_t_module1_1_raw();
-                self.battery_pack.modules[1].temperatures_a.module2 = m.batt_t_module2_1_raw();
-                self.battery_pack.modules[1].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModTemperaturesA2(m) => {
-                self.battery_pack.modules[2].temperatures_a.ambient = m.batt_t_ambient_2_raw();
-                self.battery_pack.modules[2].temperatures_a.module1 = m.batt_t_module1_2_raw();
-                self.battery_pack.modules[2].temperatures_a.module2 = m.batt_t_module2_2_raw();
-                self.battery_pack.modules[2].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModTemperaturesA3(m) => {
-                self.battery_pack.modules[3].temperatures_a.ambient = m.batt_t_ambient_3_raw();
-                self.battery_pack.modules[3].temperatures_a.module1 = m.batt_t_module1_3_raw();
-                self.battery_pack.modules[3].temperatures_a.module2 = m.batt_t_module2_3_raw();
-                self.battery_pack.modules[3].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModTemperaturesA4(m) => {
-                self.battery_pack.modules[4].temperatures_a.ambient = m.batt_t_ambient_4_raw();
-                self.battery_pack.modules[4].temperatures_a.module1 = m.batt_t_module1_4_raw();
-                self.battery_pack.modules[4].temperatures_a.module2 = m.batt_t_module2_4_raw();
-                self.battery_pack.modules[4].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModTemperaturesA5(m) => {
-                self.battery_pack.modules[5].temperatures_a.ambient = m.batt_t_ambient_5_raw();
-                self.battery_pack.modules[5].temperatures_a.module1 = m.batt_t_module1_5_raw();
-                self.battery_pack.modules[5].temperatures_a.module2 = m.batt_t_module2_5_raw();
-                self.battery_pack.modules[5].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModTemperaturesA6(m) => {
-                self.battery_pack.modules[6].temperatures_a.ambient = m.batt_t_ambient_6_raw();
-                self.battery_pack.modules[6].temperatures_a.module1 = m.batt_t_module1_6_raw();
-                self.battery_pack.modules[6].temperatures_a.module2 = m.batt_t_module2_6_raw();
-                self.battery_pack.modules[6].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModTemperaturesA7(m) => {
-                self.battery_pack.modules[7].temperatures_a.ambient = m.batt_t_ambient_7_raw();
-                self.battery_pack.modules[7].temperatures_a.module1 = m.batt_t_module1_7_raw();
-                self.battery_pack.modules[7].temperatures_a.module2 = m.batt_t_module2_7_raw();
-                self.battery_pack.modules[7].last_seen = Some(std::time::Instant::now());
-            }
+                _ = &mut timeout => {
+                    self.tick().await?;
+                    let now = self.now();
+                    for event in event_tracker.update(&self.battery_pack, &self.alarms, now) {
+                        self.log_event(&event);
+                    }
+                    if let Some(recording_error) = self.recording_error.take() {
+                        println!("event kind=recording_stopped error={recording_error:?}");
+                    }
 
-            abs_alliance_can_messages::Messages::BattModTemperaturesB0(m) => {
-                self.battery_pack.modules[0].temperatures_b.fet = m.batt_t_fet_0_raw();
-                self.battery_pack.modules[0].temperatures_b.shunt = m.batt_t_shunt_0_raw();
-                self.battery_pack.modules[0].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModTemperaturesB1(m) => {
-                self.battery_pack.modules[1].temperatures_b.fet = m.batt_t_fet_1_raw();
-                self.battery_pack.modules[1].temperatures_b.shunt = m.batt_t_shunt_1_raw();
-                self.battery_pack.modules[1].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModTemperaturesB2(m) => {
-                self.battery_pack.modules[2].temperatures_b.fet = m.batt_t_fet_2_raw();
-                self.battery_pack.modules[2].temperatures_b.shunt = m.batt_t_shunt_2_raw();
-                self.battery_pack.modules[2].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModTemperaturesB3(m) => {
-                self.battery_pack.modules[3].temperatures_b.fet = m.batt_t_fet_3_raw();
-                self.battery_pack.modules[3].temperatures_b.shunt = m.batt_t_shunt_3_raw();
-                self.battery_pack.modules[3].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModTemperaturesB4(m) => {
-                self.battery_pack.modules[4].temperatures_b.fet = m.batt_t_fet_4_raw();
-                self.battery_pack.modules[4].temperatures_b.shunt = m.batt_t_shunt_4_raw();
-                self.battery_pack.modules[4].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModTemperaturesB5(m) => {
-                self.battery_pack.modules[5].temperatures_b.fet = m.batt_t_fet_5_raw();
-                self.battery_pack.modules[5].temperatures_b.shunt = m.batt_t_shunt_5_raw();
-                self.battery_pack.modules[5].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModTemperaturesB6(m) => {
-                self.battery_pack.modules[6].temperatures_b.fet = m.batt_t_fet_6_raw();
-                self.battery_pack.modules[6].temperatures_b.shunt = m.batt_t_shunt_6_raw();
-                self.battery_pack.modules[6].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattModTemperaturesB7(m) => {
-                self.battery_pack.modules[7].temperatures_b.fet = m.batt_t_fet_7_raw();
-                self.battery_pack.modules[7].temperatures_b.shunt = m.batt_t_shunt_7_raw();
-                self.battery_pack.modules[7].last_seen = Some(std::time::Instant::now());
+                    // Same as the TUI, tick until we're in Sleep mode
+                    // and everything has timed out.
+                    match host::needs_tick(&self.battery_pack, self.mode) {
+                        true => timeout.set(tokio::time::sleep(tokio::time::Duration::from_secs(1)).fuse()),
+                        false => timeout.set(futures::future::Fuse::terminated()),
+                    }
+                }
             }
+        }
+    }
+}
 
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksA0(m) => {
-                self.battery_pack.modules[0].v_bricks[0] = m.batt_v_brick01_0_raw();
-                self.battery_pack.modules[0].v_bricks[1] = m.batt_v_brick02_0_raw();
-                self.battery_pack.modules[0].v_bricks[2] = m.batt_v_brick03_0_raw();
-                self.battery_pack.modules[0].v_bricks[3] = m.batt_v_brick04_0_raw();
-                self.battery_pack.modules[0].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksA1(m) => {
-                self.battery_pack.modules[1].v_bricks[0] = m.batt_v_brick01_1_raw();
-                self.battery_pack.modules[1].v_bricks[1] = m.batt_v_brick02_1_raw();
-                self.battery_pack.modules[1].v_bricks[2] = m.batt_v_brick03_1_raw();
-                self.battery_pack.modules[1].v_bricks[3] = m.batt_v_brick04_1_raw();
-                self.battery_pack.modules[1].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksA2(m) => {
-                self.battery_pack.modules[2].v_bricks[0] = m.batt_v_brick01_2_raw();
-                self.battery_pack.modules[2].v_bricks[1] = m.batt_v_brick02_2_raw();
-                self.battery_pack.modules[2].v_bricks[2] = m.batt_v_brick03_2_raw();
-                self.battery_pack.modules[2].v_bricks[3] = m.batt_v_brick04_2_raw();
-                self.battery_pack.modules[2].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksA3(m) => {
-                self.battery_pack.modules[3].v_bricks[0] = m.batt_v_brick01_3_raw();
-                self.battery_pack.modules[3].v_bricks[1] = m.batt_v_brick02_3_raw();
-                self.battery_pack.modules[3].v_bricks[2] = m.batt_v_brick03_3_raw();
-                self.battery_pack.modules[3].v_bricks[3] = m.batt_v_brick04_3_raw();
-                self.battery_pack.modules[3].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksA4(m) => {
-                self.battery_pack.modules[4].v_bricks[0] = m.batt_v_brick01_4_raw();
-                self.battery_pack.modules[4].v_bricks[1] = m.batt_v_brick02_4_raw();
-                self.battery_pack.modules[4].v_bricks[2] = m.batt_v_brick03_4_raw();
-                self.battery_pack.modules[4].v_bricks[3] = m.batt_v_brick04_4_raw();
-                self.battery_pack.modules[4].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksA5(m) => {
-                self.battery_pack.modules[5].v_bricks[0] = m.batt_v_brick01_5_raw();
-                self.battery_pack.modules[5].v_bricks[1] = m.batt_v_brick02_5_raw();
-                self.battery_pack.modules[5].v_bricks[2] = m.batt_v_brick03_5_raw();
-                self.battery_pack.modules[5].v_bricks[3] = m.batt_v_brick04_5_raw();
-                self.battery_pack.modules[5].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksA6(m) => {
-                self.battery_pack.modules[6].v_bricks[0] = m.batt_v_brick01_6_raw();
-                self.battery_pack.modules[6].v_bricks[1] = m.batt_v_brick02_6_raw();
-                self.battery_pack.modules[6].v_bricks[2] = m.batt_v_brick03_6_raw();
-                self.battery_pack.modules[6].v_bricks[3] = m.batt_v_brick04_6_raw();
-                self.battery_pack.modules[6].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksA7(m) => {
-                self.battery_pack.modules[7].v_bricks[0] = m.batt_v_brick01_7_raw();
-                self.battery_pack.modules[7].v_bricks[1] = m.batt_v_brick02_7_raw();
-                self.battery_pack.modules[7].v_bricks[2] = m.batt_v_brick03_7_raw();
-                self.battery_pack.modules[7].v_bricks[3] = m.batt_v_brick04_7_raw();
-                self.battery_pack.modules[7].last_seen = Some(std::time::Instant::now());
-            }
+impl<T: transport::Transport> App<T> {
+    fn render_frame(&self, frame: &mut ratatui::Frame) {
+        frame.render_widget(self, frame.area());
+    }
 
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksB0(m) => {
-                self.battery_pack.modules[0].v_bricks[4] = m.batt_v_brick05_0_raw();
-                self.battery_pack.modules[0].v_bricks[5] = m.batt_v_brick06_0_raw();
-                self.battery_pack.modules[0].v_bricks[6] = m.batt_v_brick07_0_raw();
-                self.battery_pack.modules[0].v_bricks[7] = m.batt_v_brick08_0_raw();
-                self.battery_pack.modules[0].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksB1(m) => {
-                self.battery_pack.modules[1].v_bricks[4] = m.batt_v_brick05_1_raw();
-                self.battery_pack.modules[1].v_bricks[5] = m.batt_v_brick06_1_raw();
-                self.battery_pack.modules[1].v_bricks[6] = m.batt_v_brick07_1_raw();
-                self.battery_pack.modules[1].v_bricks[7] = m.batt_v_brick08_1_raw();
-                self.battery_pack.modules[1].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksB2(m) => {
-                self.battery_pack.modules[2].v_bricks[4] = m.batt_v_brick05_2_raw();
-                self.battery_pack.modules[2].v_bricks[5] = m.batt_v_brick06_2_raw();
-                self.battery_pack.modules[2].v_bricks[6] = m.batt_v_brick07_2_raw();
-                self.battery_pack.modules[2].v_bricks[7] = m.batt_v_brick08_2_raw();
-                self.battery_pack.modules[2].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksB3(m) => {
-                self.battery_pack.modules[3].v_bricks[4] = m.batt_v_brick05_3_raw();
-                self.battery_pack.modules[3].v_bricks[5] = m.batt_v_brick06_3_raw();
-                self.battery_pack.modules[3].v_bricks[6] = m.batt_v_brick07_3_raw();
-                self.battery_pack.modules[3].v_bricks[7] = m.batt_v_brick08_3_raw();
-                self.battery_pack.modules[3].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksB4(m) => {
-                self.battery_pack.modules[4].v_bricks[4] = m.batt_v_brick05_4_raw();
-                self.battery_pack.modules[4].v_bricks[5] = m.batt_v_brick06_4_raw();
-                self.battery_pack.modules[4].v_bricks[6] = m.batt_v_brick07_4_raw();
-                self.battery_pack.modules[4].v_bricks[7] = m.batt_v_brick08_4_raw();
-                self.battery_pack.modules[4].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksB5(m) => {
-                self.battery_pack.modules[5].v_bricks[4] = m.batt_v_brick05_5_raw();
-                self.battery_pack.modules[5].v_bricks[5] = m.batt_v_brick06_5_raw();
-                self.battery_pack.modules[5].v_bricks[6] = m.batt_v_brick07_5_raw();
-                self.battery_pack.modules[5].v_bricks[7] = m.batt_v_brick08_5_raw();
-                self.battery_pack.modules[5].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksB6(m) => {
-                self.battery_pack.modules[6].v_bricks[4] = m.batt_v_brick05_6_raw();
-                self.battery_pack.modules[6].v_bricks[5] = m.batt_v_brick06_6_raw();
-                self.battery_pack.modules[6].v_bricks[6] = m.batt_v_brick07_6_raw();
-                self.battery_pack.modules[6].v_bricks[7] = m.batt_v_brick08_6_raw();
-                self.battery_pack.modules[6].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksB7(m) => {
-                self.battery_pack.modules[7].v_bricks[4] = m.batt_v_brick05_7_raw();
-                self.battery_pack.modules[7].v_bricks[5] = m.batt_v_brick06_7_raw();
-                self.battery_pack.modules[7].v_bricks[6] = m.batt_v_brick07_7_raw();
-                self.battery_pack.modules[7].v_bricks[7] = m.batt_v_brick08_7_raw();
-                self.battery_pack.modules[7].last_seen = Some(std::time::Instant::now());
-            }
+    /// Change the mode we command the pack to, and send it right away
+    /// (don't wait for the next tick).  This makes it more responsive
+    /// to user input.
+    async fn set_mode(
+        &mut self,
+        mode: abs_alliance_can_messages::HostBatteryRequestHostStateRequest,
+    ) -> Result<(), eyre::Report> {
+        if mode != self.mode {
+            self.mode = mode;
+            let line = format!("mode mode={:?}", self.mode);
+            self.record(|recorder| recorder.record_telemetry(&line));
+        }
+        if mode == abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Sleep {
+            // The Sleep mode is special.  We need to send the Sleep
+            // command once and then not again, or the subsequent Sleep
+            // commands will wake the pack up briefly to respond to the
+            // Sleep.
+            self.sleep().await?;
+        }
+        // `send_mode_command()` does *not* send a packet if we're in
+        // Sleep mode.
+        self.send_mode_command().await
+    }
 
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksC0(m) => {
-                self.battery_pack.modules[0].v_bricks[8] = m.batt_v_brick09_0_raw();
-                self.battery_pack.modules[0].v_bricks[9] = m.batt_v_brick10_0_raw();
-                self.battery_pack.modules[0].v_bricks[10] = m.batt_v_brick11_0_raw();
-                self.battery_pack.modules[0].v_bricks[11] = m.batt_v_brick12_0_raw();
-                self.battery_pack.modules[0].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksC1(m) => {
-                self.battery_pack.modules[1].v_bricks[8] = m.batt_v_brick09_1_raw();
-                self.battery_pack.modules[1].v_bricks[9] = m.batt_v_brick10_1_raw();
-                self.battery_pack.modules[1].v_bricks[10] = m.batt_v_brick11_1_raw();
-                self.battery_pack.modules[1].v_bricks[11] = m.batt_v_brick12_1_raw();
-                self.battery_pack.modules[1].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksC2(m) => {
-                self.battery_pack.modules[2].v_bricks[8] = m.batt_v_brick09_2_raw();
-                self.battery_pack.modules[2].v_bricks[9] = m.batt_v_brick10_2_raw();
-                self.battery_pack.modules[2].v_bricks[10] = m.batt_v_brick11_2_raw();
-                self.battery_pack.modules[2].v_bricks[11] = m.batt_v_brick12_2_raw();
-                self.battery_pack.modules[2].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksC3(m) => {
-                self.battery_pack.modules[3].v_bricks[8] = m.batt_v_brick09_3_raw();
-                self.battery_pack.modules[3].v_bricks[9] = m.batt_v_brick10_3_raw();
-                self.battery_pack.modules[3].v_bricks[10] = m.batt_v_brick11_3_raw();
-                self.battery_pack.modules[3].v_bricks[11] = m.batt_v_brick12_3_raw();
-                self.battery_pack.modules[3].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksC4(m) => {
-                self.battery_pack.modules[4].v_bricks[8] = m.batt_v_brick09_4_raw();
-                self.battery_pack.modules[4].v_bricks[9] = m.batt_v_brick10_4_raw();
-                self.battery_pack.modules[4].v_bricks[10] = m.batt_v_brick11_4_raw();
-                self.battery_pack.modules[4].v_bricks[11] = m.batt_v_brick12_4_raw();
-                self.battery_pack.modules[4].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksC5(m) => {
-                self.battery_pack.modules[5].v_bricks[8] = m.batt_v_brick09_5_raw();
-                self.battery_pack.modules[5].v_bricks[9] = m.batt_v_brick10_5_raw();
-                self.battery_pack.modules[5].v_bricks[10] = m.batt_v_brick11_5_raw();
-                self.battery_pack.modules[5].v_bricks[11] = m.batt_v_brick12_5_raw();
-                self.battery_pack.modules[5].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksC6(m) => {
-                self.battery_pack.modules[6].v_bricks[8] = m.batt_v_brick09_6_raw();
-                self.battery_pack.modules[6].v_bricks[9] = m.batt_v_brick10_6_raw();
-                self.battery_pack.modules[6].v_bricks[10] = m.batt_v_brick11_6_raw();
-                self.battery_pack.modules[6].v_bricks[11] = m.batt_v_brick12_6_raw();
-                self.battery_pack.modules[6].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksC7(m) => {
-                self.battery_pack.modules[7].v_bricks[8] = m.batt_v_brick09_7_raw();
-                self.battery_pack.modules[7].v_bricks[9] = m.batt_v_brick10_7_raw();
-                self.battery_pack.modules[7].v_bricks[10] = m.batt_v_brick11_7_raw();
-                self.battery_pack.modules[7].v_bricks[11] = m.batt_v_brick12_7_raw();
-                self.battery_pack.modules[7].last_seen = Some(std::time::Instant::now());
-            }
+    /// The 1 Hz housekeeping: send the mode command keep-alive, record
+    /// telemetry, time out battery modules and the pack charge request,
+    /// and check the alarms.  Returns true if anything timed out or the
+    /// alarms changed.
+    async fn tick(&mut self) -> Result<bool, eyre::Report> {
+        self.send_mode_command().await?;
+
+        let lines = self.telemetry_lines(self.now());
+        self.record(|recorder| {
+            for line in &lines {
+                recorder.record_telemetry(line)?;
+            }
+            recorder.flush()
+        });
+
+        let now = self.now();
+        for (module_history, battery_module) in self
+            .module_history
+            .iter_mut()
+            .zip(self.battery_pack.modules.iter())
+        {
+            module_history.sample(battery_module, now);
+        }
+        self.pack_history.sample(&self.battery_pack, now);
+
+        let expired = self.battery_pack.expire(now);
+        let alarms = self
+            .thresholds
+            .alarms(&self.battery_pack, &self.alarms, now);
+        let alarms_changed = alarms != self.alarms;
+        self.alarms = alarms;
+        Ok(expired || alarms_changed)
+    }
 
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksD0(m) => {
-                self.battery_pack.modules[0].v_bricks[12] = m.batt_v_brick13_0_raw();
-                self.battery_pack.modules[0].v_bricks[13] = m.batt_v_brick14_0_raw();
-                self.battery_pack.modules[0].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksD1(m) => {
-                self.battery_pack.modules[1].v_bricks[12] = m.batt_v_brick13_1_raw();
-                self.battery_pack.modules[1].v_bricks[13] = m.batt_v_brick14_1_raw();
-                self.battery_pack.modules[1].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksD2(m) => {
-                self.battery_pack.modules[2].v_bricks[12] = m.batt_v_brick13_2_raw();
-                self.battery_pack.modules[2].v_bricks[13] = m.batt_v_brick14_2_raw();
-                self.battery_pack.modules[2].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksD3(m) => {
-                self.battery_pack.modules[3].v_bricks[12] = m.batt_v_brick13_3_raw();
-                self.battery_pack.modules[3].v_bricks[13] = m.batt_v_brick14_3_raw();
-                self.battery_pack.modules[3].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksD4(m) => {
-                self.battery_pack.modules[4].v_bricks[12] = m.batt_v_brick13_4_raw();
-                self.battery_pack.modules[4].v_bricks[13] = m.batt_v_brick14_4_raw();
-                self.battery_pack.modules[4].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksD5(m) => {
-                self.battery_pack.modules[5].v_bricks[12] = m.batt_v_brick13_5_raw();
-                self.battery_pack.modules[5].v_bricks[13] = m.batt_v_brick14_5_raw();
-                self.battery_pack.modules[5].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksD6(m) => {
-                self.battery_pack.modules[6].v_bricks[12] = m.batt_v_brick13_6_raw();
-                self.battery_pack.modules[6].v_bricks[13] = m.batt_v_brick14_6_raw();
-                self.battery_pack.modules[6].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticVBricksD7(m) => {
-                self.battery_pack.modules[7].v_bricks[12] = m.batt_v_brick13_7_raw();
-                self.battery_pack.modules[7].v_bricks[13] = m.batt_v_brick14_7_raw();
-                self.battery_pack.modules[7].last_seen = Some(std::time::Instant::now());
-            }
+    /// The clock the model runs on: the wall clock for a live CAN
+    /// interface, the log's virtual clock in a replay.
+    fn now(&self) -> std::time::Instant {
+        match &self.source {
+            Source::Can(_) => std::time::Instant::now(),
+            Source::Replay(replay) => replay.clock(std::time::Instant::now()),
+        }
+    }
 
-            abs_alliance_can_messages::Messages::BattDiagnosticBalStatusBrick0(m) => {
-                self.battery_pack.modules[0].balancing[0] = m.batt_bal_status_brick01_0_raw();
-                self.battery_pack.modules[0].balancing[1] = m.batt_bal_status_brick02_0_raw();
-                self.battery_pack.modules[0].balancing[2] = m.batt_bal_status_brick03_0_raw();
-                self.battery_pack.modules[0].balancing[3] = m.batt_bal_status_brick04_0_raw();
-                self.battery_pack.modules[0].balancing[4] = m.batt_bal_status_brick05_0_raw();
-                self.battery_pack.modules[0].balancing[5] = m.batt_bal_status_brick06_0_raw();
-                self.battery_pack.modules[0].balancing[6] = m.batt_bal_status_brick07_0_raw();
-                self.battery_pack.modules[0].balancing[7] = m.batt_bal_status_brick08_0_raw();
-                self.battery_pack.modules[0].balancing[8] = m.batt_bal_status_brick09_0_raw();
-                self.battery_pack.modules[0].balancing[9] = m.batt_bal_status_brick10_0_raw();
-                self.battery_pack.modules[0].balancing[10] = m.batt_bal_status_brick11_0_raw();
-                self.battery_pack.modules[0].balancing[11] = m.batt_bal_status_brick12_0_raw();
-                self.battery_pack.modules[0].balancing[12] = m.batt_bal_status_brick13_0_raw();
-                self.battery_pack.modules[0].balancing[13] = m.batt_bal_status_brick14_0_raw();
-                self.battery_pack.modules[0].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticBalStatusBrick1(m) => {
-                self.battery_pack.modules[1].balancing[0] = m.batt_bal_status_brick01_1_raw();
-                self.battery_pack.modules[1].balancing[1] = m.batt_bal_status_brick02_1_raw();
-                self.battery_pack.modules[1].balancing[2] = m.batt_bal_status_brick03_1_raw();
-                self.battery_pack.modules[1].balancing[3] = m.batt_bal_status_brick04_1_raw();
-                self.battery_pack.modules[1].balancing[4] = m.batt_bal_status_brick05_1_raw();
-                self.battery_pack.modules[1].balancing[5] = m.batt_bal_status_brick06_1_raw();
-                self.battery_pack.modules[1].balancing[6] = m.batt_bal_status_brick07_1_raw();
-                self.battery_pack.modules[1].balancing[7] = m.batt_bal_status_brick08_1_raw();
-                self.battery_pack.modules[1].balancing[8] = m.batt_bal_status_brick09_1_raw();
-                self.battery_pack.modules[1].balancing[9] = m.batt_bal_status_brick10_1_raw();
-                self.battery_pack.modules[1].balancing[10] = m.batt_bal_status_brick11_1_raw();
-                self.battery_pack.modules[1].balancing[11] = m.batt_bal_status_brick12_1_raw();
-                self.battery_pack.modules[1].balancing[12] = m.batt_bal_status_brick13_1_raw();
-                self.battery_pack.modules[1].balancing[13] = m.batt_bal_status_brick14_1_raw();
-                self.battery_pack.modules[1].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticBalStatusBrick2(m) => {
-                self.battery_pack.modules[2].balancing[0] = m.batt_bal_status_brick01_2_raw();
-                self.battery_pack.modules[2].balancing[1] = m.batt_bal_status_brick02_2_raw();
-                self.battery_pack.modules[2].balancing[2] = m.batt_bal_status_brick03_2_raw();
-                self.battery_pack.modules[2].balancing[3] = m.batt_bal_status_brick04_2_raw();
-                self.battery_pack.modules[2].balancing[4] = m.batt_bal_status_brick05_2_raw();
-                self.battery_pack.modules[2].balancing[5] = m.batt_bal_status_brick06_2_raw();
-                self.battery_pack.modules[2].balancing[6] = m.batt_bal_status_brick07_2_raw();
-                self.battery_pack.modules[2].balancing[7] = m.batt_bal_status_brick08_2_raw();
-                self.battery_pack.modules[2].balancing[8] = m.batt_bal_status_brick09_2_raw();
-                self.battery_pack.modules[2].balancing[9] = m.batt_bal_status_brick10_2_raw();
-                self.battery_pack.modules[2].balancing[10] = m.batt_bal_status_brick11_2_raw();
-                self.battery_pack.modules[2].balancing[11] = m.batt_bal_status_brick12_2_raw();
-                self.battery_pack.modules[2].balancing[12] = m.batt_bal_status_brick13_2_raw();
-                self.battery_pack.modules[2].balancing[13] = m.batt_bal_status_brick14_2_raw();
-                self.battery_pack.modules[2].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticBalStatusBrick3(m) => {
-                self.battery_pack.modules[3].balancing[0] = m.batt_bal_status_brick01_3_raw();
-                self.battery_pack.modules[3].balancing[1] = m.batt_bal_status_brick02_3_raw();
-                self.battery_pack.modules[3].balancing[2] = m.batt_bal_status_brick03_3_raw();
-                self.battery_pack.modules[3].balancing[3] = m.batt_bal_status_brick04_3_raw();
-                self.battery_pack.modules[3].balancing[4] = m.batt_bal_status_brick05_3_raw();
-                self.battery_pack.modules[3].balancing[5] = m.batt_bal_status_brick06_3_raw();
-                self.battery_pack.modules[3].balancing[6] = m.batt_bal_status_brick07_3_raw();
-                self.battery_pack.modules[3].balancing[7] = m.batt_bal_status_brick08_3_raw();
-                self.battery_pack.modules[3].balancing[8] = m.batt_bal_status_brick09_3_raw();
-                self.battery_pack.modules[3].balancing[9] = m.batt_bal_status_brick10_3_raw();
-                self.battery_pack.modules[3].balancing[10] = m.batt_bal_status_brick11_3_raw();
-                self.battery_pack.modules[3].balancing[11] = m.batt_bal_status_brick12_3_raw();
-                self.battery_pack.modules[3].balancing[12] = m.batt_bal_status_brick13_3_raw();
-                self.battery_pack.modules[3].balancing[13] = m.batt_bal_status_brick14_3_raw();
-                self.battery_pack.modules[3].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticBalStatusBrick4(m) => {
-                self.battery_pack.modules[4].balancing[0] = m.batt_bal_status_brick01_4_raw();
-                self.battery_pack.modules[4].balancing[1] = m.batt_bal_status_brick02_4_raw();
-                self.battery_pack.modules[4].balancing[2] = m.batt_bal_status_brick03_4_raw();
-                self.battery_pack.modules[4].balancing[3] = m.batt_bal_status_brick04_4_raw();
-                self.battery_pack.modules[4].balancing[4] = m.batt_bal_status_brick05_4_raw();
-                self.battery_pack.modules[4].balancing[5] = m.batt_bal_status_brick06_4_raw();
-                self.battery_pack.modules[4].balancing[6] = m.batt_bal_status_brick07_4_raw();
-                self.battery_pack.modules[4].balancing[7] = m.batt_bal_status_brick08_4_raw();
-                self.battery_pack.modules[4].balancing[8] = m.batt_bal_status_brick09_4_raw();
-                self.battery_pack.modules[4].balancing[9] = m.batt_bal_status_brick10_4_raw();
-                self.battery_pack.modules[4].balancing[10] = m.batt_bal_status_brick11_4_raw();
-                self.battery_pack.modules[4].balancing[11] = m.batt_bal_status_brick12_4_raw();
-                self.battery_pack.modules[4].balancing[12] = m.batt_bal_status_brick13_4_raw();
-                self.battery_pack.modules[4].balancing[13] = m.batt_bal_status_brick14_4_raw();
-                self.battery_pack.modules[4].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticBalStatusBrick5(m) => {
-                self.battery_pack.modules[5].balancing[0] = m.batt_bal_status_brick01_5_raw();
-                self.battery_pack.modules[5].balancing[1] = m.batt_bal_status_brick02_5_raw();
-                self.battery_pack.modules[5].balancing[2] = m.batt_bal_status_brick03_5_raw();
-                self.battery_pack.modules[5].balancing[3] = m.batt_bal_status_brick04_5_raw();
-                self.battery_pack.modules[5].balancing[4] = m.batt_bal_status_brick05_5_raw();
-                self.battery_pack.modules[5].balancing[5] = m.batt_bal_status_brick06_5_raw();
-                self.battery_pack.modules[5].balancing[6] = m.batt_bal_status_brick07_5_raw();
-                self.battery_pack.modules[5].balancing[7] = m.batt_bal_status_brick08_5_raw();
-                self.battery_pack.modules[5].balancing[8] = m.batt_bal_status_brick09_5_raw();
-                self.battery_pack.modules[5].balancing[9] = m.batt_bal_status_brick10_5_raw();
-                self.battery_pack.modules[5].balancing[10] = m.batt_bal_status_brick11_5_raw();
-                self.battery_pack.modules[5].balancing[11] = m.batt_bal_status_brick12_5_raw();
-                self.battery_pack.modules[5].balancing[12] = m.batt_bal_status_brick13_5_raw();
-                self.battery_pack.modules[5].balancing[13] = m.batt_bal_status_brick14_5_raw();
-                self.battery_pack.modules[5].last_seen = Some(std::time::Instant::now());
-            }
-            abs_alliance_can_messages::Messages::BattDiagnosticBalStatusBrick6(m) => {
-                self.battery_pack.modules[6].balancing[0] = m.batt_bal_status_brick01_6_raw();
-                self.battery_pack.modules[6].balancing[1] = m.batt_bal_status_brick02_6_raw();
-                self.battery_pack.modules[6].balancing[2] = m.batt_bal_status_brick03_6_raw();
-                self.battery_pack.modules[6].balancing[3] = m.batt_bal_status_brick04_6_raw();
-                self.battery_pack.modules[6].balancing[4] = m.batt_bal_status_brick05_6_raw();
-                self.battery_pack.modules[6].balancing[5] = m.batt_bal_status_brick06_6_raw();
-                self.battery_pack.modules[6].balancing[6] = m.batt_bal_status_brick07_6_raw();
-                self.battery_pack.modules[6].balancing[7] = m.batt_bal_status_brick08_6_raw();
-                self.battery_pack.modules[6].balancing[8] = m.batt_bal_status_brick09_6_raw();
-                self.battery_pack.modules[6].balancing[9] = m.batt_bal_status_brick10_6_raw();
-                self.battery_pack.modules[6].balancing[10] = m.batt_bal_status_brick11_6_raw();
-                self.battery_pack.modules[6].balancing[11] = m.batt_bal_status_brick12_6_raw();
-                self.battery_pack.modules[6].balancing[12] = m.batt_bal_status_brick13_6_raw();
-                self.battery_pack.modules[6].balancing[13] = m.batt_bal_status_brick14_6_raw();
-                self.battery_pack.modules[6].last_seen = Some(std::time::Instant::now());
+    /// Answer a request from the control socket or the HTTP API.
+    async fn handle_request(&mut self, request: control::Request) -> Result<(), eyre::Report> {
+        let now = self.now();
+        let reply = match request.command {
+            control::Command::SetMode(mode) => {
+                self.log_event(&format!("event kind=mode_command mode={mode:?}"));
+                self.set_mode(mode).await?;
+                Ok(String::new())
+            }
+            control::Command::Status => Ok(self.telemetry_lines(now).join("\n")),
+            control::Command::Pack => {
+                Ok(api::pack_json(&self.battery_pack, self.mode, now).to_string())
+            }
+            control::Command::Module(module_index) => {
+                match self.battery_pack.modules.get(module_index) {
+                    Some(battery_module) => {
+                        Ok(api::module_json(module_index, battery_module, now).to_string())
+                    }
+                    None => Err(format!("no module {module_index}")),
+                }
             }
-            abs_alliance_can_messages::Messages::BattDiagnosticBalStatusBrick7(m) => {
-                self.battery_pack.modules[7].balancing[0] = m.batt_bal_status_brick01_7_raw();
-                self.battery_pack.modules[7].balancing[1] = m.batt_bal_status_brick02_7_raw();
-                self.battery_pack.modules[7].balancing[2] = m.batt_bal_status_brick03_7_raw();
-                self.battery_pack.modules[7].balancing[3] = m.batt_bal_status_brick04_7_raw();
-                self.battery_pack.modules[7].balancing[4] = m.batt_bal_status_brick05_7_raw();
-                self.battery_pack.modules[7].balancing[5] = m.batt_bal_status_brick06_7_raw();
-                self.battery_pack.modules[7].balancing[6] = m.batt_bal_status_brick07_7_raw();
-                self.battery_pack.modules[7].balancing[7] = m.batt_bal_status_brick08_7_raw();
-                self.battery_pack.modules[7].balancing[8] = m.batt_bal_status_brick09_7_raw();
-                self.battery_pack.modules[7].balancing[9] = m.batt_bal_status_brick10_7_raw();
-                self.battery_pack.modules[7].balancing[10] = m.batt_bal_status_brick11_7_raw();
-                self.battery_pack.modules[7].balancing[11] = m.batt_bal_status_brick12_7_raw();
-                self.battery_pack.modules[7].balancing[12] = m.batt_bal_status_brick13_7_raw();
-                self.battery_pack.modules[7].balancing[13] = m.batt_bal_status_brick14_7_raw();
-                self.battery_pack.modules[7].last_seen = Some(std::time::Instant::now());
+            control::Command::ChargeRequest => {
+                Ok(api::charge_request_json(&self.battery_pack, now).to_string())
             }
+            control::Command::Metrics => Ok(api::pack_metrics(&self.battery_pack, self.mode, now)),
+        };
+        // The requester may have given up, that's fine.
+        let _ = request.reply.send(reply);
+        Ok(())
+    }
 
-            abs_alliance_can_messages::Messages::BattChargerControl(m) => {
-                self.battery_pack.charge_request = Some(ChargeRequest {
-                    voltage: m.batt_charging_voltage(),
-                    current: m.batt_charging_current(),
-                    enable: m.batt_charge_enable(),
-                    last_seen: std::time::Instant::now(),
-                });
-            }
+    /// Log an event line to stdout when headless (where systemd picks
+    /// it up), and record it.
+    fn log_event(&mut self, event: &str) {
+        if self.headless {
+            println!("{event}");
+        }
+        self.record(|recorder| recorder.record_telemetry(event));
+    }
 
-            abs_alliance_can_messages::Messages::BattPackDiagnosticConnect(m) => {
-                self.battery_pack
-                    .pack_diagnostic_connect
-                    .num_modules_connected_for_charge = m.batt_pack_mod_chg_entry_conn_ok_m();
-                self.battery_pack
-                    .pack_diagnostic_connect
-                    .num_modules_connected_for_drive = m.batt_pack_mod_drv_entry_conn_ok_m();
-                self.battery_pack
-                    .pack_diagnostic_connect
-                    .num_modules_standby_for_charge = m.batt_pack_mod_chg_standby_conn_ok_m();
-                self.battery_pack
-                    .pack_diagnostic_connect
-                    .num_modules_standby_for_drive = m.batt_pack_mod_drv_standby_conn_ok_m();
+    /// Run `f` on the recorder, if we're recording.  If it fails we stop
+    /// recording and say so on the screen, rather than taking down the
+    /// monitor (and the pack with it).
+    fn record(&mut self, f: impl FnOnce(&mut recorder::Recorder) -> Result<(), eyre::Report>) {
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = f(recorder) {
+                self.recording_error = Some(format!("recording stopped: {e}"));
+                self.recorder = None;
             }
+        }
+    }
 
-            abs_alliance_can_messages::Messages::BattPackHvStatus(m) => {
-                self.battery_pack.pack_hv_status.voltage = m.batt_v_pack_raw();
-                self.battery_pack.pack_hv_status.current = m.batt_i_pack_filtered_raw();
-            }
+    /// The current pack and module telemetry, one line per record, for
+    /// the recorder.  Stale values are left out.
+    fn telemetry_lines(&self, now: std::time::Instant) -> Vec<String> {
+        let battery_pack = &self.battery_pack;
+        let mut lines = vec![];
+
+        let mut pack = vec![format!("pack mode={:?}", self.mode)];
+        if let Some(pack_status) = battery_pack.pack_status.fresh(now) {
+            pack.push(format!("state={:?}", pack_status.state_name()));
+            pack.push(format!(
+                "modules_on_hv_bus={}",
+                pack_status.num_modules_on_hv_bus
+            ));
+        }
+        if let Some(hv_status) = battery_pack.pack_hv_status.fresh(now) {
+            pack.push(format!("voltage={:.3}", hv_status.voltage));
+            pack.push(format!("current={:.3}", hv_status.current));
+        }
+        if let Some(pack_soc) = battery_pack.pack_soc.fresh(now) {
+            pack.push(format!("soc={:.1}", pack_soc.soc));
+            pack.push(format!("user_soc={:.1}", pack_soc.user_soc));
+        }
+        lines.push(pack.join(" "));
+
+        if let Some(charge_request) = battery_pack.charge_request.fresh(now) {
+            lines.push(format!(
+                "charge_request voltage={:.3} current={:.3} enable={}",
+                charge_request.voltage, charge_request.current, charge_request.enable
+            ));
+        }
+
+        for (module_index, battery_module) in battery_pack.modules.iter().enumerate() {
+            if !battery_module.is_present() {
+                continue;
+            }
+            let mut module = vec![format!("module index={module_index}")];
+            if let Some(serial_number) = battery_module.serial_number.get() {
+                module.push(format!("serial={serial_number}"));
+            }
+            if let Some(hv_status) = battery_module.hv_status.fresh(now) {
+                module.push(format!("voltage={:.3}", hv_status.voltage));
+                module.push(format!("current={:.3}", hv_status.current));
+            }
+            if let Some(soc) = battery_module.soc.fresh(now) {
+                module.push(format!("soc={:.1}", soc.soc));
+                module.push(format!("soh={:.1}", soc.soh));
+            }
+            let v_bricks: Vec<String> = battery_module
+                .v_bricks
+                .iter()
+                .map(|v_brick| match v_brick.fresh(now) {
+                    Some(v_brick) => format!("{v_brick:.3}"),
+                    None => String::from("-"),
+                })
+                .collect();
+            module.push(format!("v_bricks={}", v_bricks.join(",")));
+            if let Some(balancing) = battery_module.balancing.fresh(now) {
+                let balancing: String = balancing
+                    .iter()
+                    .map(|b| if *b { '1' } else { '0' })
+                    .collect();
+                module.push(format!("balancing={balancing}"));
+            }
+            if let Some(temperatures_a) = battery_module.temperatures_a.fresh(now) {
+                module.push(format!("t_ambient={:.1}", temperatures_a.ambient));
+                module.push(format!("t_module1={:.1}", temperatures_a.module1));
+                module.push(format!("t_module2={:.1}", temperatures_a.module2));
+            }
+            if let Some(temperatures_b) = battery_module.temperatures_b.fresh(now) {
+                module.push(format!("t_fet={:.1}", temperatures_b.fet));
+                module.push(format!("t_shunt={:.1}", temperatures_b.shunt));
+            }
+            let faults: Vec<&str> = battery_module
+                .faults
+                .active()
+                .map(|(fault_info, _)| fault_info.name)
+                .collect();
+            if !faults.is_empty() {
+                module.push(format!("faults={}", faults.join(",")));
+            }
+            lines.push(module.join(" "));
+        }
 
-            abs_alliance_can_messages::Messages::BattPackSoc(m) => {
-                self.battery_pack.pack_soc.soc = m.batt_pack_soc_raw();
-                self.battery_pack.pack_soc.user_soc = m.batt_pack_user_soc_raw();
+        lines
+    }
+
+    fn handle_frame(
+        &mut self,
+        id: embedded_can::Id,
+        data: &[u8],
+        now: std::time::Instant,
+    ) -> Result<(), eyre::Report> {
+        self.record(|recorder| recorder.record_frame(id, data));
+        if let Source::Replay(_) = self.source {
+            if let Some(mode) = replayed_mode(id, data) {
+                self.mode = mode;
             }
+        }
+        self.battery_pack.handle_frame(id, data, now)
+    }
 
-            _ => (), // ignore all other messages
+    fn handle_replay_key(&mut self, key: crossterm::event::KeyCode) {
+        let Source::Replay(replay) = &mut self.source else {
+            return;
+        };
+        let wall_now = std::time::Instant::now();
+        let position = replay.position(wall_now);
+
+        let target = match key {
+            crossterm::event::KeyCode::Char(' ') => {
+                replay.toggle_pause(wall_now);
+                return;
+            }
+            crossterm::event::KeyCode::Char('+') => {
+                replay.set_speed((replay.speed() * 2.0).min(1024.0), wall_now);
+                return;
+            }
+            crossterm::event::KeyCode::Char('-') => {
+                replay.set_speed((replay.speed() / 2.0).max(1.0 / 1024.0), wall_now);
+                return;
+            }
+            crossterm::event::KeyCode::Right => position + REPLAY_SEEK_STEP,
+            crossterm::event::KeyCode::Left => position.saturating_sub(REPLAY_SEEK_STEP),
+            crossterm::event::KeyCode::Home => std::time::Duration::ZERO,
+            _ => return,
+        };
+
+        // Rebuild the model from the start of the log up to the new
+        // position, so it looks just like it would have if we'd played
+        // up to here.
+        replay.seek(target, wall_now);
+        self.mode = abs_alliance_can_messages::HostBatteryRequestHostStateRequest::None;
+        self.battery_pack = model::BatteryPack::default();
+        for module_history in &mut self.module_history {
+            module_history.clear();
+        }
+        self.pack_history.clear();
+        for (now, frame) in replay.played() {
+            if let Some(mode) = replayed_mode(frame.id, &frame.data) {
+                self.mode = mode;
+            }
+            let _ = self.battery_pack.handle_frame(frame.id, &frame.data, now);
         }
+        self.battery_pack.expire(replay.clock(wall_now));
+    }
 
-        Ok(())
+    /// Move the module selection and switch views.  Returns true if
+    /// the key was one of ours.
+    fn handle_view_key(&mut self, key: crossterm::event::KeyCode) -> bool {
+        match key {
+            crossterm::event::KeyCode::Up => {
+                self.selected_module = self.selected_module.saturating_sub(1);
+            }
+            crossterm::event::KeyCode::Down => {
+                self.selected_module = (self.selected_module + 1).min(model::NUM_MODULES - 1);
+            }
+            crossterm::event::KeyCode::Enter => {
+                self.view = match self.view {
+                    View::Overview | View::Charts | View::Heatmap => View::ModuleDetail,
+                    View::ModuleDetail => View::Overview,
+                };
+            }
+            crossterm::event::KeyCode::Char('g') => {
+                self.view = match self.view {
+                    View::Charts => View::Overview,
+                    View::Overview | View::ModuleDetail | View::Heatmap => View::Charts,
+                };
+            }
+            crossterm::event::KeyCode::Char('h') => {
+                self.view = match self.view {
+                    View::Heatmap => View::Overview,
+                    View::Overview | View::ModuleDetail | View::Charts => View::Heatmap,
+                };
+            }
+            crossterm::event::KeyCode::Char('w') if self.view == View::Charts => {
+                self.chart_window = self.chart_window.next();
+            }
+            crossterm::event::KeyCode::Esc => self.view = View::Overview,
+            _ => return false,
+        }
+        true
     }
 
     async fn send_mode_command(&mut self) -> Result<(), eyre::Report> {
-        if self.battery_pack.mode
-            == abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Sleep
-        {
-            // Don't send any CAN packets while the battery is in sleep
-            // mode, we'd wake it up.
+        let Source::Can(transport) = &mut self.source else {
+            // Never transmit while replaying a log.
             return Ok(());
-        }
-        self.send_mode_command_raw(self.battery_pack.mode).await
+        };
+        // A failed send (like a full queue with nobody else on the bus)
+        // isn't fatal, the keep-alive tries again next tick.
+        let _ = host::send_mode_command(transport, self.mode).await;
+        Ok(())
     }
 
     async fn send_mode_command_raw(
         &mut self,
         mode: abs_alliance_can_messages::HostBatteryRequestHostStateRequest,
     ) -> Result<(), eyre::Report> {
-        let frame = abs_alliance_can_messages::HostBatteryRequest::new(
-            false,
-            false,
-            false,
-            false,
-            false,
-            mode.into(),
-        )?;
-
-        let id: u32 = match frame.id() {
-            embedded_can::Id::Standard(standard_id) => standard_id.as_raw() as u32,
-            embedded_can::Id::Extended(extended_id) => extended_id.as_raw(),
+        let Source::Can(transport) = &mut self.source else {
+            return Ok(());
         };
-        let raw_frame = tokio_socketcan::CANFrame::new(id, frame.raw(), false, false)?;
+        let _ = host::send_mode_command_raw(transport, mode).await;
+        Ok(())
+    }
+}
 
-        match self.can_socket_tx.write_frame(raw_frame) {
-            Ok(can_write_fut) => match can_write_fut.await {
-                Ok(_) => return Ok(()),
-                Err(_e) => return Ok(()),
-            },
-            Err(e) => {
-                return Err(e.into());
-            }
+/// Wait for the next frame from `source`, returning its id, payload and
+/// receive time on the model's clock.
+async fn next_frame<T: transport::Transport>(
+    source: &mut Source<T>,
+) -> Option<(embedded_can::Id, Vec<u8>, std::time::Instant)> {
+    match source {
+        Source::Can(transport) => match transport.recv().await {
+            Some(Ok((id, data))) => Some((id, data, std::time::Instant::now())),
+            Some(Err(_)) => None,
+            // Nothing more is coming (the end of a candump log, say).
+            None => futures::future::pending().await,
+        },
+        Source::Replay(replay) => {
+            let (now, frame) = replay.next_frame().await;
+            Some((frame.id, frame.data, now))
         }
     }
 }
 
-impl ratatui::widgets::Widget for &BatteryPack {
-    fn render(self, area: ratatui::layout::Rect, buf: &mut ratatui::buffer::Buffer) {
-        let mut text = vec![];
+/// In a replay the mode is whatever the host in the capture asked for.
+fn replayed_mode(
+    id: embedded_can::Id,
+    data: &[u8],
+) -> Option<abs_alliance_can_messages::HostBatteryRequestHostStateRequest> {
+    match abs_alliance_can_messages::Messages::from_can_message(id, data) {
+        Ok(abs_alliance_can_messages::Messages::HostBatteryRequest(m)) => {
+            Some(m.host_state_request())
+        }
+        _ => None,
+    }
+}
 
-        let (sleep_style, charge_style, drive_style, none_style) = match self.mode {
-            abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Sleep => (
-                ratatui::style::Style::default().bg(ratatui::style::Color::Green),
-                ratatui::style::Style::default(),
-                ratatui::style::Style::default(),
-                ratatui::style::Style::default(),
-            ),
-            abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Charge => (
-                ratatui::style::Style::default(),
-                ratatui::style::Style::default().bg(ratatui::style::Color::Green),
-                ratatui::style::Style::default(),
-                ratatui::style::Style::default(),
+fn severity_style(severity: model::FaultSeverity) -> ratatui::style::Style {
+    match severity {
+        model::FaultSeverity::Severe => ratatui::style::Style::default()
+            .fg(ratatui::style::Color::White)
+            .bg(ratatui::style::Color::Red),
+        model::FaultSeverity::Moderate => {
+            ratatui::style::Style::default().fg(ratatui::style::Color::Red)
+        }
+        model::FaultSeverity::Warning => {
+            ratatui::style::Style::default().fg(ratatui::style::Color::Magenta)
+        }
+        model::FaultSeverity::Other => {
+            ratatui::style::Style::default().fg(ratatui::style::Color::Black)
+        }
+    }
+}
+
+/// `style` for a value at `level`: unchanged when it's normal, on
+/// yellow for a warning and on red when it's critical.
+fn level_style(style: ratatui::style::Style, level: thresholds::Level) -> ratatui::style::Style {
+    match level {
+        thresholds::Level::Normal => style,
+        thresholds::Level::Warning => style.bg(ratatui::style::Color::Yellow),
+        thresholds::Level::Critical => style.bg(ratatui::style::Color::Red),
+    }
+}
+
+/// The worst level of `module`'s alarms on any of `signals`.
+fn worst_level(
+    alarms: &[thresholds::Alarm],
+    module: usize,
+    signals: &[thresholds::Signal],
+) -> thresholds::Level {
+    alarms
+        .iter()
+        .filter(|alarm| alarm.module == Some(module) && signals.contains(&alarm.signal))
+        .map(|alarm| alarm.level)
+        .max()
+        .unwrap_or(thresholds::Level::Normal)
+}
+
+/// The level of the alarm on one value, `Normal` if there isn't one.
+fn alarm_level(
+    alarms: &[thresholds::Alarm],
+    signal: thresholds::Signal,
+    module: Option<usize>,
+    brick: Option<usize>,
+) -> thresholds::Level {
+    alarms
+        .iter()
+        .find(|alarm| alarm.key() == (signal, module, brick))
+        .map_or(thresholds::Level::Normal, |alarm| alarm.level)
+}
+
+fn format_age(age: std::time::Duration) -> String {
+    let secs = age.as_secs();
+    if secs >= 3600 {
+        format!("{}h{:02}m", secs / 3600, (secs % 3600) / 60)
+    } else if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+fn render_pack(
+    battery_pack: &model::BatteryPack,
+    mode: abs_alliance_can_messages::HostBatteryRequestHostStateRequest,
+    alarms: &[thresholds::Alarm],
+    now: std::time::Instant,
+    area: ratatui::layout::Rect,
+    buf: &mut ratatui::buffer::Buffer,
+) {
+    let mut text = vec![];
+
+    let (sleep_style, charge_style, drive_style, none_style) = match mode {
+        abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Sleep => (
+            ratatui::style::Style::default().bg(ratatui::style::Color::Green),
+            ratatui::style::Style::default(),
+            ratatui::style::Style::default(),
+            ratatui::style::Style::default(),
+        ),
+        abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Charge => (
+            ratatui::style::Style::default(),
+            ratatui::style::Style::default().bg(ratatui::style::Color::Green),
+            ratatui::style::Style::default(),
+            ratatui::style::Style::default(),
+        ),
+        abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Drive => (
+            ratatui::style::Style::default(),
+            ratatui::style::Style::default(),
+            ratatui::style::Style::default().bg(ratatui::style::Color::Green),
+            ratatui::style::Style::default(),
+        ),
+        abs_alliance_can_messages::HostBatteryRequestHostStateRequest::None => (
+            ratatui::style::Style::default(),
+            ratatui::style::Style::default(),
+            ratatui::style::Style::default(),
+            ratatui::style::Style::default().bg(ratatui::style::Color::Green),
+        ),
+        _ => (
+            ratatui::style::Style::default(),
+            ratatui::style::Style::default(),
+            ratatui::style::Style::default(),
+            ratatui::style::Style::default(),
+        ),
+    };
+
+    text.push(ratatui::text::Line::from(vec![
+        ratatui::text::Span::styled("Mode: ", ratatui::style::Style::default()),
+        ratatui::text::Span::styled("Sleep", sleep_style),
+        ratatui::text::Span::styled(" ", ratatui::style::Style::default()),
+        ratatui::text::Span::styled("Charge", charge_style),
+        ratatui::text::Span::styled(" ", ratatui::style::Style::default()),
+        ratatui::text::Span::styled("Drive", drive_style),
+        ratatui::text::Span::styled(" ", ratatui::style::Style::default()),
+        ratatui::text::Span::styled("None", none_style),
+    ]));
+
+    let pack_status = battery_pack.pack_status.get();
+    match pack_status {
+        None => {
+            text.push(ratatui::text::Line::from(vec![
+                ratatui::text::Span::styled(
+                    "Pack state: unknown",
+                    ratatui::style::Style::default().fg(ratatui::style::Color::DarkGray),
+                ),
+            ]));
+        }
+        Some(pack_status) => {
+            text.push(ratatui::text::Line::from(vec![
+                    ratatui::text::Span::styled(
+                        format!(
+                            "Pack state: {}, {} on HV bus, {} configured, {} on network, comms OK {:012b}",
+                            pack_status.state_name(),
+                            pack_status.num_modules_on_hv_bus,
+                            pack_status.num_modules_configured,
+                            pack_status.num_modules_on_network,
+                            pack_status.module_com_status_ok,
+                        ),
+                        ratatui::style::Style::default().fg(ratatui::style::Color::Black),
+                    ),
+                ]));
+
+            // Welded FETs mean the pack can no longer disconnect
+            // itself from the HV bus.  Make this impossible to miss.
+            let alarm_style = ratatui::style::Style::default()
+                .fg(ratatui::style::Color::White)
+                .bg(ratatui::style::Color::Red)
+                .add_modifier(ratatui::style::Modifier::BOLD)
+                .add_modifier(ratatui::style::Modifier::SLOW_BLINK);
+            if pack_status.dsg_fets_welded {
+                text.push(ratatui::text::Line::from(vec![
+                    ratatui::text::Span::styled("!!! PACK DISCHARGE FETS WELDED !!!", alarm_style),
+                ]));
+            }
+            if pack_status.chg_fets_welded {
+                text.push(ratatui::text::Line::from(vec![
+                    ratatui::text::Span::styled("!!! PACK CHARGE FETS WELDED !!!", alarm_style),
+                ]));
+            }
+        }
+    }
+
+    let pack_diagnostic_connect = battery_pack.pack_diagnostic_connect.get_or_default();
+    text.push(ratatui::text::Line::from(vec![
+        ratatui::text::Span::styled(
+            format!(
+                "Charge modules: {:012b} connected, {:012b} standby",
+                pack_diagnostic_connect.num_modules_connected_for_charge,
+                pack_diagnostic_connect.num_modules_standby_for_charge
             ),
-            abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Drive => (
-                ratatui::style::Style::default(),
-                ratatui::style::Style::default(),
-                ratatui::style::Style::default().bg(ratatui::style::Color::Green),
-                ratatui::style::Style::default(),
+            ratatui::style::Style::default().fg(ratatui::style::Color::Black),
+        ),
+    ]));
+
+    text.push(ratatui::text::Line::from(vec![
+        ratatui::text::Span::styled(
+            format!(
+                "Drive modules:  {:012b} connected, {:012b} standby",
+                pack_diagnostic_connect.num_modules_connected_for_drive,
+                pack_diagnostic_connect.num_modules_standby_for_drive
             ),
-            abs_alliance_can_messages::HostBatteryRequestHostStateRequest::None => (
-                ratatui::style::Style::default(),
-                ratatui::style::Style::default(),
-                ratatui::style::Style::default(),
-                ratatui::style::Style::default().bg(ratatui::style::Color::Green),
+            ratatui::style::Style::default().fg(ratatui::style::Color::Black),
+        ),
+    ]));
+
+    let soc_level = alarm_level(alarms, thresholds::Signal::Soc, None, None);
+    text.push(ratatui::text::Line::from(vec![
+        ratatui::text::Span::styled(
+            format!("SOC: {:.1}%", battery_pack.pack_soc.get_or_default().soc),
+            level_style(
+                ratatui::style::Style::default().fg(ratatui::style::Color::Black),
+                soc_level,
             ),
-            _ => (
-                ratatui::style::Style::default(),
-                ratatui::style::Style::default(),
-                ratatui::style::Style::default(),
-                ratatui::style::Style::default(),
+        ),
+    ]));
+
+    let pack_hv_status = battery_pack.pack_hv_status.get_or_default();
+    let current_level = alarm_level(alarms, thresholds::Signal::PackCurrent, None, None);
+    text.push(ratatui::text::Line::from(vec![
+        ratatui::text::Span::styled(
+            format!("{:.3}V ", pack_hv_status.voltage),
+            ratatui::style::Style::default().fg(ratatui::style::Color::Black),
+        ),
+        ratatui::text::Span::styled(
+            format!("{:8.3}A", pack_hv_status.current),
+            level_style(
+                ratatui::style::Style::default().fg(ratatui::style::Color::Black),
+                current_level,
             ),
+        ),
+    ]));
+
+    // The BMS's own pack-wide extremes.  The pack message only has
+    // module IDs, the brick comes from that module's min/max, and we
+    // check the location against our own value for that brick, or for
+    // the module if we don't know the brick.
+    let pack_min_max = battery_pack.pack_min_max.fresh(now).unwrap_or_default();
+    let brick_extreme =
+        |extreme: model::Extreme,
+         bms_brick: fn(model::MinMax) -> Option<model::Extreme>,
+         ours: fn(model::ModuleBrick, model::ModuleBrick) -> model::ModuleBrick| {
+            let Some(module) = extreme.index(model::NUM_MODULES) else {
+                return BmsExtreme::unknown_location(extreme.value);
+            };
+            let battery_module = &battery_pack.modules[module];
+            let brick = battery_module
+                .min_max
+                .fresh(now)
+                .and_then(bms_brick)
+                .and_then(|brick| brick.index(model::NUM_BRICKS));
+            match brick {
+                Some(brick) => BmsExtreme {
+                    value: extreme.value,
+                    location: format!("m{module} b{}", brick + 1),
+                    ours_there: battery_module.v_bricks[brick].fresh(now),
+                },
+                None => BmsExtreme {
+                    value: extreme.value,
+                    location: format!("m{module}"),
+                    ours_there: battery_module
+                        .v_brick_min_max(now)
+                        .map(|(min, max)| ours(min, max).voltage),
+                },
+            }
         };
+    let module_extreme = |extreme: model::Extreme, ours: fn(_, _) -> model::ModuleTemperature| {
+        let Some(module) = extreme.index(model::NUM_MODULES) else {
+            return BmsExtreme::unknown_location(extreme.value);
+        };
+        BmsExtreme {
+            value: extreme.value,
+            location: format!("m{module}"),
+            ours_there: battery_pack.modules[module]
+                .t_module_min_max(now)
+                .map(|(min, max)| ours(min, max).temperature),
+        }
+    };
+    let v_brick_min_max = battery_pack.v_brick_min_max(now);
+    let t_module_min_max = battery_pack.t_module_min_max(now);
+    text.push(min_max_line(
+        "Brick min: ",
+        pack_min_max
+            .v_brick_min
+            .map(|e| brick_extreme(e, |m| m.v_brick_min, |min, _| min)),
+        v_brick_min_max
+            .map(|(min, _)| (min.voltage, format!("m{} b{}", min.module, min.brick + 1))),
+        model::MIN_MAX_VOLTAGE_TOLERANCE,
+        "V",
+        3,
+    ));
+    text.push(min_max_line(
+        "Brick max: ",
+        pack_min_max
+            .v_brick_max
+            .map(|e| brick_extreme(e, |m| m.v_brick_max, |_, max| max)),
+        v_brick_min_max
+            .map(|(_, max)| (max.voltage, format!("m{} b{}", max.module, max.brick + 1))),
+        model::MIN_MAX_VOLTAGE_TOLERANCE,
+        "V",
+        3,
+    ));
+    text.push(min_max_line(
+        "Temp min:  ",
+        pack_min_max
+            .t_module_min
+            .map(|e| module_extreme(e, |min, _| min)),
+        t_module_min_max.map(|(min, _)| (min.temperature, format!("m{}", min.module))),
+        model::MIN_MAX_TEMPERATURE_TOLERANCE,
+        "°C",
+        1,
+    ));
+    text.push(min_max_line(
+        "Temp max:  ",
+        pack_min_max
+            .t_module_max
+            .map(|e| module_extreme(e, |_, max| max)),
+        t_module_min_max.map(|(_, max)| (max.temperature, format!("m{}", max.module))),
+        model::MIN_MAX_TEMPERATURE_TOLERANCE,
+        "°C",
+        1,
+    ));
+
+    let border_style = match pack_status {
+        Some(pack_status) if pack_status.fets_welded() => ratatui::style::Style::default()
+            .fg(ratatui::style::Color::Red)
+            .add_modifier(ratatui::style::Modifier::BOLD),
+        _ => ratatui::style::Style::default(),
+    };
+
+    ratatui::widgets::Paragraph::new(text)
+        .block(
+            ratatui::widgets::Block::new()
+                .title("Battery Pack")
+                .borders(ratatui::widgets::Borders::ALL)
+                .border_style(border_style)
+                .padding(ratatui::widgets::block::Padding::ZERO),
+        )
+        .render(area, buf);
+}
 
-        text.push(ratatui::text::Line::from(vec![
-            ratatui::text::Span::styled("Mode: ", ratatui::style::Style::default()),
-            ratatui::text::Span::styled("Sleep", sleep_style),
-            ratatui::text::Span::styled(" ", ratatui::style::Style::default()),
-            ratatui::text::Span::styled("Charge", charge_style),
-            ratatui::text::Span::styled(" ", ratatui::style::Style::default()),
-            ratatui::text::Span::styled("Drive", drive_style),
-            ratatui::text::Span::styled(" ", ratatui::style::Style::default()),
-            ratatui::text::Span::styled("None", none_style),
-        ]));
+/// One of the BMS's min/max extremes: its value, where it is, and our
+/// own value there, if we have one to check it by.
+struct BmsExtreme {
+    value: f32,
+    location: String,
+    ours_there: Option<f32>,
+}
 
-        text.push(ratatui::text::Line::from(vec![
-            ratatui::text::Span::styled(
-                format!(
-                    "Charge modules: {:012b} connected, {:012b} standby",
-                    self.pack_diagnostic_connect
-                        .num_modules_connected_for_charge,
-                    self.pack_diagnostic_connect.num_modules_standby_for_charge
-                ),
-                ratatui::style::Style::default().fg(ratatui::style::Color::Black),
+impl BmsExtreme {
+    fn unknown_location(value: f32) -> Self {
+        BmsExtreme {
+            value,
+            location: String::from("?"),
+            ours_there: None,
+        }
+    }
+}
+
+/// One of the BMS's min/max extremes, `bms`, as its value and where
+/// it is, then `ours` in red if the two disagree: if their values are
+/// further apart than `tolerance`, or if our value where the BMS says
+/// the extreme is isn't ours.  That means one side is working from
+/// stale data, most likely because frames are being dropped, or that
+/// we're reading the BMS's IDs wrong.
+fn min_max_spans(
+    bms: Option<BmsExtreme>,
+    ours: Option<(f32, String)>,
+    tolerance: f32,
+    unit: &str,
+    precision: usize,
+) -> Vec<ratatui::text::Span<'static>> {
+    let text_style = ratatui::style::Style::default().fg(ratatui::style::Color::Black);
+    let format = |value: f32, location: &str| match location.is_empty() {
+        true => format!("{value:.precision$}{unit}"),
+        false => format!("{value:.precision$}{unit} {location}"),
+    };
+
+    let Some(bms) = bms else {
+        return vec![ratatui::text::Span::styled("?", text_style)];
+    };
+    let mut spans = vec![ratatui::text::Span::styled(
+        format(bms.value, &bms.location),
+        text_style,
+    )];
+    if let Some(ours) = ours {
+        let value_differs = (bms.value - ours.0).abs() > tolerance;
+        let location_differs = bms
+            .ours_there
+            .is_some_and(|ours_there| (ours_there - ours.0).abs() > tolerance);
+        if value_differs || location_differs {
+            spans.push(ratatui::text::Span::styled(
+                format!(" ≠ ours {}", format(ours.0, &ours.1)),
+                ratatui::style::Style::default()
+                    .fg(ratatui::style::Color::Red)
+                    .add_modifier(ratatui::style::Modifier::BOLD),
+            ));
+        }
+    }
+    spans
+}
+
+fn min_max_line(
+    label: &'static str,
+    bms: Option<BmsExtreme>,
+    ours: Option<(f32, String)>,
+    tolerance: f32,
+    unit: &str,
+    precision: usize,
+) -> ratatui::text::Line<'static> {
+    let mut spans = vec![ratatui::text::Span::styled(
+        label,
+        ratatui::style::Style::default().fg(ratatui::style::Color::Black),
+    )];
+    spans.extend(min_max_spans(bms, ours, tolerance, unit, precision));
+    ratatui::text::Line::from(spans)
+}
+
+// Render a bar showing how much of `limit` the measured `current`
+// is using.  Both are magnitudes, in Amps.
+fn limit_bar(label: &str, current: f32, limit: f32) -> ratatui::text::Line<'static> {
+    const BAR_WIDTH: usize = 20;
+
+    let fraction = if limit > 0.0 {
+        (current / limit).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let filled = (fraction * BAR_WIDTH as f32).round() as usize;
+
+    let bar_style = if limit > 0.0 && current >= limit {
+        ratatui::style::Style::default().fg(ratatui::style::Color::Red)
+    } else if fraction >= 0.8 {
+        ratatui::style::Style::default().fg(ratatui::style::Color::Magenta)
+    } else {
+        ratatui::style::Style::default().fg(ratatui::style::Color::Green)
+    };
+
+    ratatui::text::Line::from(vec![
+        ratatui::text::Span::styled(
+            format!("{label:9} "),
+            ratatui::style::Style::default().fg(ratatui::style::Color::Black),
+        ),
+        ratatui::text::Span::styled("█".repeat(filled), bar_style),
+        ratatui::text::Span::styled(
+            "░".repeat(BAR_WIDTH - filled),
+            ratatui::style::Style::default().fg(ratatui::style::Color::DarkGray),
+        ),
+        ratatui::text::Span::styled(
+            format!(" {:7.2}/{:7.2}A", current, limit),
+            ratatui::style::Style::default().fg(ratatui::style::Color::Black),
+        ),
+    ])
+}
+
+fn render_limits(
+    battery_pack: &model::BatteryPack,
+    area: ratatui::layout::Rect,
+    buf: &mut ratatui::buffer::Buffer,
+) {
+    let mut text = vec![];
+
+    let pack_hv_status = battery_pack.pack_hv_status.get_or_default();
+    let pack_chg_limits = battery_pack.pack_chg_limits.get_or_default();
+    let pack_dch_limits = battery_pack.pack_dch_limits.get_or_default();
+    let pack_pwr_available = battery_pack.pack_pwr_available.get_or_default();
+
+    // Positive pack current is charging, negative is discharging.
+    // The discharge limits are reported as negative currents.
+    let current = pack_hv_status.current;
+    let charge_current = current.max(0.0);
+    let discharge_current = (-current).max(0.0);
+
+    text.push(ratatui::text::Line::from(vec![
+        ratatui::text::Span::styled(
+            format!(
+                "Pack current: {:8.3}A ({})",
+                current,
+                if current < 0.0 {
+                    "discharging"
+                } else {
+                    "charging"
+                }
             ),
-        ]));
+            ratatui::style::Style::default().fg(ratatui::style::Color::Black),
+        ),
+    ]));
+
+    text.push(limit_bar(
+        "Chg inst",
+        charge_current,
+        pack_chg_limits.current_inst,
+    ));
+    text.push(limit_bar(
+        "Chg 10s",
+        charge_current,
+        pack_chg_limits.current_10s,
+    ));
+    text.push(limit_bar(
+        "Chg cont",
+        charge_current,
+        pack_chg_limits.current_cont,
+    ));
+    text.push(limit_bar(
+        "Dch inst",
+        discharge_current,
+        pack_dch_limits.current_inst.abs(),
+    ));
+    text.push(limit_bar(
+        "Dch 10s",
+        discharge_current,
+        pack_dch_limits.current_10s.abs(),
+    ));
+    text.push(limit_bar(
+        "Dch cont",
+        discharge_current,
+        pack_dch_limits.current_cont.abs(),
+    ));
+
+    text.push(ratatui::text::Line::from(vec![
+        ratatui::text::Span::styled(
+            format!(
+                "Bus voltage: {:.3}V (min {:.3}V, max {:.3}V)",
+                pack_hv_status.voltage,
+                pack_dch_limits.bus_voltage_min,
+                pack_chg_limits.bus_voltage_max,
+            ),
+            ratatui::style::Style::default().fg(ratatui::style::Color::Black),
+        ),
+    ]));
+
+    text.push(ratatui::text::Line::from(vec![
+        ratatui::text::Span::styled(
+            format!(
+                "Power available: charge {:.2}kW, discharge {:.2}kW",
+                pack_pwr_available.charge_kw, pack_pwr_available.discharge_kw,
+            ),
+            ratatui::style::Style::default().fg(ratatui::style::Color::Black),
+        ),
+    ]));
+
+    ratatui::widgets::Paragraph::new(text)
+        .block(
+            ratatui::widgets::Block::new()
+                .title("Pack Limits")
+                .borders(ratatui::widgets::Borders::ALL)
+                .padding(ratatui::widgets::block::Padding::ZERO),
+        )
+        .render(area, buf);
+}
 
+fn num_fault_lines(battery_pack: &model::BatteryPack, alarms: &[thresholds::Alarm]) -> usize {
+    let mut severities = vec![];
+    let mut num_faults = 0;
+    for battery_module in &battery_pack.modules {
+        for (fault_info, _) in battery_module.faults.active() {
+            num_faults += 1;
+            if !severities.contains(&fault_info.severity()) {
+                severities.push(fault_info.severity());
+            }
+        }
+    }
+    // One header line per severity group, plus the faults themselves,
+    // then the same for the alarms.
+    let num_alarm_lines = match alarms.is_empty() {
+        true => 0,
+        false => 1 + alarms.len(),
+    };
+    severities.len() + num_faults + num_alarm_lines
+}
+
+fn render_faults(
+    battery_pack: &model::BatteryPack,
+    alarms: &[thresholds::Alarm],
+    now: std::time::Instant,
+    area: ratatui::layout::Rect,
+    buf: &mut ratatui::buffer::Buffer,
+) {
+    let mut faults = vec![];
+    for (module_index, battery_module) in battery_pack.modules.iter().enumerate() {
+        for (fault_info, active_fault) in battery_module.faults.active() {
+            faults.push((
+                fault_info.severity(),
+                module_index,
+                fault_info,
+                active_fault,
+            ));
+        }
+    }
+    faults.sort_by_key(|(severity, module_index, fault_info, _)| {
+        (*severity, *module_index, fault_info.name)
+    });
+
+    let mut text = vec![];
+    let mut current_severity = None;
+    for (severity, module_index, fault_info, active_fault) in faults {
+        if current_severity != Some(severity) {
+            current_severity = Some(severity);
+            text.push(ratatui::text::Line::from(vec![
+                ratatui::text::Span::styled(
+                    format!("{}:", severity.name()),
+                    severity_style(severity).add_modifier(ratatui::style::Modifier::BOLD),
+                ),
+            ]));
+        }
         text.push(ratatui::text::Line::from(vec![
             ratatui::text::Span::styled(
                 format!(
-                    "Drive modules:  {:012b} connected, {:012b} standby",
-                    self.pack_diagnostic_connect.num_modules_connected_for_drive,
-                    self.pack_diagnostic_connect.num_modules_standby_for_drive
+                    "    module {} {:32} first seen {} ago, last seen {} ago",
+                    module_index,
+                    fault_info.name,
+                    format_age(now.saturating_duration_since(active_fault.first_seen)),
+                    format_age(now.saturating_duration_since(active_fault.last_seen)),
                 ),
-                ratatui::style::Style::default().fg(ratatui::style::Color::Black),
+                severity_style(severity),
             ),
         ]));
+    }
 
+    if !alarms.is_empty() {
+        text.push(ratatui::text::Line::styled(
+            "Alarms:",
+            ratatui::style::Style::default()
+                .fg(ratatui::style::Color::Black)
+                .add_modifier(ratatui::style::Modifier::BOLD),
+        ));
+        let mut alarms = alarms.to_vec();
+        alarms.sort_by_key(|alarm| (std::cmp::Reverse(alarm.level), alarm.key()));
+        for alarm in alarms {
+            text.push(ratatui::text::Line::styled(
+                format!("    {alarm} {}", alarm.level.name()),
+                level_style(
+                    ratatui::style::Style::default().fg(ratatui::style::Color::Black),
+                    alarm.level,
+                ),
+            ));
+        }
+    }
+
+    if text.is_empty() {
         text.push(ratatui::text::Line::from(vec![
             ratatui::text::Span::styled(
-                format!("SOC: {:.1}%", self.pack_soc.soc),
-                ratatui::style::Style::default().fg(ratatui::style::Color::Black),
+                "No active faults or alarms",
+                ratatui::style::Style::default().fg(ratatui::style::Color::DarkGray),
             ),
         ]));
+    }
 
-        text.push(ratatui::text::Line::from(vec![
-            ratatui::text::Span::styled(
+    ratatui::widgets::Paragraph::new(text)
+        .block(
+            ratatui::widgets::Block::new()
+                .title("Faults")
+                .borders(ratatui::widgets::Borders::ALL)
+                .padding(ratatui::widgets::block::Padding::ZERO),
+        )
+        .render(area, buf);
+}
+
+/// Format `value` with `f`, or "?" if we've never heard it.
+fn or_unknown<T>(value: Option<T>, f: impl FnOnce(T) -> String) -> String {
+    match value {
+        Some(value) => f(value),
+        None => String::from("?"),
+    }
+}
+
+/// One line of `area`: `label` with the latest value, then a sparkline
+/// of as much of `history` as fits, scaled to its own min and max.
+fn render_sparkline(
+    label: &str,
+    unit: &str,
+    history: &history::History,
+    area: ratatui::layout::Rect,
+    buf: &mut ratatui::buffer::Buffer,
+) {
+    const LABEL_WIDTH: u16 = 44;
+
+    let columns = ratatui::layout::Layout::default()
+        .direction(ratatui::layout::Direction::Horizontal)
+        .constraints(vec![
+            ratatui::layout::Constraint::Length(LABEL_WIDTH),
+            ratatui::layout::Constraint::Min(0),
+        ])
+        .split(area);
+
+    let width = columns[1].width as usize;
+    let mut values: Vec<f32> = history
+        .samples()
+        .rev()
+        .take(width)
+        .map(|(_, value)| value)
+        .collect();
+    values.reverse();
+
+    let min = values.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let text = match history.latest() {
+        None => format!("{label:12} no data"),
+        Some(latest) => format!("{label:12} {latest:8.2}{unit:2} ({min:.2} to {max:.2})"),
+    };
+    ratatui::widgets::Paragraph::new(text)
+        .style(ratatui::style::Style::default().fg(ratatui::style::Color::Black))
+        .render(columns[0], buf);
+
+    // One to eight eighths of a cell, so the lowest value still shows.
+    let data: Vec<u64> = values
+        .iter()
+        .map(|value| match max > min {
+            true => 1 + ((value - min) / (max - min) * 7.0).round() as u64,
+            false => 4,
+        })
+        .collect();
+    ratatui::widgets::Sparkline::default()
+        .data(&data)
+        .max(8)
+        .style(ratatui::style::Style::default().fg(ratatui::style::Color::Blue))
+        .render(columns[1], buf);
+}
+
+/// One line per `series` (a label, its history and its colour) over the
+/// last `window`, scaled to fit all of them.
+fn render_chart(
+    title: String,
+    series: &[(&str, &history::History, ratatui::style::Color)],
+    precision: usize,
+    window: ChartWindow,
+    now: std::time::Instant,
+    area: ratatui::layout::Rect,
+    buf: &mut ratatui::buffer::Buffer,
+) {
+    let window_secs = window.duration().as_secs_f64();
+    let start = now.checked_sub(window.duration()).unwrap_or(now);
+
+    // x is seconds relative to now, so the newest sample is on the
+    // right edge.
+    let points: Vec<Vec<(f64, f64)>> = series
+        .iter()
+        .map(|(_, history, _)| {
+            history
+                .since(start)
+                .map(|(t, value)| {
+                    (
+                        -now.saturating_duration_since(t).as_secs_f64(),
+                        value as f64,
+                    )
+                })
+                .collect()
+        })
+        .collect();
+
+    let (min, max) = points
+        .iter()
+        .flatten()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), (_, y)| {
+            (min.min(*y), max.max(*y))
+        });
+    // Pad the range a little, but don't take things that can't go
+    // negative (like a count) below zero.
+    let floor = match min >= 0.0 {
+        true => 0.0,
+        false => f64::NEG_INFINITY,
+    };
+    let (min, max) = match (min.is_finite(), max > min) {
+        (false, _) => (0.0, 1.0),
+        (true, true) => (
+            (min - (max - min) * 0.1).max(floor),
+            max + (max - min) * 0.1,
+        ),
+        (true, false) => ((min - 0.5).max(floor), max + 0.5),
+    };
+
+    let datasets: Vec<ratatui::widgets::Dataset> = series
+        .iter()
+        .zip(points.iter())
+        .map(|((name, _, color), points)| {
+            ratatui::widgets::Dataset::default()
+                .name(*name)
+                .marker(ratatui::symbols::Marker::Braille)
+                .graph_type(ratatui::widgets::GraphType::Line)
+                .style(ratatui::style::Style::default().fg(*color))
+                .data(points)
+        })
+        .collect();
+
+    let axis_style = ratatui::style::Style::default().fg(ratatui::style::Color::DarkGray);
+    let x_axis = ratatui::widgets::Axis::default()
+        .style(axis_style)
+        .bounds([-window_secs, 0.0])
+        .labels([
+            format!("-{}", format_age(window.duration())),
+            format!("-{}", format_age(window.duration() / 2)),
+            String::from("now"),
+        ]);
+    let y_axis = ratatui::widgets::Axis::default()
+        .style(axis_style)
+        .bounds([min, max])
+        .labels([
+            format!("{min:.precision$}"),
+            format!("{:.precision$}", (min + max) / 2.0),
+            format!("{max:.precision$}"),
+        ]);
+
+    // Only show the legend when there's more than one line to tell
+    // apart.
+    let legend = match series.len() > 1 {
+        true => Some(ratatui::widgets::LegendPosition::TopLeft),
+        false => None,
+    };
+
+    ratatui::widgets::Chart::new(datasets)
+        .block(
+            ratatui::widgets::Block::new()
+                .title(title)
+                .borders(ratatui::widgets::Borders::ALL)
+                .style(ratatui::style::Style::default().fg(ratatui::style::Color::Black)),
+        )
+        .x_axis(x_axis)
+        .y_axis(y_axis)
+        .legend_position(legend)
+        .hidden_legend_constraints((
+            ratatui::layout::Constraint::Ratio(1, 4),
+            ratatui::layout::Constraint::Ratio(1, 2),
+        ))
+        .render(area, buf);
+}
+
+/// The latest value of `history` for a chart title, or "no data".
+fn latest(history: &history::History, f: impl FnOnce(f32) -> String) -> String {
+    match history.latest() {
+        Some(value) => f(value),
+        None => String::from("no data"),
+    }
+}
+
+/// Charts of the pack's vitals over the last `window`: voltage and
+/// current for following a charge from CC to CV, and the brick
+/// voltage spread and balancing for watching the pack balance.
+fn render_charts(
+    pack_history: &history::PackHistory,
+    window: ChartWindow,
+    now: std::time::Instant,
+    area: ratatui::layout::Rect,
+    buf: &mut ratatui::buffer::Buffer,
+) {
+    let rows = ratatui::layout::Layout::default()
+        .direction(ratatui::layout::Direction::Vertical)
+        .constraints(vec![ratatui::layout::Constraint::Ratio(1, 3); 3])
+        .split(area);
+    let cells: Vec<ratatui::layout::Rect> = rows
+        .iter()
+        .flat_map(|row| {
+            ratatui::layout::Layout::default()
+                .direction(ratatui::layout::Direction::Horizontal)
+                .constraints(vec![ratatui::layout::Constraint::Ratio(1, 2); 2])
+                .split(*row)
+                .to_vec()
+        })
+        .collect();
+
+    render_chart(
+        format!(
+            "Pack voltage {}",
+            latest(&pack_history.voltage, |v| format!("{v:.3}V"))
+        ),
+        &[(
+            "Voltage",
+            &pack_history.voltage,
+            ratatui::style::Color::Blue,
+        )],
+        2,
+        window,
+        now,
+        cells[0],
+        buf,
+    );
+    render_chart(
+        format!(
+            "Pack current {}",
+            latest(&pack_history.current, |a| format!("{a:.3}A"))
+        ),
+        &[(
+            "Current",
+            &pack_history.current,
+            ratatui::style::Color::Blue,
+        )],
+        2,
+        window,
+        now,
+        cells[1],
+        buf,
+    );
+    render_chart(
+        format!(
+            "SOC {}",
+            latest(&pack_history.soc, |soc| format!("{soc:.1}%"))
+        ),
+        &[("SOC", &pack_history.soc, ratatui::style::Color::Blue)],
+        1,
+        window,
+        now,
+        cells[2],
+        buf,
+    );
+    let spread = match (
+        pack_history.brick_min.latest(),
+        pack_history.brick_max.latest(),
+    ) {
+        (Some(min), Some(max)) => format!(
+            "min {min:.3}V max {max:.3}V spread {:.0}mV",
+            (max - min) * 1000.0
+        ),
+        _ => String::from("no data"),
+    };
+    render_chart(
+        format!("Brick voltage {spread}"),
+        &[
+            ("Max", &pack_history.brick_max, ratatui::style::Color::Red),
+            ("Min", &pack_history.brick_min, ratatui::style::Color::Blue),
+        ],
+        3,
+        window,
+        now,
+        cells[3],
+        buf,
+    );
+    render_chart(
+        format!(
+            "Max module temperature {}",
+            latest(&pack_history.max_temperature, |t| format!("{t:.1}°C"))
+        ),
+        &[(
+            "Temperature",
+            &pack_history.max_temperature,
+            ratatui::style::Color::Blue,
+        )],
+        1,
+        window,
+        now,
+        cells[4],
+        buf,
+    );
+    render_chart(
+        format!(
+            "Bricks balancing {}",
+            latest(&pack_history.balancing, |n| format!("{n:.0}"))
+        ),
+        &[(
+            "Balancing",
+            &pack_history.balancing,
+            ratatui::style::Color::Blue,
+        )],
+        0,
+        window,
+        now,
+        cells[5],
+        buf,
+    );
+}
+
+/// Where `v` falls between `min` and `max`, from blue for the lowest
+/// through white to red for the highest.
+fn heat_color(v: f32, min: f32, max: f32) -> ratatui::style::Color {
+    let x = match max > min {
+        true => ((v - min) / (max - min)).clamp(0.0, 1.0),
+        false => 0.5,
+    };
+    let (r, g, b) = match x < 0.5 {
+        true => {
+            let x = x * 2.0;
+            (100.0 + 155.0 * x, 140.0 + 115.0 * x, 255.0)
+        }
+        false => {
+            let x = (x - 0.5) * 2.0;
+            (255.0, 255.0 - 155.0 * x, 255.0 - 155.0 * x)
+        }
+    };
+    ratatui::style::Color::Rgb(r as u8, g as u8, b as u8)
+}
+
+/// Every fresh brick voltage in the pack, one row per module, coloured
+/// relative to the pack's lowest and highest brick.  The lowest brick
+/// is marked ▼, the highest ▲, and balancing bricks •.
+fn render_heatmap(
+    battery_pack: &model::BatteryPack,
+    alarms: &[thresholds::Alarm],
+    selected_module: usize,
+    now: std::time::Instant,
+    area: ratatui::layout::Rect,
+    buf: &mut ratatui::buffer::Buffer,
+) {
+    let text_style = ratatui::style::Style::default().fg(ratatui::style::Color::Black);
+    let absent_style = ratatui::style::Style::default().fg(ratatui::style::Color::DarkGray);
+
+    let pack_min_max = battery_pack.v_brick_min_max(now);
+    let (min, max) = match pack_min_max {
+        Some((min, max)) => (min.voltage, max.voltage),
+        None => (0.0, 0.0),
+    };
+
+    let mut header: Vec<ratatui::text::Span> = vec![ratatui::text::Span::styled(
+        format!("{:10}", ""),
+        text_style,
+    )];
+    for brick_index in 0..model::NUM_BRICKS {
+        header.push(ratatui::text::Span::styled(
+            format!("{:>5}   ", brick_index + 1),
+            text_style,
+        ));
+    }
+    header.push(ratatui::text::Span::styled("  Spread", text_style));
+    let mut lines = vec![ratatui::text::Line::from(header)];
+
+    // The average brick voltage of each module we have all the bricks
+    // of, for the module-to-module spread.
+    let mut module_averages: Vec<(usize, f32)> = vec![];
+
+    for (module_index, battery_module) in battery_pack.modules.iter().enumerate() {
+        let label_style = match module_index == selected_module {
+            true => text_style.bg(ratatui::style::Color::DarkGray),
+            false => text_style,
+        };
+        let mut spans = vec![
+            ratatui::text::Span::styled(format!("Module {module_index:<2}"), label_style),
+            ratatui::text::Span::raw(" "),
+        ];
+
+        let balancing = battery_module.balancing.fresh(now).unwrap_or_default();
+        let v_bricks: Vec<Option<f32>> = battery_module
+            .v_bricks
+            .iter()
+            .map(|v_brick| v_brick.fresh(now))
+            .collect();
+
+        for (brick_index, v_brick) in v_bricks.iter().enumerate() {
+            let Some(v_brick) = v_brick else {
+                spans.push(ratatui::text::Span::styled("   --   ", absent_style));
+                continue;
+            };
+            let at = |pack_brick: model::PackBrick| {
+                pack_brick.module == module_index && pack_brick.brick == brick_index
+            };
+            let marker = match pack_min_max {
+                Some((pack_min, _)) if at(pack_min) => "▼",
+                Some((_, pack_max)) if at(pack_max) => "▲",
+                _ => " ",
+            };
+            let balancing = match balancing[brick_index] {
+                true => "•",
+                false => " ",
+            };
+            let mut style = text_style.bg(heat_color(*v_brick, min, max));
+            if marker != " " {
+                style = style.bold();
+            }
+            spans.push(ratatui::text::Span::styled(
+                format!("{marker}{v_brick:.3}{balancing}"),
+                style,
+            ));
+            spans.push(ratatui::text::Span::raw(" "));
+        }
+
+        match battery_module.v_brick_min_max(now) {
+            Some((module_min, module_max)) => {
+                let spread = (module_max.voltage - module_min.voltage) * 1000.0;
+                spans.push(ratatui::text::Span::styled(
+                    format!("{spread:6.0}mV"),
+                    level_style(
+                        text_style,
+                        alarm_level(
+                            alarms,
+                            thresholds::Signal::BrickDelta,
+                            Some(module_index),
+                            None,
+                        ),
+                    ),
+                ));
+                if v_bricks.iter().all(|v_brick| v_brick.is_some()) {
+                    let sum: f32 = v_bricks.iter().flatten().sum();
+                    module_averages.push((module_index, sum / model::NUM_BRICKS as f32));
+                }
+            }
+            None => spans.push(ratatui::text::Span::styled(
+                match battery_module.is_present() {
+                    true => "no brick voltages",
+                    false => "absent",
+                },
+                absent_style,
+            )),
+        }
+
+        lines.push(ratatui::text::Line::from(spans));
+    }
+
+    lines.push(ratatui::text::Line::raw(""));
+    match pack_min_max {
+        Some((pack_min, pack_max)) => {
+            lines.push(ratatui::text::Line::styled(
                 format!(
-                    "{:.3}V {:8.3}A",
-                    self.pack_hv_status.voltage, self.pack_hv_status.current,
+                    "Pack: ▼ lowest {:.3}V (module {} brick {}), ▲ highest {:.3}V (module {} brick {}), spread {:.0}mV",
+                    pack_min.voltage,
+                    pack_min.module,
+                    pack_min.brick + 1,
+                    pack_max.voltage,
+                    pack_max.module,
+                    pack_max.brick + 1,
+                    (pack_max.voltage - pack_min.voltage) * 1000.0,
                 ),
-                ratatui::style::Style::default().fg(ratatui::style::Color::Black),
-            ),
-        ]));
+                text_style,
+            ));
+        }
+        None => lines.push(ratatui::text::Line::styled(
+            "Pack: no brick voltages",
+            text_style,
+        )),
+    }
 
-        ratatui::widgets::Paragraph::new(text)
-            .block(
-                ratatui::widgets::Block::new()
-                    .title("Battery Pack")
-                    .borders(ratatui::widgets::Borders::ALL)
-                    .padding(ratatui::widgets::block::Padding::ZERO),
-            )
-            .render(area, buf);
+    let lowest_module = module_averages
+        .iter()
+        .copied()
+        .reduce(|a, b| match b.1 < a.1 {
+            true => b,
+            false => a,
+        });
+    let highest_module = module_averages
+        .iter()
+        .copied()
+        .reduce(|a, b| match b.1 > a.1 {
+            true => b,
+            false => a,
+        });
+    if let (Some(lowest), Some(highest)) = (lowest_module, highest_module) {
+        lines.push(ratatui::text::Line::styled(
+            format!(
+                "Module to module: average brick {:.3}V (module {}) to {:.3}V (module {}), spread {:.0}mV",
+                lowest.1,
+                lowest.0,
+                highest.1,
+                highest.0,
+                (highest.1 - lowest.1) * 1000.0,
+            ),
+            text_style,
+        ));
     }
+
+    lines.push(ratatui::text::Line::styled(
+        "• balancing",
+        ratatui::style::Style::default().fg(ratatui::style::Color::DarkGray),
+    ));
+
+    ratatui::widgets::Paragraph::new(lines)
+        .block(
+            ratatui::widgets::Block::new()
+                .title("Brick Voltages")
+                .borders(ratatui::widgets::Borders::ALL),
+        )
+        .render(area, buf);
 }
 
-impl ratatui::widgets::Widget for &App {
+impl<T: transport::Transport> ratatui::widgets::Widget for &App<T> {
     fn render(self, area: ratatui::layout::Rect, buf: &mut ratatui::buffer::Buffer) {
         let title = ratatui::text::Line::from(" ABS Alliance Battery Monitor ".bold());
-        let title_bottom = ratatui::text::Line::from(vec![
-            " ".into(),
-            "Q".blue().bold(),
-            "uit ".into(),
-            "S".blue().bold(),
-            "leep ".into(),
-            "C".blue().bold(),
-            "harge ".into(),
-            "D".blue().bold(),
-            "rive ".into(),
-            "N".blue().bold(),
-            "one ".into(),
-        ]);
+        let title_bottom = match &self.source {
+            Source::Can(_) => ratatui::text::Line::from(vec![
+                " ".into(),
+                "Q".blue().bold(),
+                "uit ".into(),
+                "S".blue().bold(),
+                "leep ".into(),
+                "C".blue().bold(),
+                "harge ".into(),
+                "D".blue().bold(),
+                "rive ".into(),
+                "N".blue().bold(),
+                "one ".into(),
+            ]),
+            Source::Replay(_) => ratatui::text::Line::from(vec![
+                " ".into(),
+                "Q".blue().bold(),
+                "uit ".into(),
+                "Space".blue().bold(),
+                " pause ".into(),
+                "←/→".blue().bold(),
+                " seek ".into(),
+                "Home".blue().bold(),
+                " restart ".into(),
+                "+/-".blue().bold(),
+                " speed ".into(),
+            ]),
+        };
 
-        let block = ratatui::widgets::Block::bordered()
+        let mut block = ratatui::widgets::Block::bordered()
             .title(title.centered())
             .title_bottom(title_bottom.centered())
             .border_set(ratatui::symbols::border::THICK);
 
+        let view_keys = match self.view {
+            View::Overview => ratatui::text::Line::from(vec![
+                " ".into(),
+                "↑/↓".blue().bold(),
+                " select ".into(),
+                "Enter".blue().bold(),
+                " details ".into(),
+                "G".blue().bold(),
+                "raphs ".into(),
+                "H".blue().bold(),
+                "eatmap ".into(),
+            ]),
+            View::ModuleDetail => ratatui::text::Line::from(vec![
+                " ".into(),
+                "↑/↓".blue().bold(),
+                " module ".into(),
+                "G".blue().bold(),
+                "raphs ".into(),
+                "H".blue().bold(),
+                "eatmap ".into(),
+                "Esc".blue().bold(),
+                " back ".into(),
+            ]),
+            View::Heatmap => ratatui::text::Line::from(vec![
+                " ".into(),
+                "↑/↓".blue().bold(),
+                " select ".into(),
+                "Enter".blue().bold(),
+                " details ".into(),
+                "Esc".blue().bold(),
+                " back ".into(),
+            ]),
+            View::Charts => ratatui::text::Line::from(vec![
+                " ".into(),
+                "W".blue().bold(),
+                format!("indow {} ", self.chart_window.name()).into(),
+                "Esc".blue().bold(),
+                " back ".into(),
+            ]),
+        };
+        block = block.title_bottom(view_keys.right_aligned());
+
+        if let Some(recording_error) = &self.recording_error {
+            block = block.title(
+                ratatui::text::Line::from(format!(" {recording_error} "))
+                    .red()
+                    .left_aligned(),
+            );
+        }
+
+        if let Source::Replay(replay) = &self.source {
+            let position = replay.position(std::time::Instant::now());
+            let state = if replay.is_paused() {
+                "paused"
+            } else if replay.is_finished() {
+                "finished"
+            } else {
+                "playing"
+            };
+            block = block.title(
+                ratatui::text::Line::from(format!(
+                    " {} {:.1}s/{:.1}s {}x {} ",
+                    replay.name(),
+                    position.as_secs_f64(),
+                    replay.duration().as_secs_f64(),
+                    replay.speed(),
+                    state,
+                ))
+                .right_aligned(),
+            );
+        }
+
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        match self.view {
+            View::Overview => self.render_overview(&self.alarms, inner, buf),
+            View::ModuleDetail => self.render_module_detail(&self.alarms, inner, buf),
+            View::Charts => render_charts(
+                &self.pack_history,
+                self.chart_window,
+                self.now(),
+                inner,
+                buf,
+            ),
+            View::Heatmap => render_heatmap(
+                &self.battery_pack,
+                &self.alarms,
+                self.selected_module,
+                self.now(),
+                inner,
+                buf,
+            ),
+        }
+    }
+}
+
+impl<T: transport::Transport> App<T> {
+    /// The pack, its limits and faults, and the module list.
+    fn render_overview(
+        &self,
+        alarms: &[thresholds::Alarm],
+        area: ratatui::layout::Rect,
+        buf: &mut ratatui::buffer::Buffer,
+    ) {
         let layout = ratatui::layout::Layout::default()
             .direction(ratatui::layout::Direction::Vertical)
             .constraints(vec![
                 ratatui::layout::Constraint::Min(12),
+                ratatui::layout::Constraint::Length(
+                    (num_fault_lines(&self.battery_pack, alarms).clamp(1, 16) + 2) as u16,
+                ),
                 ratatui::layout::Constraint::Percentage(100),
             ])
-            .split(block.inner(area));
+            .split(area);
 
-        block.render(area, buf);
-
-        self.battery_pack.render(layout[0], buf);
+        let pack_layout = ratatui::layout::Layout::default()
+            .direction(ratatui::layout::Direction::Horizontal)
+            .constraints(vec![
+                ratatui::layout::Constraint::Percentage(50),
+                ratatui::layout::Constraint::Percentage(50),
+            ])
+            .split(layout[0]);
+
+        render_pack(
+            &self.battery_pack,
+            self.mode,
+            alarms,
+            self.now(),
+            pack_layout[0],
+            buf,
+        );
+        render_limits(&self.battery_pack, pack_layout[1], buf);
+        render_faults(&self.battery_pack, alarms, self.now(), layout[1], buf);
 
         // Convert slice of BatteryModule to Vec<ListItem>
         let items: Vec<ratatui::widgets::ListItem> = self
             .battery_pack
             .modules
             .iter()
-            .map(|battery_module| {
+            .enumerate()
+            .map(|(module_index, battery_module)| {
                 let mut text: Vec<ratatui::text::Line> = vec![];
                 match battery_module.last_seen {
                     None => {
                         text.push(ratatui::text::Line::from(vec![
                         ratatui::text::Span::styled(
-                            format!("Module absent"),
+                            format!("Module {module_index} absent"),
                             ratatui::style::Style::default().fg(ratatui::style::Color::DarkGray),
                         )
                     ]));
                     }
                     Some(_) => {
+                        let soc = battery_module.soc.get_or_default();
+                        let hv_status = battery_module.hv_status.get_or_default();
+                        let adc2 = battery_module.adc2.get_or_default();
+                        let temperatures_a = battery_module.temperatures_a.get_or_default();
+                        let temperatures_b = battery_module.temperatures_b.get_or_default();
+                        let charge_limit = battery_module.charge_limit.get_or_default();
+                        let balancing = battery_module.balancing.get_or_default();
+
                         text.push(
                             ratatui::text::Line::from(vec![ratatui::text::Span::styled(
                             format!(
-                                "Serial {}",
-                                battery_module.serial_number,
+                                "Module {module_index} serial {}",
+                                battery_module.serial_number.get_or_default(),
                             ),
                             ratatui::style::Style::default().fg(ratatui::style::Color::Black),
                         )]));
@@ -1304,16 +2077,29 @@ impl ratatui::widgets::Widget for &App {
                         text.push(
                             ratatui::text::Line::from(vec![ratatui::text::Span::styled(
                             format!(
-                                "    SOC:{:5.1}% {:.3}V {:8.3}A SoH:{:5.1}% ({:.3}V {:.3}V {:.3}V)",
-                                battery_module.soc.soc,
-                                battery_module.hv_status.voltage,
-                                battery_module.hv_status.current,
-                                battery_module.soc.soh,
-                                battery_module.adc2.rail_5v,
-                                battery_module.adc2.rail_12v,
-                                battery_module.adc2.rail_3v3,
+                                "    SOC:{:5.1}% {:.3}V {:8.3}A SoH:{:5.1}% ",
+                                soc.soc,
+                                hv_status.voltage,
+                                hv_status.current,
+                                soc.soh,
                             ),
                             ratatui::style::Style::default().fg(ratatui::style::Color::Black),
+                        ),
+                        ratatui::text::Span::styled(
+                            format!(
+                                "({:.3}V {:.3}V {:.3}V)",
+                                adc2.rail_5v,
+                                adc2.rail_12v,
+                                adc2.rail_3v3,
+                            ),
+                            level_style(
+                                ratatui::style::Style::default().fg(ratatui::style::Color::Black),
+                                worst_level(alarms, module_index, &[
+                                    thresholds::Signal::Rail5v,
+                                    thresholds::Signal::Rail12v,
+                                    thresholds::Signal::Rail3v3,
+                                ]),
+                            ),
                         )]));
 
                         let mut battery_voltages: Vec<ratatui::text::Span> = vec![];
@@ -1324,14 +2110,13 @@ impl ratatui::widgets::Widget for &App {
 
                         for i in 0..14 {
                             let mut style = ratatui::style::Style::default();
-                            if battery_module.v_bricks[i] < 3.0 || battery_module.v_bricks[i] > 4.0 {
-                                style = style.bg(ratatui::style::Color::Red);
-                            }
-                            if battery_module.balancing[i] {
+                            let v_brick = battery_module.v_bricks[i].get_or_default();
+                            style = level_style(style, alarm_level(alarms, thresholds::Signal::BrickVoltage, Some(module_index), Some(i)));
+                            if balancing[i] {
                                 style = style.fg(ratatui::style::Color::Blue);
                             }
                             battery_voltages.push(ratatui::text::Span::styled(
-                                format!("{:5.3}", battery_module.v_bricks[i]),
+                                format!("{:5.3}", v_brick),
                                 style
                             ));
                             battery_voltages.push(ratatui::text::Span::styled(
@@ -1346,21 +2131,28 @@ impl ratatui::widgets::Widget for &App {
                             ratatui::text::Line::from(vec![ratatui::text::Span::styled(
                             format!(
                                 "    Temperatures: ambient:{:.1}°C module1:{:.1}°C module2:{:.1}°C FET:{:.1}°C Shunt:{:.1}°C",
-                                battery_module.temperatures_a.ambient,
-                                battery_module.temperatures_a.module1,
-                                battery_module.temperatures_a.module2,
-                                battery_module.temperatures_b.fet,
-                                battery_module.temperatures_b.shunt,
+                                temperatures_a.ambient,
+                                temperatures_a.module1,
+                                temperatures_a.module2,
+                                temperatures_b.fet,
+                                temperatures_b.shunt,
+                            ),
+                            level_style(
+                                ratatui::style::Style::default().fg(ratatui::style::Color::Black),
+                                worst_level(alarms, module_index, &[
+                                    thresholds::Signal::ModuleTemperature,
+                                    thresholds::Signal::FetTemperature,
+                                    thresholds::Signal::ShuntTemperature,
+                                ]),
                             ),
-                            ratatui::style::Style::default().fg(ratatui::style::Color::Black),
                         )]));
 
                         text.push(
                             ratatui::text::Line::from(vec![ratatui::text::Span::styled(
                             format!(
                                 "    Charge request: {:.3}V {:8.3}A",
-                                battery_module.charge_limit.voltage,
-                                battery_module.charge_limit.current,
+                                charge_limit.voltage,
+                                charge_limit.current,
                             ),
                             ratatui::style::Style::default().fg(ratatui::style::Color::Black),
                         )]));
@@ -1370,14 +2162,365 @@ impl ratatui::widgets::Widget for &App {
             })
             .collect();
 
-        // Create a List widget
-        ratatui::widgets::List::new(items)
+        let list = ratatui::widgets::List::new(items)
             .block(
                 ratatui::widgets::Block::default()
                     .title("Battery Modules")
                     .borders(ratatui::widgets::Borders::ALL),
             )
-            .highlight_style(ratatui::style::Style::default().bg(ratatui::style::Color::DarkGray))
-            .render(layout[1], buf);
+            .highlight_style(ratatui::style::Style::default().bg(ratatui::style::Color::DarkGray));
+        let mut list_state = ratatui::widgets::ListState::default()
+            .with_selected(Some(self.selected_module))
+            .with_offset(self.module_list_offset.get());
+        ratatui::widgets::StatefulWidget::render(list, layout[2], buf, &mut list_state);
+        self.module_list_offset.set(list_state.offset());
+    }
+
+    /// Everything we know about the selected module.
+    fn render_module_detail(
+        &self,
+        alarms: &[thresholds::Alarm],
+        area: ratatui::layout::Rect,
+        buf: &mut ratatui::buffer::Buffer,
+    ) {
+        let module_index = self.selected_module;
+        let battery_module = &self.battery_pack.modules[module_index];
+        let module_history = &self.module_history[module_index];
+        let now = self.now();
+
+        let text_style = ratatui::style::Style::default().fg(ratatui::style::Color::Black);
+
+        let layout = ratatui::layout::Layout::default()
+            .direction(ratatui::layout::Direction::Vertical)
+            .constraints(vec![
+                ratatui::layout::Constraint::Length(10),
+                ratatui::layout::Constraint::Min(17),
+                ratatui::layout::Constraint::Length(7),
+            ])
+            .split(area);
+
+        let mut text = vec![];
+        let status = match battery_module.last_seen {
+            None => String::from("absent"),
+            Some(last_seen) => format!(
+                "last heard from {} ago",
+                format_age(now.saturating_duration_since(last_seen))
+            ),
+        };
+        text.push(ratatui::text::Line::styled(
+            format!(
+                "Serial {}, {status}",
+                or_unknown(battery_module.serial_number.get(), |serial| serial
+                    .to_string()),
+            ),
+            text_style,
+        ));
+        text.push(ratatui::text::Line::styled(
+            format!(
+                "Firmware {}, bootloader {}, hardware {}",
+                or_unknown(battery_module.sw_version.get(), |v| v.app_version()),
+                or_unknown(battery_module.sw_version.get(), |v| v.bootloader_version()),
+                or_unknown(battery_module.hardware_version.get(), |v| format!(
+                    "0x{v:08x}"
+                )),
+            ),
+            text_style,
+        ));
+        text.push(ratatui::text::Line::styled(
+            format!(
+                "{}, {}, capacity {}",
+                or_unknown(battery_module.hv_status.get(), |hv_status| format!(
+                    "{:.3}V {:.3}A",
+                    hv_status.voltage, hv_status.current
+                )),
+                or_unknown(battery_module.soc.get(), |soc| format!(
+                    "SOC {:.1}% SoH {:.1}%",
+                    soc.soc, soc.soh
+                )),
+                or_unknown(battery_module.capacity.get(), |capacity| format!(
+                    "{:.2}/{:.2}Ah",
+                    capacity.remaining_ah, capacity.full_charge_ah
+                )),
+            ),
+            text_style,
+        ));
+        text.push(ratatui::text::Line::from(vec![
+            ratatui::text::Span::styled(
+                format!(
+                    "ADC: {}, ",
+                    or_unknown(battery_module.adc1.get(), |adc1| format!(
+                        "pack {:.2}V common drain {:.2}V load {:.2}V",
+                        adc1.pack_voltage, adc1.common_drain_voltage, adc1.load_voltage
+                    )),
+                ),
+                text_style,
+            ),
+            ratatui::text::Span::styled(
+                or_unknown(battery_module.adc2.get(), |adc2| {
+                    format!(
+                        "5V {:.2}V 12V {:.2}V 3V3 {:.2}V",
+                        adc2.rail_5v, adc2.rail_12v, adc2.rail_3v3
+                    )
+                }),
+                level_style(
+                    text_style,
+                    worst_level(
+                        alarms,
+                        module_index,
+                        &[
+                            thresholds::Signal::Rail5v,
+                            thresholds::Signal::Rail12v,
+                            thresholds::Signal::Rail3v3,
+                        ],
+                    ),
+                ),
+            ),
+        ]));
+        text.push(ratatui::text::Line::from(vec![
+            ratatui::text::Span::styled("Temperatures: ", text_style),
+            ratatui::text::Span::styled(
+                or_unknown(battery_module.temperatures_a.get(), |t| {
+                    format!(
+                        "ambient {:.1}°C module1 {:.1}°C module2 {:.1}°C",
+                        t.ambient, t.module1, t.module2
+                    )
+                }),
+                level_style(
+                    text_style,
+                    worst_level(
+                        alarms,
+                        module_index,
+                        &[thresholds::Signal::ModuleTemperature],
+                    ),
+                ),
+            ),
+            ratatui::text::Span::styled(", ", text_style),
+            ratatui::text::Span::styled(
+                or_unknown(battery_module.temperatures_b.get(), |t| {
+                    format!("FET {:.1}°C shunt {:.1}°C", t.fet, t.shunt)
+                }),
+                level_style(
+                    text_style,
+                    worst_level(
+                        alarms,
+                        module_index,
+                        &[
+                            thresholds::Signal::FetTemperature,
+                            thresholds::Signal::ShuntTemperature,
+                        ],
+                    ),
+                ),
+            ),
+        ]));
+        let min_max = battery_module.min_max.fresh(now).unwrap_or_default();
+        let v_brick_min_max = battery_module.v_brick_min_max(now);
+        let t_module_min_max = battery_module.t_module_min_max(now);
+        let mut min_max_text = vec![ratatui::text::Span::styled(
+            "BMS min/max: bricks ",
+            text_style,
+        )];
+        let brick_extreme = |extreme: model::Extreme| match extreme.index(model::NUM_BRICKS) {
+            Some(brick) => BmsExtreme {
+                value: extreme.value,
+                location: format!("b{}", brick + 1),
+                ours_there: battery_module.v_bricks[brick].fresh(now),
+            },
+            None => BmsExtreme::unknown_location(extreme.value),
+        };
+        // We don't know how the BMS numbers the temperature sensors, so
+        // there's no location to check.
+        let temperature_extreme = |extreme: model::Extreme| BmsExtreme {
+            value: extreme.value,
+            location: String::new(),
+            ours_there: None,
+        };
+        min_max_text.extend(min_max_spans(
+            min_max.v_brick_min.map(brick_extreme),
+            v_brick_min_max.map(|(min, _)| (min.voltage, format!("b{}", min.brick + 1))),
+            model::MIN_MAX_VOLTAGE_TOLERANCE,
+            "V",
+            3,
+        ));
+        min_max_text.push(ratatui::text::Span::styled(" / ", text_style));
+        min_max_text.extend(min_max_spans(
+            min_max.v_brick_max.map(brick_extreme),
+            v_brick_min_max.map(|(_, max)| (max.voltage, format!("b{}", max.brick + 1))),
+            model::MIN_MAX_VOLTAGE_TOLERANCE,
+            "V",
+            3,
+        ));
+        min_max_text.push(ratatui::text::Span::styled(", temperatures ", text_style));
+        min_max_text.extend(min_max_spans(
+            min_max.t_module_min.map(temperature_extreme),
+            t_module_min_max.map(|(min, _)| (min.temperature, String::new())),
+            model::MIN_MAX_TEMPERATURE_TOLERANCE,
+            "°C",
+            1,
+        ));
+        min_max_text.push(ratatui::text::Span::styled(" / ", text_style));
+        min_max_text.extend(min_max_spans(
+            min_max.t_module_max.map(temperature_extreme),
+            t_module_min_max.map(|(_, max)| (max.temperature, String::new())),
+            model::MIN_MAX_TEMPERATURE_TOLERANCE,
+            "°C",
+            1,
+        ));
+        text.push(ratatui::text::Line::from(min_max_text));
+        text.push(ratatui::text::Line::styled(
+            format!(
+                "Charge limits: {}",
+                or_unknown(battery_module.charge_limit.get(), |limit| format!(
+                    "{:.3}V, {:.2}A inst, {:.2}A 10s, {:.2}A cont",
+                    limit.voltage, limit.current_inst, limit.current_10s, limit.current
+                )),
+            ),
+            text_style,
+        ));
+        text.push(ratatui::text::Line::styled(
+            format!(
+                "Discharge limits: {}",
+                or_unknown(battery_module.discharge_limit.get(), |limit| format!(
+                    "{:.3}V, {:.2}A inst, {:.2}A 10s, {:.2}A cont",
+                    limit.voltage, limit.current_inst, limit.current_10s, limit.current_cont
+                )),
+            ),
+            text_style,
+        ));
+        ratatui::widgets::Paragraph::new(text)
+            .block(
+                ratatui::widgets::Block::new()
+                    .title(format!("Module {module_index}"))
+                    .borders(ratatui::widgets::Borders::ALL)
+                    .padding(ratatui::widgets::block::Padding::ZERO),
+            )
+            .render(layout[0], buf);
+
+        let middle = ratatui::layout::Layout::default()
+            .direction(ratatui::layout::Direction::Horizontal)
+            .constraints(vec![
+                ratatui::layout::Constraint::Length(58),
+                ratatui::layout::Constraint::Min(0),
+            ])
+            .split(layout[1]);
+
+        let balancing = battery_module.balancing.get_or_default();
+        let mut bricks = vec![ratatui::text::Line::styled(
+            "Brick  Voltage    SOC  Capacity  Resistance  Balancing",
+            text_style.add_modifier(ratatui::style::Modifier::BOLD),
+        )];
+        for (brick_index, balancing) in balancing.iter().enumerate() {
+            let style = match balancing {
+                true => ratatui::style::Style::default().fg(ratatui::style::Color::Blue),
+                false => text_style,
+            };
+            let style = level_style(
+                style,
+                alarm_level(
+                    alarms,
+                    thresholds::Signal::BrickVoltage,
+                    Some(module_index),
+                    Some(brick_index),
+                ),
+            );
+            bricks.push(ratatui::text::Line::styled(
+                format!(
+                    "{:5}  {:>6}V {:>5}% {:>8}Ah {:>7}mOhm  {}",
+                    brick_index + 1,
+                    or_unknown(battery_module.v_bricks[brick_index].get(), |v| format!(
+                        "{v:.3}"
+                    )),
+                    or_unknown(battery_module.soc_bricks[brick_index].get(), |soc| format!(
+                        "{soc:.1}"
+                    )),
+                    or_unknown(battery_module.cap_bricks[brick_index].get(), |cap| format!(
+                        "{cap:.3}"
+                    )),
+                    or_unknown(battery_module.res_bricks[brick_index].get(), |res| format!(
+                        "{res:.2}"
+                    )),
+                    match balancing {
+                        true => "yes",
+                        false => "",
+                    },
+                ),
+                style,
+            ));
+        }
+        ratatui::widgets::Paragraph::new(bricks)
+            .block(
+                ratatui::widgets::Block::new()
+                    .title("Bricks")
+                    .borders(ratatui::widgets::Borders::ALL)
+                    .padding(ratatui::widgets::block::Padding::ZERO),
+            )
+            .render(middle[0], buf);
+
+        let mut faults = vec![];
+        for (fault_info, active_fault) in battery_module.faults.active() {
+            faults.push(ratatui::text::Line::styled(
+                format!(
+                    "{:8} {:32} first seen {} ago, last seen {} ago",
+                    fault_info.severity().name(),
+                    fault_info.name,
+                    format_age(now.saturating_duration_since(active_fault.first_seen)),
+                    format_age(now.saturating_duration_since(active_fault.last_seen)),
+                ),
+                severity_style(fault_info.severity()),
+            ));
+        }
+        for alarm in alarms
+            .iter()
+            .filter(|alarm| alarm.module == Some(module_index))
+        {
+            faults.push(ratatui::text::Line::styled(
+                format!("{:8} {alarm}", alarm.level.name()),
+                level_style(text_style, alarm.level),
+            ));
+        }
+        if faults.is_empty() {
+            faults.push(ratatui::text::Line::styled(
+                "No active faults or alarms",
+                ratatui::style::Style::default().fg(ratatui::style::Color::DarkGray),
+            ));
+        }
+        ratatui::widgets::Paragraph::new(faults)
+            .block(
+                ratatui::widgets::Block::new()
+                    .title("Faults")
+                    .borders(ratatui::widgets::Borders::ALL)
+                    .padding(ratatui::widgets::block::Padding::ZERO),
+            )
+            .render(middle[1], buf);
+
+        let history_block = ratatui::widgets::Block::new()
+            .title(format!(
+                "History (last {})",
+                format_age(history::MODULE_HISTORY_AGE)
+            ))
+            .borders(ratatui::widgets::Borders::ALL)
+            .padding(ratatui::widgets::block::Padding::ZERO);
+        let rows = ratatui::layout::Layout::default()
+            .direction(ratatui::layout::Direction::Vertical)
+            .constraints(vec![ratatui::layout::Constraint::Length(1); 5])
+            .split(history_block.inner(layout[2]));
+        history_block.render(layout[2], buf);
+
+        render_sparkline("Voltage", "V", &module_history.voltage, rows[0], buf);
+        render_sparkline("Current", "A", &module_history.current, rows[1], buf);
+        render_sparkline("SOC", "%", &module_history.soc, rows[2], buf);
+        render_sparkline(
+            "Max temp",
+            "°C",
+            &module_history.max_temperature,
+            rows[3],
+            buf,
+        );
+        render_sparkline(
+            "Brick spread",
+            "mV",
+            &module_history.brick_spread,
+            rows[4],
+            buf,
+        );
     }
 }
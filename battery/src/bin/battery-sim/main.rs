@@ -0,0 +1,117 @@
+use clap::Parser;
+
+use battery::abs_alliance_module_messages;
+use battery::transport;
+use battery::transport::Transport;
+
+mod pack;
+
+/// How often the simulation steps, and the fast frames go out.
+const TICK: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// The slow frames go out every this many ticks.
+const SLOW_FRAME_TICKS: u32 = 10;
+
+/// Simulate an ABS Alliance E48-2.0 battery pack on a CAN interface,
+/// usually a vcan.
+#[derive(clap::Parser, Debug)]
+#[command(version, about, long_about=None)]
+struct Args {
+    #[arg(long, short = 'c', default_value_t = String::from("vcan0"))]
+    can_interface: String,
+
+    /// Number of modules in the pack.
+    #[arg(long, short = 'm', default_value_t = 3)]
+    modules: usize,
+
+    /// Starting SOC of each module in percent, like `--soc 74,62,38`.
+    /// By default the modules are spread between 40% and 70%, so they
+    /// don't all connect at once.
+    #[arg(long, value_delimiter = ',')]
+    soc: Vec<f32>,
+
+    /// Current into the pack in Charge mode, shared by the connected
+    /// modules.
+    #[arg(long, default_value_t = 10.0)]
+    charge_amps: f32,
+
+    /// Current out of the pack in Drive mode, shared by the connected
+    /// modules.
+    #[arg(long, default_value_t = 20.0)]
+    drive_amps: f32,
+}
+
+async fn send_frames(
+    transport: &mut impl transport::Transport,
+    frames: Vec<transport::Frame>,
+) -> Result<(), eyre::Report> {
+    for (id, data) in frames {
+        transport.send(id, &data).await?;
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), eyre::Report> {
+    let args = Args::parse();
+    println!("config: {args:#?}");
+
+    if args.modules == 0 || args.modules > abs_alliance_module_messages::NUM_MODULES {
+        return Err(eyre::eyre!(
+            "--modules must be 1 to {}",
+            abs_alliance_module_messages::NUM_MODULES
+        ));
+    }
+    let socs: Vec<f32> = match args.soc.len() {
+        0 => (0..args.modules)
+            .map(|module_index| match args.modules {
+                1 => 0.55,
+                n => 0.70 - 0.30 * module_index as f32 / (n - 1) as f32,
+            })
+            .collect(),
+        n if n == args.modules => args.soc.iter().map(|soc| soc / 100.0).collect(),
+        n => return Err(eyre::eyre!("got {n} SOCs for {} modules", args.modules)),
+    };
+
+    let mut transport = transport::SocketCan::open(&args.can_interface)?;
+
+    let mut sim_pack = pack::SimPack::new(
+        &socs,
+        args.charge_amps,
+        args.drive_amps,
+        std::time::Instant::now(),
+    );
+
+    let mut interval = tokio::time::interval(TICK);
+    let mut ticks: u32 = 0;
+
+    loop {
+        tokio::select! {
+            maybe_frame = transport.recv() => {
+                match maybe_frame {
+                    Some(Ok((id, data))) => {
+                        if let Some(event) = sim_pack.handle_frame(id, &data, std::time::Instant::now()) {
+                            println!("{event}");
+                            if sim_pack.is_asleep() {
+                                println!("asleep, restart the simulator to wake the pack");
+                            }
+                        }
+                    }
+                    Some(Err(e)) => return Err(e),
+                    None => return Ok(()),
+                }
+            }
+
+            _ = interval.tick() => {
+                for event in sim_pack.step(TICK, std::time::Instant::now()) {
+                    println!("{event}");
+                }
+                send_frames(&mut transport, sim_pack.fast_frames()?).await?;
+                if ticks % SLOW_FRAME_TICKS == 0 {
+                    send_frames(&mut transport, sim_pack.slow_frames()?).await?;
+                }
+                ticks = ticks.wrapping_add(1);
+            }
+        }
+    }
+}
@@ -0,0 +1,472 @@
+// A simulated ABS Alliance E48-2.0 pack: modules that connect to the HV
+// bus in response to the host's mode requests the way the real ones do
+// (see the scope trace in the README), a crude electrical model, and
+// the CAN frames the real pack would send about it all.
+//
+// In Charge mode the lowest modules connect, in Drive mode the highest,
+// and a module joins once it's within `CONNECT_WINDOW` of the ones
+// already connected.  After a mode change all modules disconnect and
+// the new ones connect `CONNECT_DELAY` later.
+
+use battery::abs_alliance_can_messages;
+use battery::model;
+
+/// How long modules stay off the HV bus after a mode change.
+const CONNECT_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Modules whose voltage is within this much of the lowest (charging)
+/// or highest (driving) module connect.
+const CONNECT_WINDOW: f32 = 0.5;
+
+// Brick open circuit voltage, roughly linear in SOC over the range
+// seen in captures.
+const BRICK_EMPTY_VOLTS: f32 = 3.2;
+const BRICK_FULL_VOLTS: f32 = 3.8;
+
+const MODULE_CAPACITY_AH: f32 = 40.0;
+const MODULE_RESISTANCE_OHMS: f32 = 0.02;
+
+/// What the pack asks the charger for, per connected module.
+const CHARGE_VOLTS: f32 = 52.835;
+const MODULE_CHARGE_AMPS: f32 = 12.0;
+
+/// Bricks this far above the lowest brick in their module balance
+/// while charging.
+const BALANCE_THRESHOLD: f32 = 0.01;
+
+const AMBIENT_TEMPERATURE: f32 = 25.0;
+
+#[derive(Debug)]
+struct SimModule {
+    serial_number: u64,
+
+    /// 0.0 to 1.0.
+    soc: f32,
+
+    /// Each brick's SOC relative to the module's, so they're not all
+    /// the same.
+    brick_soc_offsets: [f32; model::NUM_BRICKS],
+
+    /// Positive is charging.
+    current: f32,
+
+    connected: bool,
+}
+
+impl SimModule {
+    fn new(module_index: usize, soc: f32) -> Self {
+        let mut brick_soc_offsets = [0.0; model::NUM_BRICKS];
+        for (brick_index, offset) in brick_soc_offsets.iter_mut().enumerate() {
+            // Deterministic scatter of about +/- 1%.
+            *offset = ((module_index * 7 + brick_index * 5) % 11) as f32 * 0.002 - 0.01;
+        }
+        SimModule {
+            serial_number: 2_400_000 + module_index as u64,
+            soc,
+            brick_soc_offsets,
+            current: 0.0,
+            connected: false,
+        }
+    }
+
+    fn brick_voltage(&self, brick_index: usize) -> f32 {
+        let soc = (self.soc + self.brick_soc_offsets[brick_index]).clamp(0.0, 1.0);
+        BRICK_EMPTY_VOLTS
+            + (BRICK_FULL_VOLTS - BRICK_EMPTY_VOLTS) * soc
+            + self.current * MODULE_RESISTANCE_OHMS / model::NUM_BRICKS as f32
+    }
+
+    fn brick_voltages(&self) -> [f32; model::NUM_BRICKS] {
+        std::array::from_fn(|brick_index| self.brick_voltage(brick_index))
+    }
+
+    fn voltage(&self) -> f32 {
+        self.brick_voltages().iter().sum()
+    }
+
+    fn open_circuit_voltage(&self) -> f32 {
+        model::NUM_BRICKS as f32
+            * (BRICK_EMPTY_VOLTS + (BRICK_FULL_VOLTS - BRICK_EMPTY_VOLTS) * self.soc)
+    }
+
+    fn is_full(&self) -> bool {
+        self.soc >= 1.0
+    }
+
+    fn is_empty(&self) -> bool {
+        self.soc <= 0.0
+    }
+
+    fn balancing(
+        &self,
+        mode: abs_alliance_can_messages::HostBatteryRequestHostStateRequest,
+    ) -> [bool; model::NUM_BRICKS] {
+        let charging = self.connected
+            && mode == abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Charge;
+        let brick_voltages = self.brick_voltages();
+        let lowest = brick_voltages.iter().copied().fold(f32::INFINITY, f32::min);
+        brick_voltages.map(|v| charging && v > lowest + BALANCE_THRESHOLD)
+    }
+}
+
+#[derive(Debug)]
+pub struct SimPack {
+    modules: Vec<SimModule>,
+    mode: abs_alliance_can_messages::HostBatteryRequestHostStateRequest,
+    mode_changed: std::time::Instant,
+    asleep: bool,
+
+    /// The current pushed into the pack by the (imaginary) charger in
+    /// Charge mode, and drawn from it by the load in Drive mode.
+    charge_amps: f32,
+    drive_amps: f32,
+
+    alive_counter: u8,
+}
+
+impl SimPack {
+    /// A pack with one module per entry in `socs` (0.0 to 1.0).
+    pub fn new(socs: &[f32], charge_amps: f32, drive_amps: f32, now: std::time::Instant) -> Self {
+        SimPack {
+            modules: socs
+                .iter()
+                .enumerate()
+                .map(|(module_index, soc)| SimModule::new(module_index, *soc))
+                .collect(),
+            mode: abs_alliance_can_messages::HostBatteryRequestHostStateRequest::None,
+            mode_changed: now,
+            asleep: false,
+            charge_amps,
+            drive_amps,
+            alive_counter: 0,
+        }
+    }
+
+    /// Once asleep the pack says nothing more, like the real one, until
+    /// the simulator is restarted.
+    pub fn is_asleep(&self) -> bool {
+        self.asleep
+    }
+
+    /// Handle a frame from the bus.  Only the host's mode requests
+    /// matter, everything else (including our own frames) is ignored.
+    /// Returns a description of the mode change, if any.
+    pub fn handle_frame(
+        &mut self,
+        id: embedded_can::Id,
+        data: &[u8],
+        now: std::time::Instant,
+    ) -> Option<String> {
+        let Ok(abs_alliance_can_messages::Messages::HostBatteryRequest(m)) =
+            abs_alliance_can_messages::Messages::from_can_message(id, data)
+        else {
+            return None;
+        };
+        let mode = m.host_state_request();
+        if self.asleep || mode == self.mode {
+            return None;
+        }
+
+        self.mode = mode;
+        self.mode_changed = now;
+        for module in &mut self.modules {
+            module.connected = false;
+            module.current = 0.0;
+        }
+        if mode == abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Sleep {
+            self.asleep = true;
+        }
+        Some(format!("mode: {mode:?}"))
+    }
+
+    /// Advance the simulation by `dt`, returning descriptions of the
+    /// modules that connected or disconnected.
+    pub fn step(&mut self, dt: std::time::Duration, now: std::time::Instant) -> Vec<String> {
+        let mut events = vec![];
+        if self.asleep {
+            return events;
+        }
+
+        // Decide who should be on the bus, using open circuit voltage so
+        // the connected modules' own current doesn't push the others
+        // away.
+        let settled = now.saturating_duration_since(self.mode_changed) >= CONNECT_DELAY;
+        let candidates = self.modules.iter().filter(|module| match self.mode {
+            abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Charge => {
+                !module.is_full()
+            }
+            abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Drive => {
+                !module.is_empty()
+            }
+            _ => false,
+        });
+        let voltages: Vec<f32> = candidates
+            .map(|module| module.open_circuit_voltage())
+            .collect();
+        let lowest = voltages.iter().copied().fold(f32::INFINITY, f32::min);
+        let highest = voltages.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+
+        for (module_index, module) in self.modules.iter_mut().enumerate() {
+            let v = module.open_circuit_voltage();
+            let connected = settled
+                && match self.mode {
+                    abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Charge => {
+                        !module.is_full() && v <= lowest + CONNECT_WINDOW
+                    }
+                    abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Drive => {
+                        !module.is_empty() && v >= highest - CONNECT_WINDOW
+                    }
+                    _ => false,
+                };
+            if connected != module.connected {
+                module.connected = connected;
+                events.push(format!(
+                    "module {module_index} {} at {:.2}V",
+                    match connected {
+                        true => "connected",
+                        false => "disconnected",
+                    },
+                    module.voltage()
+                ));
+            }
+        }
+
+        // Share the current between the connected modules.
+        let num_connected = self
+            .modules
+            .iter()
+            .filter(|module| module.connected)
+            .count();
+        let pack_current = match self.mode {
+            abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Charge => {
+                self.charge_amps
+            }
+            abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Drive => {
+                -self.drive_amps
+            }
+            _ => 0.0,
+        };
+        for module in &mut self.modules {
+            module.current = match module.connected {
+                true => pack_current / num_connected as f32,
+                false => 0.0,
+            };
+            module.soc = (module.soc
+                + module.current * dt.as_secs_f32() / 3600.0 / MODULE_CAPACITY_AH)
+                .clamp(0.0, 1.0);
+        }
+
+        events
+    }
+
+    /// The frames the pack sends ten times a second.
+    pub fn fast_frames(&mut self) -> Result<Vec<(embedded_can::Id, Vec<u8>)>, eyre::Report> {
+        let mut frames = vec![];
+        if self.asleep {
+            return Ok(frames);
+        }
+
+        for (module_index, module) in self.modules.iter().enumerate() {
+            let mut m = abs_alliance_can_messages::BattModHvStatus0::try_from(&[0u8; 8][..])?;
+            m.set_batt_v_mod_0(module.voltage())?;
+            m.set_batt_v_mod_sum_of_cells_0(module.voltage())?;
+            // Clamped to what the message can carry.
+            let current = module.current.clamp(-120.0, 60.0);
+            m.set_batt_i_mod_0(current)?;
+            m.set_batt_i_mod_filtered_0(current)?;
+            frames.push(module_frame(&m, module_index));
+        }
+
+        let connected: Vec<&SimModule> = self
+            .modules
+            .iter()
+            .filter(|module| module.connected)
+            .collect();
+        let bus_voltage = match connected.is_empty() {
+            true => 0.0,
+            false => {
+                connected.iter().map(|module| module.voltage()).sum::<f32>()
+                    / connected.len() as f32
+            }
+        };
+        let pack_current = connected
+            .iter()
+            .map(|module| module.current)
+            .sum::<f32>()
+            .clamp(-480.0, 240.0);
+        let mut m = abs_alliance_can_messages::BattPackHvStatus::try_from(&[0u8; 8][..])?;
+        m.set_batt_v_pack(bus_voltage)?;
+        m.set_batt_v_pack_load(bus_voltage)?;
+        m.set_batt_i_pack(pack_current)?;
+        m.set_batt_i_pack_filtered(pack_current)?;
+        frames.push(pack_frame(&m));
+
+        let num_modules = self.modules.len() as u8;
+        let mut m = abs_alliance_can_messages::BattPackStatus::try_from(&[0u8; 8][..])?;
+        m.set_batt_pack_state(self.pack_state().into())?;
+        m.set_batt_pack_num_mods_on_hv_bus(connected.len() as u8)?;
+        m.set_batt_pack_num_mods_configured(num_modules)?;
+        m.set_batt_pack_num_mods_on_network(num_modules)?;
+        m.set_batt_pack_num_mods_data_valid(num_modules)?;
+        m.set_batt_pack_mod_com_status_ok_m((1u16 << num_modules) - 1)?;
+        m.set_batt_pack_alive_cntr(self.alive_counter)?;
+        frames.push(pack_frame(&m));
+        self.alive_counter = (self.alive_counter + 1) % 16;
+
+        // Only ask for charge in Charge mode, the charger app stops
+        // charging when this goes quiet.
+        if self.mode == abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Charge {
+            let charge_current = (connected.len() as f32 * MODULE_CHARGE_AMPS).min(40.95);
+            let mut m = abs_alliance_can_messages::BattChargerControl::try_from(&[0u8; 6][..])?;
+            m.set_batt_charger_connected(true)?;
+            m.set_batt_charge_mode(1)?;
+            m.set_batt_charge_status(3)?;
+            m.set_batt_charge_enable(!connected.is_empty())?;
+            m.set_batt_charging_voltage(CHARGE_VOLTS)?;
+            m.set_batt_charging_current(charge_current)?;
+            frames.push(pack_frame(&m));
+        }
+
+        Ok(frames)
+    }
+
+    /// The frames the pack sends once a second.
+    pub fn slow_frames(&self) -> Result<Vec<(embedded_can::Id, Vec<u8>)>, eyre::Report> {
+        let mut frames = vec![];
+        if self.asleep {
+            return Ok(frames);
+        }
+
+        for (module_index, module) in self.modules.iter().enumerate() {
+            let mut m = abs_alliance_can_messages::BattDeviceInfo0::try_from(&[0u8; 8][..])?;
+            m.set_batt_serial_number_0(module.serial_number)?;
+            m.set_batt_expected_module_0(module_index as u8)?;
+            frames.push(module_frame(&m, module_index));
+
+            let mut m = abs_alliance_can_messages::BattModSoc0::try_from(&[0u8; 7][..])?;
+            m.set_batt_soc_mod_0(module.soc * 100.0)?;
+            m.set_batt_soc_limiting_0(module.soc * 100.0)?;
+            m.set_batt_sohc_mod_0(95.0)?;
+            frames.push(module_frame(&m, module_index));
+
+            let mut m = abs_alliance_can_messages::BattModChgLimits0::try_from(&[0u8; 8][..])?;
+            m.set_batt_v_mod_chg_limit_0(CHARGE_VOLTS)?;
+            m.set_batt_i_mod_chg_limit_cont_0(MODULE_CHARGE_AMPS)?;
+            m.set_batt_i_mod_chg_limit10s_0(MODULE_CHARGE_AMPS * 4.2)?;
+            m.set_batt_i_mod_chg_limit_inst_0(MODULE_CHARGE_AMPS * 5.4)?;
+            frames.push(module_frame(&m, module_index));
+
+            // Things warm up a little with current.
+            let heating = module.current.abs() * 0.1;
+            let mut m = abs_alliance_can_messages::BattModTemperaturesA0::try_from(&[0u8; 8][..])?;
+            m.set_batt_t_ambient_0(AMBIENT_TEMPERATURE)?;
+            m.set_batt_t_module1_0(AMBIENT_TEMPERATURE + heating)?;
+            m.set_batt_t_module2_0(AMBIENT_TEMPERATURE + heating * 0.8)?;
+            frames.push(module_frame(&m, module_index));
+
+            let mut m = abs_alliance_can_messages::BattModTemperaturesB0::try_from(&[0u8; 8][..])?;
+            m.set_batt_t_fet_0(AMBIENT_TEMPERATURE + heating * 2.0)?;
+            m.set_batt_t_shunt_0(AMBIENT_TEMPERATURE + heating * 1.5)?;
+            frames.push(module_frame(&m, module_index));
+
+            let v = module.brick_voltages();
+            let mut m =
+                abs_alliance_can_messages::BattDiagnosticVBricksA0::try_from(&[0u8; 8][..])?;
+            m.set_batt_v_brick01_0(v[0])?;
+            m.set_batt_v_brick02_0(v[1])?;
+            m.set_batt_v_brick03_0(v[2])?;
+            m.set_batt_v_brick04_0(v[3])?;
+            frames.push(module_frame(&m, module_index));
+
+            let mut m =
+                abs_alliance_can_messages::BattDiagnosticVBricksB0::try_from(&[0u8; 8][..])?;
+            m.set_batt_v_brick05_0(v[4])?;
+            m.set_batt_v_brick06_0(v[5])?;
+            m.set_batt_v_brick07_0(v[6])?;
+            m.set_batt_v_brick08_0(v[7])?;
+            frames.push(module_frame(&m, module_index));
+
+            let mut m =
+                abs_alliance_can_messages::BattDiagnosticVBricksC0::try_from(&[0u8; 8][..])?;
+            m.set_batt_v_brick09_0(v[8])?;
+            m.set_batt_v_brick10_0(v[9])?;
+            m.set_batt_v_brick11_0(v[10])?;
+            m.set_batt_v_brick12_0(v[11])?;
+            frames.push(module_frame(&m, module_index));
+
+            let mut m =
+                abs_alliance_can_messages::BattDiagnosticVBricksD0::try_from(&[0u8; 4][..])?;
+            m.set_batt_v_brick13_0(v[12])?;
+            m.set_batt_v_brick14_0(v[13])?;
+            frames.push(module_frame(&m, module_index));
+
+            let b = module.balancing(self.mode);
+            let mut m =
+                abs_alliance_can_messages::BattDiagnosticBalStatusBrick0::try_from(&[0u8; 2][..])?;
+            m.set_batt_bal_status_brick01_0(b[0])?;
+            m.set_batt_bal_status_brick02_0(b[1])?;
+            m.set_batt_bal_status_brick03_0(b[2])?;
+            m.set_batt_bal_status_brick04_0(b[3])?;
+            m.set_batt_bal_status_brick05_0(b[4])?;
+            m.set_batt_bal_status_brick06_0(b[5])?;
+            m.set_batt_bal_status_brick07_0(b[6])?;
+            m.set_batt_bal_status_brick08_0(b[7])?;
+            m.set_batt_bal_status_brick09_0(b[8])?;
+            m.set_batt_bal_status_brick10_0(b[9])?;
+            m.set_batt_bal_status_brick11_0(b[10])?;
+            m.set_batt_bal_status_brick12_0(b[11])?;
+            m.set_batt_bal_status_brick13_0(b[12])?;
+            m.set_batt_bal_status_brick14_0(b[13])?;
+            frames.push(module_frame(&m, module_index));
+        }
+
+        let soc = self.modules.iter().map(|module| module.soc).sum::<f32>()
+            / self.modules.len() as f32
+            * 100.0;
+        let mut m = abs_alliance_can_messages::BattPackSoc::try_from(&[0u8; 4][..])?;
+        m.set_batt_pack_soc(soc)?;
+        m.set_batt_pack_user_soc(soc)?;
+        frames.push(pack_frame(&m));
+
+        Ok(frames)
+    }
+
+    fn pack_state(&self) -> abs_alliance_can_messages::BattPackStatusBattPackState {
+        let connected = self.modules.iter().any(|module| module.connected);
+        match (self.mode, connected) {
+            (abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Charge, true) => {
+                abs_alliance_can_messages::BattPackStatusBattPackState::Charge
+            }
+            (abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Charge, false) => {
+                abs_alliance_can_messages::BattPackStatusBattPackState::SelectCharge
+            }
+            (abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Drive, true) => {
+                abs_alliance_can_messages::BattPackStatusBattPackState::Drive
+            }
+            (abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Drive, false) => {
+                abs_alliance_can_messages::BattPackStatusBattPackState::SelectDrive
+            }
+            _ => abs_alliance_can_messages::BattPackStatusBattPackState::StandbyReady,
+        }
+    }
+}
+
+fn pack_frame(msg: &impl embedded_can::Frame) -> (embedded_can::Id, Vec<u8>) {
+    (msg.id(), msg.data().to_vec())
+}
+
+// Every module's messages have the same layout as module 0's, at
+// module 0's id plus the module index, so we build module 0's message
+// and move it.
+fn module_frame(
+    msg: &impl embedded_can::Frame,
+    module_index: usize,
+) -> (embedded_can::Id, Vec<u8>) {
+    let id = match msg.id() {
+        embedded_can::Id::Standard(id) => u32::from(id.as_raw()),
+        embedded_can::Id::Extended(id) => id.as_raw(),
+    };
+    let id = embedded_can::ExtendedId::new(id + module_index as u32)
+        .expect("module message ids are well inside the extended id range");
+    (embedded_can::Id::Extended(id), msg.data().to_vec())
+}
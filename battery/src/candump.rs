@@ -0,0 +1,112 @@
+// Reading and writing CAN logs in the format written by `candump -l`
+// (and `candump -L`), one frame per line:
+//
+// (1712345678.123456) can0 502#0200
+// (1712345678.124001) can0 1CEBFF80#0102030405060708
+//
+// Extended ids are always written with 8 hex digits, standard ids with 3.
+
+#[derive(Clone, Debug)]
+pub struct CandumpFrame {
+    /// When the frame was captured, since the Unix epoch.
+    pub timestamp: std::time::Duration,
+    pub interface: String,
+    pub id: embedded_can::Id,
+    pub data: Vec<u8>,
+}
+
+pub fn parse_line(line: &str) -> Result<CandumpFrame, eyre::Report> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() != 3 {
+        return Err(eyre::eyre!("expected 3 fields, got {}", fields.len()));
+    }
+
+    let timestamp = fields[0]
+        .strip_prefix('(')
+        .and_then(|s| s.strip_suffix(')'))
+        .ok_or_else(|| eyre::eyre!("bad timestamp {}", fields[0]))?;
+    let (secs, frac) = timestamp
+        .split_once('.')
+        .ok_or_else(|| eyre::eyre!("bad timestamp {}", fields[0]))?;
+    if frac.is_empty() || frac.len() > 9 || !frac.chars().all(|c| c.is_ascii_digit()) {
+        return Err(eyre::eyre!("bad timestamp {}", fields[0]));
+    }
+    let nanos: u32 = format!("{frac:0<9}").parse()?;
+    let timestamp = std::time::Duration::new(secs.parse()?, nanos);
+
+    let (id, data) = fields[2]
+        .split_once('#')
+        .ok_or_else(|| eyre::eyre!("bad frame {}", fields[2]))?;
+    if data.starts_with('#') || data.starts_with('R') {
+        return Err(eyre::eyre!("CAN FD and remote frames are not supported"));
+    }
+
+    let raw_id = u32::from_str_radix(id, 16)?;
+    let id = match id.len() {
+        8 => embedded_can::Id::Extended(
+            embedded_can::ExtendedId::new(raw_id)
+                .ok_or_else(|| eyre::eyre!("invalid extended frame id {id}"))?,
+        ),
+        _ => embedded_can::Id::Standard(
+            u16::try_from(raw_id)
+                .ok()
+                .and_then(embedded_can::StandardId::new)
+                .ok_or_else(|| eyre::eyre!("invalid standard frame id {id}"))?,
+        ),
+    };
+
+    // The payload may have '.' separators between bytes, like cansend
+    // accepts.
+    let data: String = data.chars().filter(|c| *c != '.').collect();
+    if data.len() % 2 != 0 || data.len() > 16 || !data.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(eyre::eyre!("bad payload {data}"));
+    }
+    let data = (0..data.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&data[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()?;
+
+    Ok(CandumpFrame {
+        timestamp,
+        interface: String::from(fields[1]),
+        id,
+        data,
+    })
+}
+
+/// Format a frame as a candump log line, without the trailing newline.
+pub fn format_line(
+    timestamp: std::time::Duration,
+    interface: &str,
+    id: embedded_can::Id,
+    data: &[u8],
+) -> String {
+    let id = match id {
+        embedded_can::Id::Standard(id) => format!("{:03X}", id.as_raw()),
+        embedded_can::Id::Extended(id) => format!("{:08X}", id.as_raw()),
+    };
+    let data: String = data.iter().map(|byte| format!("{byte:02X}")).collect();
+    format!(
+        "({}.{:06}) {interface} {id}#{data}",
+        timestamp.as_secs(),
+        timestamp.subsec_micros()
+    )
+}
+
+/// Read all the frames from a candump log.  Blank lines are skipped,
+/// any other line we can't parse is an error.
+pub fn read_file(path: &std::path::Path) -> Result<Vec<CandumpFrame>, eyre::Report> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| eyre::eyre!("failed to read {}: {e}", path.display()))?;
+
+    let mut frames = vec![];
+    for (line_number, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let frame = parse_line(line)
+            .map_err(|e| eyre::eyre!("{}:{}: {e}", path.display(), line_number + 1))?;
+        frames.push(frame);
+    }
+    Ok(frames)
+}
@@ -0,0 +1,52 @@
+// The commands the headless battery controller takes on its control
+// socket (see `battery::control_socket`), and that the HTTP API and MQTT
+// pass to the app.
+//
+// Commands:
+//     mode <none|drive|charge|sleep>    Change the commanded pack mode.
+//     status                            Print the current pack telemetry.
+
+use battery::abs_alliance_can_messages;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Command {
+    SetMode(abs_alliance_can_messages::HostBatteryRequestHostStateRequest),
+    Status,
+
+    // These answer with JSON, for the HTTP API.
+    Pack,
+    Module(usize),
+    ChargeRequest,
+
+    // Prometheus text format, for the HTTP API.
+    Metrics,
+}
+
+/// A command from a control client, the HTTP API or MQTT, and where to
+/// send the answer.
+pub type Request = battery::control_socket::Request<Command>;
+
+/// Parse a mode name like "charge", case insensitive.
+pub fn parse_mode(
+    name: &str,
+) -> Option<abs_alliance_can_messages::HostBatteryRequestHostStateRequest> {
+    match name.to_ascii_lowercase().as_str() {
+        "none" => Some(abs_alliance_can_messages::HostBatteryRequestHostStateRequest::None),
+        "drive" => Some(abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Drive),
+        "charge" => Some(abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Charge),
+        "sleep" => Some(abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Sleep),
+        _ => None,
+    }
+}
+
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        ["mode", name] => match parse_mode(name) {
+            Some(mode) => Ok(Command::SetMode(mode)),
+            None => Err(format!("unknown mode {name:?}")),
+        },
+        ["status"] => Ok(Command::Status),
+        _ => Err(format!("unknown command {line:?}")),
+    }
+}
@@ -0,0 +1,92 @@
+// The line protocol the battery and charger apps take commands in, on a
+// Unix socket: one text command per line, each answered with one or more
+// lines, the last of which is "ok" or starts with "error:".
+//
+// $ echo "mode charge" | socat - UNIX-CONNECT:/run/battery/control.sock
+// ok
+//
+// Each app has its own commands.  It passes its parser to `listen()`,
+// and gets the parsed commands as `Request`s to answer.
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+/// A command from a control client, and where to send the answer.
+#[derive(Debug)]
+pub struct Request<C> {
+    pub command: C,
+    pub reply: tokio::sync::oneshot::Sender<Result<String, String>>,
+}
+
+/// Format an answer for the client: "ok" or "error: ...", after the
+/// answer's text if it has any.
+pub fn format_answer(answer: Result<String, String>) -> String {
+    match answer {
+        Ok(text) if text.is_empty() => String::from("ok\n"),
+        Ok(text) => format!("{text}\nok\n"),
+        Err(e) => format!("error: {e}\n"),
+    }
+}
+
+/// Listen on the Unix socket at `path`, replacing any stale socket left
+/// behind by a previous run.  Each line from a client is parsed with
+/// `parse`, and the requests from all clients go to `request_tx`.
+pub fn listen<C: Send + 'static>(
+    path: &std::path::Path,
+    parse: fn(&str) -> Result<C, String>,
+    request_tx: tokio::sync::mpsc::Sender<Request<C>>,
+) -> Result<(), eyre::Report> {
+    match std::fs::remove_file(path) {
+        Ok(()) => (),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+        Err(e) => return Err(eyre::eyre!("failed to remove {}: {e}", path.display())),
+    }
+    let listener = tokio::net::UnixListener::bind(path)
+        .map_err(|e| eyre::eyre!("failed to bind {}: {e}", path.display()))?;
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(handle_client(stream, parse, request_tx.clone()));
+                }
+                // Probably out of file descriptors, back off and
+                // try again.
+                Err(_) => tokio::time::sleep(std::time::Duration::from_millis(100)).await,
+            }
+        }
+    });
+
+    Ok(())
+}
+
+async fn handle_client<C>(
+    stream: tokio::net::UnixStream,
+    parse: fn(&str) -> Result<C, String>,
+    request_tx: tokio::sync::mpsc::Sender<Request<C>>,
+) -> Result<(), eyre::Report> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = tokio::io::BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let answer = match parse(&line) {
+            Err(e) => Err(e),
+            Ok(command) => {
+                let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+                request_tx
+                    .send(Request {
+                        command,
+                        reply: reply_tx,
+                    })
+                    .await
+                    .map_err(|_| eyre::eyre!("the app has gone away"))?;
+                reply_rx.await?
+            }
+        };
+        writer.write_all(format_answer(answer).as_bytes()).await?;
+    }
+
+    Ok(())
+}
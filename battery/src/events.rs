@@ -0,0 +1,133 @@
+// Turns changes in the battery pack model into log events for the
+// headless controller, one `event kind=... key=value` line each:
+//
+// event kind=module_appeared module=3 serial=1234567
+// event kind=fault_raised module=3 fault=BATT_vBrickUnderWar severity=Warning
+// event kind=alarm_raised alarm=brick_voltage module=3 brick=7 level=Critical value=2.984
+
+use battery::model;
+use battery::thresholds;
+
+#[derive(Debug, Default)]
+pub struct EventTracker {
+    present: [bool; model::NUM_MODULES],
+    faults: [Vec<&'static str>; model::NUM_MODULES],
+    pack_state: Option<String>,
+    charge_request: bool,
+    alarms: Vec<thresholds::Alarm>,
+}
+
+impl EventTracker {
+    /// Compare the pack and its alarms to what they looked like last
+    /// time, and describe what changed.
+    pub fn update(
+        &mut self,
+        battery_pack: &model::BatteryPack,
+        alarms: &[thresholds::Alarm],
+        now: std::time::Instant,
+    ) -> Vec<String> {
+        let mut events = vec![];
+
+        for (module_index, battery_module) in battery_pack.modules.iter().enumerate() {
+            let present = battery_module.is_present();
+            if present != self.present[module_index] {
+                self.present[module_index] = present;
+                match present {
+                    true => events.push(format!(
+                        "event kind=module_appeared module={module_index} serial={}",
+                        battery_module
+                            .serial_number
+                            .get()
+                            .map_or(String::from("unknown"), |serial| serial.to_string())
+                    )),
+                    false => events.push(format!("event kind=module_lost module={module_index}")),
+                }
+            }
+
+            let faults: Vec<&'static str> = battery_module
+                .faults
+                .active()
+                .map(|(fault_info, _)| fault_info.name)
+                .collect();
+            for (fault_info, _) in battery_module.faults.active() {
+                if !self.faults[module_index].contains(&fault_info.name) {
+                    events.push(format!(
+                        "event kind=fault_raised module={module_index} fault={} severity={}",
+                        fault_info.name,
+                        fault_info.severity().name()
+                    ));
+                }
+            }
+            for name in &self.faults[module_index] {
+                if !faults.contains(name) {
+                    events.push(format!(
+                        "event kind=fault_cleared module={module_index} fault={name}"
+                    ));
+                }
+            }
+            self.faults[module_index] = faults;
+        }
+
+        let pack_state = battery_pack
+            .pack_status
+            .fresh(now)
+            .map(|pack_status| pack_status.state_name());
+        if pack_state != self.pack_state {
+            match &pack_state {
+                Some(state) => events.push(format!("event kind=pack_state state={state:?}")),
+                None => events.push(String::from("event kind=pack_status_lost")),
+            }
+            self.pack_state = pack_state;
+        }
+
+        let charge_request = battery_pack.charge_request.get();
+        if charge_request.is_some() != self.charge_request {
+            self.charge_request = charge_request.is_some();
+            match charge_request {
+                Some(charge_request) => events.push(format!(
+                    "event kind=charge_request voltage={:.3} current={:.3} enable={}",
+                    charge_request.voltage, charge_request.current, charge_request.enable
+                )),
+                None => events.push(String::from("event kind=charge_request_expired")),
+            }
+        }
+
+        // An alarm that changes level is raised again at the new level.
+        for alarm in alarms {
+            let previous = self.alarms.iter().find(|a| a.key() == alarm.key());
+            if previous.map(|a| a.level) != Some(alarm.level) {
+                events.push(format!(
+                    "event kind=alarm_raised alarm={}{} level={} value={:.3}",
+                    alarm.signal.name(),
+                    alarm_location(alarm),
+                    alarm.level.name(),
+                    alarm.value
+                ));
+            }
+        }
+        for previous in &self.alarms {
+            if !alarms.iter().any(|a| a.key() == previous.key()) {
+                events.push(format!(
+                    "event kind=alarm_cleared alarm={}{}",
+                    previous.signal.name(),
+                    alarm_location(previous)
+                ));
+            }
+        }
+        self.alarms = alarms.to_vec();
+
+        events
+    }
+}
+
+/// The module and brick keys for an alarm event, if it has them.
+fn alarm_location(alarm: &thresholds::Alarm) -> String {
+    let mut location = String::new();
+    if let Some(module) = alarm.module {
+        location.push_str(&format!(" module={module}"));
+    }
+    if let Some(brick) = alarm.brick {
+        location.push_str(&format!(" brick={}", brick + 1));
+    }
+    location
+}
@@ -0,0 +1,186 @@
+// Recent values of the pack and module signals, for the TUI's
+// sparklines and charts.  The app samples them once a tick; samples
+// older than the history's `max_age` fall off the front as new ones
+// come in.
+
+use crate::model;
+
+/// How much module history the module detail view keeps.
+pub const MODULE_HISTORY_AGE: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+
+/// How much pack history the charts keep, enough for their longest
+/// window.
+pub const PACK_HISTORY_AGE: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+#[derive(Clone, Debug)]
+pub struct History {
+    max_age: std::time::Duration,
+    samples: std::collections::VecDeque<(std::time::Instant, f32)>,
+}
+
+impl History {
+    pub fn new(max_age: std::time::Duration) -> Self {
+        History {
+            max_age,
+            samples: std::collections::VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, now: std::time::Instant, value: f32) {
+        self.samples.push_back((now, value));
+        while let Some((t, _)) = self.samples.front() {
+            if now.saturating_duration_since(*t) <= self.max_age {
+                break;
+            }
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn latest(&self) -> Option<f32> {
+        self.samples.back().map(|(_, value)| *value)
+    }
+
+    /// All the samples, oldest first.
+    pub fn samples(&self) -> impl DoubleEndedIterator<Item = (std::time::Instant, f32)> + '_ {
+        self.samples.iter().copied()
+    }
+
+    /// The samples taken at or after `start`, oldest first.
+    pub fn since(
+        &self,
+        start: std::time::Instant,
+    ) -> impl DoubleEndedIterator<Item = (std::time::Instant, f32)> + '_ {
+        let first = self.samples.partition_point(|(t, _)| *t < start);
+        self.samples.range(first..).copied()
+    }
+}
+
+/// The history of one module's vitals.
+#[derive(Clone, Debug)]
+pub struct ModuleHistory {
+    pub voltage: History,
+    pub current: History,
+    pub soc: History,
+    pub max_temperature: History,
+
+    /// Highest brick voltage minus lowest, in mV.
+    pub brick_spread: History,
+}
+
+impl Default for ModuleHistory {
+    fn default() -> Self {
+        ModuleHistory {
+            voltage: History::new(MODULE_HISTORY_AGE),
+            current: History::new(MODULE_HISTORY_AGE),
+            soc: History::new(MODULE_HISTORY_AGE),
+            max_temperature: History::new(MODULE_HISTORY_AGE),
+            brick_spread: History::new(MODULE_HISTORY_AGE),
+        }
+    }
+}
+
+impl ModuleHistory {
+    /// Add the module's current values.  Stale ones are skipped rather
+    /// than repeated.
+    pub fn sample(&mut self, battery_module: &model::BatteryModule, now: std::time::Instant) {
+        if let Some(hv_status) = battery_module.hv_status.fresh(now) {
+            self.voltage.push(now, hv_status.voltage);
+            self.current.push(now, hv_status.current);
+        }
+        if let Some(soc) = battery_module.soc.fresh(now) {
+            self.soc.push(now, soc.soc);
+        }
+        if let Some(temperatures_a) = battery_module.temperatures_a.fresh(now) {
+            self.max_temperature
+                .push(now, temperatures_a.module1.max(temperatures_a.module2));
+        }
+        if let Some((min, max)) = battery_module.v_brick_min_max(now) {
+            self.brick_spread
+                .push(now, (max.voltage - min.voltage) * 1000.0);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = ModuleHistory::default();
+    }
+}
+
+/// The history of the pack's vitals, for the charts.
+#[derive(Clone, Debug)]
+pub struct PackHistory {
+    pub voltage: History,
+    pub current: History,
+    pub soc: History,
+
+    /// The lowest and highest brick voltages in any module.
+    pub brick_min: History,
+    pub brick_max: History,
+
+    /// The hottest module temperature sensor in the pack.
+    pub max_temperature: History,
+
+    /// How many bricks are balancing, in all modules.
+    pub balancing: History,
+}
+
+impl Default for PackHistory {
+    fn default() -> Self {
+        PackHistory {
+            voltage: History::new(PACK_HISTORY_AGE),
+            current: History::new(PACK_HISTORY_AGE),
+            soc: History::new(PACK_HISTORY_AGE),
+            brick_min: History::new(PACK_HISTORY_AGE),
+            brick_max: History::new(PACK_HISTORY_AGE),
+            max_temperature: History::new(PACK_HISTORY_AGE),
+            balancing: History::new(PACK_HISTORY_AGE),
+        }
+    }
+}
+
+impl PackHistory {
+    /// Add the pack's current values.  Like `ModuleHistory::sample()`,
+    /// stale ones are skipped.
+    pub fn sample(&mut self, battery_pack: &model::BatteryPack, now: std::time::Instant) {
+        if let Some(pack_hv_status) = battery_pack.pack_hv_status.fresh(now) {
+            self.voltage.push(now, pack_hv_status.voltage);
+            self.current.push(now, pack_hv_status.current);
+        }
+        if let Some(pack_soc) = battery_pack.pack_soc.fresh(now) {
+            self.soc.push(now, pack_soc.soc);
+        }
+        if let Some((min, max)) = battery_pack.v_brick_min_max(now) {
+            self.brick_min.push(now, min.voltage);
+            self.brick_max.push(now, max.voltage);
+        }
+        let max_temperature = battery_pack
+            .modules
+            .iter()
+            .filter_map(|battery_module| battery_module.temperatures_a.fresh(now))
+            .map(|temperatures_a| temperatures_a.module1.max(temperatures_a.module2))
+            .reduce(f32::max);
+        if let Some(max_temperature) = max_temperature {
+            self.max_temperature.push(now, max_temperature);
+        }
+        let balancing = battery_pack
+            .modules
+            .iter()
+            .filter_map(|battery_module| battery_module.balancing.fresh(now))
+            .map(|balancing| balancing.iter().filter(|b| **b).count())
+            .reduce(|a, b| a + b);
+        if let Some(balancing) = balancing {
+            self.balancing.push(now, balancing as f32);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = PackHistory::default();
+    }
+}
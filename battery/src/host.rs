@@ -0,0 +1,49 @@
+// The pack's mode is commanded by the host in HOST_batteryRequest,
+// sent when the mode changes and then once a second as a keep-alive.
+//
+// Sleep is special: we send it once and then go quiet, because every
+// Sleep command wakes the pack up briefly to respond to it.
+
+use crate::abs_alliance_can_messages;
+use crate::model;
+use crate::transport;
+
+/// True if the app's once-a-second tick has anything to do: a
+/// keep-alive to send in any mode but Sleep, or telemetry in
+/// `battery_pack` to time out.  With neither the app can stop ticking
+/// until a frame or a command comes in.
+pub fn needs_tick(
+    battery_pack: &model::BatteryPack,
+    mode: abs_alliance_can_messages::HostBatteryRequestHostStateRequest,
+) -> bool {
+    battery_pack.has_live_data()
+        || mode != abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Sleep
+}
+
+/// Send the keep-alive for `mode`.  Nothing is sent in Sleep mode, we'd
+/// wake the pack up.
+pub async fn send_mode_command(
+    transport: &mut impl transport::Transport,
+    mode: abs_alliance_can_messages::HostBatteryRequestHostStateRequest,
+) -> Result<(), eyre::Report> {
+    if mode == abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Sleep {
+        return Ok(());
+    }
+    send_mode_command_raw(transport, mode).await
+}
+
+/// Send `mode`, even if it's Sleep.
+pub async fn send_mode_command_raw(
+    transport: &mut impl transport::Transport,
+    mode: abs_alliance_can_messages::HostBatteryRequestHostStateRequest,
+) -> Result<(), eyre::Report> {
+    let frame = abs_alliance_can_messages::HostBatteryRequest::new(
+        false,
+        false,
+        false,
+        false,
+        false,
+        mode.into(),
+    )?;
+    transport::send_message(transport, &frame).await
+}
@@ -1,2 +1,12 @@
 pub mod abs_alliance_can_messages;
+pub mod abs_alliance_module_messages;
+pub mod candump;
+pub mod control_socket;
+pub mod history;
+pub mod host;
+pub mod model;
+pub mod prometheus;
+pub mod recorder;
+pub mod thresholds;
+pub mod transport;
 pub mod tui;
@@ -1,6 +1,11 @@
 use clap::Parser;
 
+mod api;
 mod app;
+mod control;
+mod events;
+mod mqtt;
+mod replay;
 mod tui;
 
 /// Read telemetry from ABS Alliance E48-2.0 batteries.
@@ -9,6 +14,72 @@ mod tui;
 struct Args {
     #[arg(long, short = 'c', default_value_t = String::from("can0"))]
     can_interface: String,
+
+    /// Replay a candump log (as written by `candump -l`) instead of
+    /// talking to a CAN interface.
+    #[arg(long, short = 'r', conflicts_with = "record")]
+    replay: Option<std::path::PathBuf>,
+
+    /// Playback speed for --replay, 1.0 is the original timing.
+    #[arg(long, default_value_t = 1.0)]
+    speed: f64,
+
+    /// Record every received frame (in candump format) and the decoded
+    /// telemetry to files in this directory.
+    #[arg(long)]
+    record: Option<std::path::PathBuf>,
+
+    /// Start a new recording file when the current one reaches this
+    /// size.
+    #[arg(long, default_value_t = 64)]
+    record_file_mb: u64,
+
+    /// Keep at most this many recording files of each kind, deleting
+    /// the oldest.
+    #[arg(long, default_value_t = 100)]
+    record_max_files: usize,
+
+    /// Run without the TUI, for running under systemd.  Mode commands
+    /// come in on the control socket, events are logged to stdout, and
+    /// SIGTERM puts the pack to sleep and exits.
+    #[arg(long)]
+    headless: bool,
+
+    /// The Unix socket to take commands on in --headless mode.  The
+    /// default is in the directory systemd makes for
+    /// `RuntimeDirectory=battery`.
+    #[arg(long, default_value = "/run/battery/control.sock")]
+    control_socket: std::path::PathBuf,
+
+    /// Serve the HTTP/JSON API on this address, like 0.0.0.0:8080.
+    #[arg(long)]
+    http: Option<std::net::SocketAddr>,
+
+    /// Publish telemetry to this MQTT broker, like localhost or
+    /// mqtt.lan:1883, with Home Assistant discovery.
+    #[arg(long)]
+    mqtt: Option<String>,
+
+    #[arg(long, requires = "mqtt")]
+    mqtt_username: Option<String>,
+
+    /// Read the MQTT password from this file, so it doesn't show up in
+    /// `ps` or in the config we print at startup.
+    #[arg(long, requires = "mqtt_username")]
+    mqtt_password_file: Option<std::path::PathBuf>,
+
+    /// Prefix for our MQTT topics, and our Home Assistant node id.
+    #[arg(long, default_value = "battery")]
+    mqtt_prefix: String,
+
+    /// Home Assistant's MQTT discovery prefix.
+    #[arg(long, default_value = "homeassistant")]
+    mqtt_discovery_prefix: String,
+
+    /// Read the alarm thresholds from this TOML file instead of using
+    /// the built-in ones (see thresholds.toml).
+    #[arg(long)]
+    thresholds: Option<std::path::PathBuf>,
 }
 
 #[tokio::main]
@@ -16,10 +87,69 @@ async fn main() -> Result<(), eyre::Report> {
     let args = Args::parse();
     println!("config: {args:#?}");
 
-    let mut app = app::App::new(&args.can_interface)?;
+    let thresholds = match &args.thresholds {
+        Some(path) => battery::thresholds::Thresholds::load(path)?,
+        None => battery::thresholds::Thresholds::default(),
+    };
+
+    let mut app = match &args.replay {
+        Some(path) => app::App::replay(replay::Replay::open(path, args.speed)?, thresholds),
+        None => {
+            let recorder = match &args.record {
+                Some(dir) => Some(battery::recorder::Recorder::new(
+                    dir,
+                    "battery",
+                    &args.can_interface,
+                    args.record_file_mb * 1024 * 1024,
+                    args.record_max_files,
+                )?),
+                None => None,
+            };
+            let transport = battery::transport::SocketCan::open(&args.can_interface)?;
+            app::App::new(transport, recorder, thresholds)
+        }
+    };
+
+    // Commands from the control socket, the HTTP API and MQTT.
+    let (request_tx, request_rx) = tokio::sync::mpsc::channel(16);
+
+    if let Some(addr) = args.http {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(|e| eyre::eyre!("failed to bind {addr}: {e}"))?;
+        tokio::spawn(api::serve(listener, request_tx.clone(), args.headless));
+    }
+
+    if let Some(broker) = &args.mqtt {
+        let (host, port) = mqtt::parse_broker(broker)?;
+        let password = match &args.mqtt_password_file {
+            Some(path) => Some(mqtt::read_password(path)?),
+            None => None,
+        };
+        let config = mqtt::Config {
+            host,
+            port,
+            username: args.mqtt_username.clone(),
+            password,
+            prefix: args.mqtt_prefix.clone(),
+            discovery_prefix: args.mqtt_discovery_prefix.clone(),
+        };
+        tokio::spawn(mqtt::run(config, request_tx.clone(), args.headless));
+    }
+
+    if args.headless {
+        battery::control_socket::listen(&args.control_socket, control::parse_command, request_tx)?;
+        let result = app.run_headless(request_rx).await;
+
+        // Put the battery to sleep on exit.
+        let _ = app.sleep().await;
+        let _ = std::fs::remove_file(&args.control_socket);
+        return result;
+    }
+
     let terminal = tui::init()?;
 
-    let result = app.run(terminal).await;
+    let result = app.run(terminal, request_rx).await;
 
     // Put the battery to sleep on exit, then restore the terminal.
     let _ = app.sleep().await;
@@ -0,0 +1,1621 @@
+// A UI-independent model of an ABS Alliance battery pack, built up from
+// the CAN messages the pack and its modules send.
+//
+// Feed it frames with `BatteryPack::handle_frame()` (or already-decoded
+// messages with `BatteryPack::handle_message()`), and call
+// `BatteryPack::expire()` periodically to time out modules that have gone
+// quiet.  Every telemetry value is `Timestamped` so callers can tell how
+// fresh it is.
+
+use crate::abs_alliance_can_messages;
+use crate::abs_alliance_module_messages::{self, ModuleMessage};
+
+/// How long a telemetry value is good for before it's stale.
+pub const STALE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// How long a module can be quiet before we consider it absent.
+pub const MODULE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// How long the pack's charge request is good for.
+pub const CHARGE_REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+pub const NUM_MODULES: usize = 10;
+pub const NUM_BRICKS: usize = 14;
+
+/// A telemetry value and when we last received it.
+#[derive(Clone, Copy, Debug)]
+pub struct Timestamped<T> {
+    inner: Option<(T, std::time::Instant)>,
+}
+
+// Have to impl this by hand because derive(Default) would require
+// T: Default, and some of our values (like PackStatus) aren't.
+impl<T> Default for Timestamped<T> {
+    fn default() -> Self {
+        Timestamped { inner: None }
+    }
+}
+
+impl<T: Copy> Timestamped<T> {
+    pub fn set(&mut self, value: T, now: std::time::Instant) {
+        self.inner = Some((value, now));
+    }
+
+    pub fn clear(&mut self) {
+        self.inner = None;
+    }
+
+    /// The last value received, no matter how old.
+    pub fn get(&self) -> Option<T> {
+        self.inner.map(|(value, _)| value)
+    }
+
+    /// When the value was last received.
+    pub fn updated(&self) -> Option<std::time::Instant> {
+        self.inner.map(|(_, updated)| updated)
+    }
+
+    pub fn age(&self, now: std::time::Instant) -> Option<std::time::Duration> {
+        self.updated()
+            .map(|updated| now.saturating_duration_since(updated))
+    }
+
+    /// True if we've never received the value, or if it's older than
+    /// STALE_TIMEOUT.
+    pub fn is_stale(&self, now: std::time::Instant) -> bool {
+        match self.age(now) {
+            None => true,
+            Some(age) => age > STALE_TIMEOUT,
+        }
+    }
+
+    /// The value, if it's not stale.
+    pub fn fresh(&self, now: std::time::Instant) -> Option<T> {
+        match self.is_stale(now) {
+            true => None,
+            false => self.get(),
+        }
+    }
+}
+
+impl<T: Copy + Default> Timestamped<T> {
+    /// The last value received, or the default if we've never received
+    /// one.
+    pub fn get_or_default(&self) -> T {
+        self.get().unwrap_or_default()
+    }
+}
+
+// BATT_boardADC_1_5(
+//     BATT_boardADC_PACK_5: 45.59 V,
+//     BATT_boardADC_COMMON_DRAIN_5: 45.62 V,
+//     BATT_boardADC_LOAD_5: 45.57 V
+// )
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Adc1 {
+    pub pack_voltage: f32,
+    pub common_drain_voltage: f32,
+    pub load_voltage: f32,
+}
+
+// BATT_boardADC_2_5(
+//     BATT_boardADC_5V_5: 4.97 V,
+//     BATT_boardADC_12V_5: 12.47 V,
+//     BATT_boardADC_3V3_5: 3.3000000000000003 V
+// )
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Adc2 {
+    pub rail_5v: f32,
+    pub rail_12v: f32,
+    pub rail_3v3: f32,
+}
+
+// BATT_modHvStatus_0(
+//     BATT_iModFiltered_0: 0.015 A,
+//     BATT_iMod_0: 0.085 A,
+//     BATT_vMod_0: 47.85 V,
+//     BATT_vModSumOfCells_0: 47.83 V
+// )
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HvStatus {
+    pub voltage: f32,
+    pub current: f32,
+}
+
+// BATT_modChgLimits_0(
+//     BATT_iModChgLimit10s_0: 50.4 A,
+//     BATT_iModChgLimitCont_0: 12.0 A,
+//     BATT_iModChgLimitInst_0: 64.8 A,
+//     BATT_vModChgLimit_0: 52.835 V
+// )
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ChargeLimit {
+    pub voltage: f32,
+    /// The continuous limit.
+    pub current: f32,
+    pub current_inst: f32,
+    pub current_10s: f32,
+}
+
+// BATT_modDchLimits_0(
+//     BATT_iModDchLimit10s_0: -100.0 A,
+//     BATT_iModDchLimitCont_0: -60.0 A,
+//     BATT_iModDchLimitInst_0: -150.0 A,
+//     BATT_vModDchLimit_0: 42.0 V
+// )
+//
+// The current limits are negative, like discharge current.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DischargeLimit {
+    pub voltage: f32,
+    pub current_inst: f32,
+    pub current_10s: f32,
+    pub current_cont: f32,
+}
+
+// BATT_modCapacity_0(
+//     BATT_modFullChargeCap_Ah_0: 92.5 Ah,
+//     BATT_modRemainingCap_Ah_0: 57.3 Ah
+// )
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Capacity {
+    pub full_charge_ah: f32,
+    pub remaining_ah: f32,
+}
+
+// BATT_swVersion_0(
+//     BATT_appMajor_0: 78,
+//     BATT_appMinor_0: 0,
+//     BATT_appRevision_0: 7,
+//     BATT_bootloaderMajor_0: 2,
+//     BATT_bootloaderMinor_0: 1,
+//     BATT_bootloaderRevision_0: 0,
+//     BATT_bootFaultCnt_0: 0,
+//     BATT_bootTotalFaultCnt_0: 0
+// )
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SwVersion {
+    pub app: [u8; 3],
+    pub bootloader: [u8; 3],
+}
+
+impl SwVersion {
+    pub fn app_version(&self) -> String {
+        format!("{}.{:02}.{:03}", self.app[0], self.app[1], self.app[2])
+    }
+
+    pub fn bootloader_version(&self) -> String {
+        format!(
+            "{}.{}.{}",
+            self.bootloader[0], self.bootloader[1], self.bootloader[2]
+        )
+    }
+}
+
+// BATT_modSOC_5(
+//     BATT_sohcMod_5: 91.0 %,
+//     BATT_socMod_5: 8.1 %,
+//     BATT_socLimiting_5: 8.75 %,
+//     BATT_idSOCLimiting_5: 6
+// )
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SOC {
+    pub soc: f32,
+    pub soh: f32,
+}
+
+// BATT_modTemperaturesA_5(
+//     BATT_tAmbient_5: 25.900000000000002 deg C,
+//     BATT_tModule1_5: 23.8 deg C,
+//     BATT_tModule2_5: 25.8 deg C
+// )
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TemperaturesA {
+    pub ambient: f32,
+    pub module1: f32,
+    pub module2: f32,
+}
+
+// BATT_modTemperaturesB_0(
+//     BATT_tFET_0: 22.900000000000002 deg C,
+//     BATT_tShunt_0: 23.6 deg C
+// )
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TemperaturesB {
+    pub fet: f32,
+    pub shunt: f32,
+}
+
+/// One end of a min/max that the BMS works out: its value, and the ID
+/// of the brick, sensor or module where it is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Extreme {
+    pub id: u8,
+    pub value: f32,
+}
+
+impl Extreme {
+    // The min/max messages use the top of each signal's range for
+    // "signal not available".
+    const ID_SNA: u8 = 15;
+    const VOLTAGE_SNA: f32 = 8.19;
+    const TEMPERATURE_SNA: f32 = 204.7;
+
+    fn voltage(id: u8, value: f32) -> Option<Self> {
+        Extreme::new(id, value, Extreme::VOLTAGE_SNA)
+    }
+
+    fn temperature(id: u8, value: f32) -> Option<Self> {
+        Extreme::new(id, value, Extreme::TEMPERATURE_SNA)
+    }
+
+    // The BMS numbers modules and bricks in its min/max messages from
+    // 1, where our indexes start at 0.  That's from the DBC, which
+    // gives the module and brick IDs ranges of [0|10] and [0|14], up
+    // to the number of modules and bricks rather than one less; we
+    // haven't had a capture with these messages in it to check it
+    // against.  The TUI flags a BMS extreme that isn't where we see
+    // ours, so an off-by-one here shows up as soon as one comes in.
+    const ID_BASE: u8 = 1;
+
+    fn new(id: u8, value: f32, sna: f32) -> Option<Self> {
+        match id == Extreme::ID_SNA || value > sna - 0.01 {
+            true => None,
+            false => Some(Extreme { id, value }),
+        }
+    }
+
+    /// The module or brick the extreme is in, as one of our indexes,
+    /// out of `count` of them.  None if the ID is out of range.
+    pub fn index(&self, count: usize) -> Option<usize> {
+        self.id
+            .checked_sub(Extreme::ID_BASE)
+            .map(usize::from)
+            .filter(|index| *index < count)
+    }
+}
+
+/// How far the BMS's min/max values can be from the ones we work out
+/// from the bricks and sensors ourselves before we say they disagree.
+/// The BMS samples at slightly different times than the frames we get,
+/// so these allow for a few counts of the signals' resolution.
+pub const MIN_MAX_VOLTAGE_TOLERANCE: f32 = 0.010;
+pub const MIN_MAX_TEMPERATURE_TOLERANCE: f32 = 1.0;
+
+/// The lowest and highest brick voltage and module temperature, as the
+/// BMS reports them.  In a module's min/max the voltage IDs are bricks,
+/// and the temperature IDs are sensors that we don't know the numbering
+/// of.  In the pack's min/max all the IDs are modules.  `Extreme::index()`
+/// turns a module or brick ID into one of our indexes.  An extreme the
+/// BMS doesn't have is None.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MinMax {
+    pub v_brick_min: Option<Extreme>,
+    pub v_brick_max: Option<Extreme>,
+    pub t_module_min: Option<Extreme>,
+    pub t_module_max: Option<Extreme>,
+}
+
+// The BATT_eventMatrix_A_n and BATT_eventMatrix_B_n messages are bit
+// fields, one bit per fault.  The DBC suffixes the graded faults with
+// War (warning), Mod (moderate) and Sev (severe).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FaultSeverity {
+    Severe,
+    Moderate,
+    Warning,
+    Other,
+}
+
+impl FaultSeverity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FaultSeverity::Severe => "Severe",
+            FaultSeverity::Moderate => "Moderate",
+            FaultSeverity::Warning => "Warning",
+            FaultSeverity::Other => "Other",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventMatrix {
+    A,
+    B,
+}
+
+#[derive(Debug)]
+pub struct FaultInfo {
+    pub name: &'static str,
+    pub matrix: EventMatrix,
+    pub bit: u8,
+}
+
+impl FaultInfo {
+    pub fn severity(&self) -> FaultSeverity {
+        if self.name.ends_with("Sev") {
+            FaultSeverity::Severe
+        } else if self.name.ends_with("Mod") {
+            FaultSeverity::Moderate
+        } else if self.name.ends_with("War") {
+            FaultSeverity::Warning
+        } else {
+            FaultSeverity::Other
+        }
+    }
+}
+
+pub const NUM_FAULTS: usize = 74;
+
+pub static FAULTS: [FaultInfo; NUM_FAULTS] = [
+    FaultInfo {
+        name: "BATT_afeError",
+        matrix: EventMatrix::A,
+        bit: 0,
+    },
+    FaultInfo {
+        name: "BATT_bmsMemoryError",
+        matrix: EventMatrix::A,
+        bit: 1,
+    },
+    FaultInfo {
+        name: "BATT_bmsProcessorError",
+        matrix: EventMatrix::A,
+        bit: 2,
+    },
+    FaultInfo {
+        name: "BATT_brickOverCharge",
+        matrix: EventMatrix::A,
+        bit: 3,
+    },
+    FaultInfo {
+        name: "BATT_brickOverDischarge",
+        matrix: EventMatrix::A,
+        bit: 4,
+    },
+    FaultInfo {
+        name: "BATT_capLow",
+        matrix: EventMatrix::A,
+        bit: 5,
+    },
+    FaultInfo {
+        name: "BATT_fetDsgFailToClose",
+        matrix: EventMatrix::A,
+        bit: 6,
+    },
+    FaultInfo {
+        name: "BATT_fetDsgShorted",
+        matrix: EventMatrix::A,
+        bit: 7,
+    },
+    FaultInfo {
+        name: "BATT_packModIdFault",
+        matrix: EventMatrix::A,
+        bit: 8,
+    },
+    FaultInfo {
+        name: "BATT_hostMIA",
+        matrix: EventMatrix::A,
+        bit: 10,
+    },
+    FaultInfo {
+        name: "BATT_iUnderCharge",
+        matrix: EventMatrix::A,
+        bit: 11,
+    },
+    FaultInfo {
+        name: "BATT_inverterDriveMIA",
+        matrix: EventMatrix::A,
+        bit: 12,
+    },
+    FaultInfo {
+        name: "BATT_iOverCharge",
+        matrix: EventMatrix::A,
+        bit: 13,
+    },
+    FaultInfo {
+        name: "BATT_iOverDischarge",
+        matrix: EventMatrix::A,
+        bit: 14,
+    },
+    FaultInfo {
+        name: "BATT_fetOverTemp",
+        matrix: EventMatrix::A,
+        bit: 15,
+    },
+    FaultInfo {
+        name: "BATT_packModExitedFromBus",
+        matrix: EventMatrix::A,
+        bit: 16,
+    },
+    FaultInfo {
+        name: "BATT_afeFault",
+        matrix: EventMatrix::A,
+        bit: 17,
+    },
+    FaultInfo {
+        name: "BATT_packModImbalance",
+        matrix: EventMatrix::A,
+        bit: 18,
+    },
+    FaultInfo {
+        name: "BATT_packModMIA",
+        matrix: EventMatrix::A,
+        bit: 19,
+    },
+    FaultInfo {
+        name: "BATT_packModConnectFault",
+        matrix: EventMatrix::A,
+        bit: 20,
+    },
+    FaultInfo {
+        name: "BATT_packModSelectFault",
+        matrix: EventMatrix::A,
+        bit: 21,
+    },
+    FaultInfo {
+        name: "BATT_fetChgFailToClose",
+        matrix: EventMatrix::A,
+        bit: 22,
+    },
+    FaultInfo {
+        name: "BATT_fetChgShorted",
+        matrix: EventMatrix::A,
+        bit: 23,
+    },
+    FaultInfo {
+        name: "BATT_iOverHardware",
+        matrix: EventMatrix::A,
+        bit: 24,
+    },
+    FaultInfo {
+        name: "BATT_fetChgShortedAux",
+        matrix: EventMatrix::A,
+        bit: 25,
+    },
+    FaultInfo {
+        name: "BATT_packCellSoftShort",
+        matrix: EventMatrix::A,
+        bit: 26,
+    },
+    FaultInfo {
+        name: "BATT_powerPackInsufficient",
+        matrix: EventMatrix::A,
+        bit: 27,
+    },
+    FaultInfo {
+        name: "BATT_ramOverUtilError",
+        matrix: EventMatrix::A,
+        bit: 28,
+    },
+    FaultInfo {
+        name: "BATT_resPackHigh",
+        matrix: EventMatrix::A,
+        bit: 29,
+    },
+    FaultInfo {
+        name: "BATT_senseBrickTempError",
+        matrix: EventMatrix::A,
+        bit: 30,
+    },
+    FaultInfo {
+        name: "BATT_abnormalResetReason",
+        matrix: EventMatrix::A,
+        bit: 31,
+    },
+    FaultInfo {
+        name: "BATT_senseCurrentError",
+        matrix: EventMatrix::A,
+        bit: 32,
+    },
+    FaultInfo {
+        name: "BATT_senseLoadVoltError",
+        matrix: EventMatrix::A,
+        bit: 33,
+    },
+    FaultInfo {
+        name: "BATT_sensePackVoltError",
+        matrix: EventMatrix::A,
+        bit: 34,
+    },
+    FaultInfo {
+        name: "BATT_packFetChgWelded",
+        matrix: EventMatrix::A,
+        bit: 35,
+    },
+    FaultInfo {
+        name: "BATT_packFetDsgWelded",
+        matrix: EventMatrix::A,
+        bit: 36,
+    },
+    FaultInfo {
+        name: "BATT_tempAmbientOverMod",
+        matrix: EventMatrix::A,
+        bit: 37,
+    },
+    FaultInfo {
+        name: "BATT_tempAmbientOverSev",
+        matrix: EventMatrix::A,
+        bit: 38,
+    },
+    FaultInfo {
+        name: "BATT_prechargeFetFail",
+        matrix: EventMatrix::A,
+        bit: 39,
+    },
+    FaultInfo {
+        name: "BATT_tempAmbientOverWar",
+        matrix: EventMatrix::A,
+        bit: 40,
+    },
+    FaultInfo {
+        name: "BATT_taskLockupError",
+        matrix: EventMatrix::A,
+        bit: 41,
+    },
+    FaultInfo {
+        name: "BATT_tempBrickImbalance",
+        matrix: EventMatrix::A,
+        bit: 42,
+    },
+    FaultInfo {
+        name: "BATT_tempModuleOverMod",
+        matrix: EventMatrix::A,
+        bit: 43,
+    },
+    FaultInfo {
+        name: "BATT_tempModuleOverSev",
+        matrix: EventMatrix::A,
+        bit: 44,
+    },
+    FaultInfo {
+        name: "BATT_tempModuleOverWar",
+        matrix: EventMatrix::A,
+        bit: 45,
+    },
+    FaultInfo {
+        name: "BATT_tempModuleUnderMod",
+        matrix: EventMatrix::A,
+        bit: 46,
+    },
+    FaultInfo {
+        name: "BATT_tempModuleUnderSev",
+        matrix: EventMatrix::A,
+        bit: 47,
+    },
+    FaultInfo {
+        name: "BATT_tempModuleUnderWar",
+        matrix: EventMatrix::A,
+        bit: 48,
+    },
+    FaultInfo {
+        name: "BATT_prechargeTooSlow",
+        matrix: EventMatrix::A,
+        bit: 49,
+    },
+    FaultInfo {
+        name: "BATT_prechargeTooFast",
+        matrix: EventMatrix::A,
+        bit: 50,
+    },
+    FaultInfo {
+        name: "BATT_prechargeTimeout",
+        matrix: EventMatrix::A,
+        bit: 51,
+    },
+    FaultInfo {
+        name: "BATT_vBrickImbalance",
+        matrix: EventMatrix::A,
+        bit: 52,
+    },
+    FaultInfo {
+        name: "BATT_vBrickOverMod",
+        matrix: EventMatrix::A,
+        bit: 53,
+    },
+    FaultInfo {
+        name: "BATT_vBrickOverSev",
+        matrix: EventMatrix::A,
+        bit: 54,
+    },
+    FaultInfo {
+        name: "BATT_vBrickOverWar",
+        matrix: EventMatrix::A,
+        bit: 55,
+    },
+    FaultInfo {
+        name: "BATT_vBrickUnderMod",
+        matrix: EventMatrix::A,
+        bit: 56,
+    },
+    FaultInfo {
+        name: "BATT_vBrickUnderSev",
+        matrix: EventMatrix::A,
+        bit: 57,
+    },
+    FaultInfo {
+        name: "BATT_vBrickUnderWar",
+        matrix: EventMatrix::A,
+        bit: 58,
+    },
+    FaultInfo {
+        name: "BATT_chargerError",
+        matrix: EventMatrix::A,
+        bit: 59,
+    },
+    FaultInfo {
+        name: "BATT_chargerMIA",
+        matrix: EventMatrix::A,
+        bit: 60,
+    },
+    FaultInfo {
+        name: "BATT_loggerAlmostFull",
+        matrix: EventMatrix::A,
+        bit: 61,
+    },
+    FaultInfo {
+        name: "BATT_loggerFull",
+        matrix: EventMatrix::A,
+        bit: 62,
+    },
+    FaultInfo {
+        name: "BATT_fetDsgShortedAux",
+        matrix: EventMatrix::A,
+        bit: 63,
+    },
+    FaultInfo {
+        name: "BATT_packModCurrImbalance",
+        matrix: EventMatrix::B,
+        bit: 0,
+    },
+    FaultInfo {
+        name: "BATT_packModTempImbalance",
+        matrix: EventMatrix::B,
+        bit: 1,
+    },
+    FaultInfo {
+        name: "BATT_chargeNotNeeded",
+        matrix: EventMatrix::B,
+        bit: 2,
+    },
+    FaultInfo {
+        name: "BATT_cellOpenWire",
+        matrix: EventMatrix::B,
+        bit: 3,
+    },
+    FaultInfo {
+        name: "BATT_flashAppCRCError",
+        matrix: EventMatrix::B,
+        bit: 4,
+    },
+    FaultInfo {
+        name: "BATT_flashBootCRCError",
+        matrix: EventMatrix::B,
+        bit: 5,
+    },
+    FaultInfo {
+        name: "BATT_multiIgnLoFault",
+        matrix: EventMatrix::B,
+        bit: 6,
+    },
+    FaultInfo {
+        name: "BATT_packLevelArbitrationFault",
+        matrix: EventMatrix::B,
+        bit: 7,
+    },
+    FaultInfo {
+        name: "BATT_modThermistorOpenWire",
+        matrix: EventMatrix::B,
+        bit: 8,
+    },
+    FaultInfo {
+        name: "BATT_modThermistorShorted",
+        matrix: EventMatrix::B,
+        bit: 9,
+    },
+    FaultInfo {
+        name: "BATT_harnessImpedanceFault",
+        matrix: EventMatrix::B,
+        bit: 10,
+    },
+];
+
+#[derive(Clone, Copy, Debug)]
+pub struct ActiveFault {
+    pub first_seen: std::time::Instant,
+    pub last_seen: std::time::Instant,
+}
+
+// One slot per entry in FAULTS, `Some` while the module reports the
+// fault as active.
+#[derive(Clone, Copy, Debug)]
+pub struct FaultSet {
+    faults: [Option<ActiveFault>; NUM_FAULTS],
+}
+
+// Have to impl this by hand because Default is not implemented for
+// arrays this long.
+impl Default for FaultSet {
+    fn default() -> Self {
+        FaultSet {
+            faults: [None; NUM_FAULTS],
+        }
+    }
+}
+
+impl FaultSet {
+    fn update(&mut self, matrix: EventMatrix, payload: &[u8], now: std::time::Instant) {
+        let mut bits: u64 = 0;
+        for (i, byte) in payload.iter().take(8).enumerate() {
+            bits |= (*byte as u64) << (8 * i);
+        }
+
+        for (fault_info, fault) in FAULTS.iter().zip(self.faults.iter_mut()) {
+            if fault_info.matrix != matrix {
+                continue;
+            }
+            if bits & (1u64 << fault_info.bit) == 0 {
+                *fault = None;
+                continue;
+            }
+            match fault {
+                Some(active_fault) => active_fault.last_seen = now,
+                None => {
+                    *fault = Some(ActiveFault {
+                        first_seen: now,
+                        last_seen: now,
+                    })
+                }
+            }
+        }
+    }
+
+    pub fn active(&self) -> impl Iterator<Item = (&'static FaultInfo, ActiveFault)> + '_ {
+        FAULTS
+            .iter()
+            .zip(self.faults.iter())
+            .filter_map(|(fault_info, fault)| fault.map(|active_fault| (fault_info, active_fault)))
+    }
+}
+
+/// One brick's voltage, and which brick in its module it is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ModuleBrick {
+    pub brick: usize,
+    pub voltage: f32,
+}
+
+/// One module temperature sensor's reading, and which of the module's
+/// two sensors it is (0 for `module1`, 1 for `module2`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ModuleTemperature {
+    pub sensor: usize,
+    pub temperature: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BatteryModule {
+    pub serial_number: Timestamped<u64>,
+    pub sw_version: Timestamped<SwVersion>,
+    pub hardware_version: Timestamped<u32>,
+
+    pub adc1: Timestamped<Adc1>,
+    pub adc2: Timestamped<Adc2>,
+    pub hv_status: Timestamped<HvStatus>,
+    pub charge_limit: Timestamped<ChargeLimit>,
+    pub discharge_limit: Timestamped<DischargeLimit>,
+    pub capacity: Timestamped<Capacity>,
+    pub soc: Timestamped<SOC>,
+    pub temperatures_a: Timestamped<TemperaturesA>,
+    pub temperatures_b: Timestamped<TemperaturesB>,
+    pub v_bricks: [Timestamped<f32>; NUM_BRICKS],
+    pub balancing: Timestamped<[bool; NUM_BRICKS]>,
+
+    /// The module's min/max as the BMS works it out, to check ours by.
+    pub min_max: Timestamped<MinMax>,
+
+    /// Per-brick SOC (%), capacity (Ah) and resistance (mOhm), as the
+    /// BMS estimates them.
+    pub soc_bricks: [Timestamped<f32>; NUM_BRICKS],
+    pub cap_bricks: [Timestamped<f32>; NUM_BRICKS],
+    pub res_bricks: [Timestamped<f32>; NUM_BRICKS],
+
+    pub faults: FaultSet,
+
+    /// When we last heard anything from this module, None if the module
+    /// is absent.
+    pub last_seen: Option<std::time::Instant>,
+}
+
+impl BatteryModule {
+    pub fn is_present(&self) -> bool {
+        self.last_seen.is_some()
+    }
+
+    /// The lowest and highest fresh brick voltages, or None if we
+    /// have no fresh brick voltages.
+    pub fn v_brick_min_max(&self, now: std::time::Instant) -> Option<(ModuleBrick, ModuleBrick)> {
+        let mut min_max: Option<(ModuleBrick, ModuleBrick)> = None;
+        for (brick, v_brick) in self.v_bricks.iter().enumerate() {
+            let Some(voltage) = v_brick.fresh(now) else {
+                continue;
+            };
+            let this = ModuleBrick { brick, voltage };
+            min_max = match min_max {
+                None => Some((this, this)),
+                Some((min, max)) => Some((
+                    match voltage < min.voltage {
+                        true => this,
+                        false => min,
+                    },
+                    match voltage > max.voltage {
+                        true => this,
+                        false => max,
+                    },
+                )),
+            };
+        }
+        min_max
+    }
+
+    /// The lowest and highest of the module's two temperature sensors,
+    /// or None if they're stale.
+    pub fn t_module_min_max(
+        &self,
+        now: std::time::Instant,
+    ) -> Option<(ModuleTemperature, ModuleTemperature)> {
+        let temperatures_a = self.temperatures_a.fresh(now)?;
+        let module1 = ModuleTemperature {
+            sensor: 0,
+            temperature: temperatures_a.module1,
+        };
+        let module2 = ModuleTemperature {
+            sensor: 1,
+            temperature: temperatures_a.module2,
+        };
+        Some(match module2.temperature < module1.temperature {
+            true => (module2, module1),
+            false => (module1, module2),
+        })
+    }
+
+    /// Update the module from one of its own messages.  Any message
+    /// from the module counts as hearing from it, even the ones we
+    /// don't decode yet.
+    pub fn handle_message(&mut self, msg: &ModuleMessage, now: std::time::Instant) {
+        // Whichever module sent it, the message comes as module 0's copy
+        // (see `split_module_message()`), hence the `_0` on every signal.
+        match msg {
+            ModuleMessage::BattDeviceInfo(m) => {
+                self.serial_number.set(m.batt_serial_number_0_raw(), now);
+            }
+
+            ModuleMessage::BattSwVersion(m) => {
+                self.sw_version.set(
+                    SwVersion {
+                        app: [
+                            m.batt_app_major_0_raw(),
+                            m.batt_app_minor_0_raw(),
+                            m.batt_app_revision_0_raw(),
+                        ],
+                        bootloader: [
+                            m.batt_bootloader_major_0_raw(),
+                            m.batt_bootloader_minor_0_raw(),
+                            m.batt_bootloader_revision_0_raw(),
+                        ],
+                    },
+                    now,
+                );
+            }
+
+            ModuleMessage::BattHardwareVersion(m) => {
+                self.hardware_version
+                    .set(m.batt_hardware_version_0_raw(), now);
+            }
+
+            ModuleMessage::BattBoardAdc1(m) => {
+                self.adc1.set(
+                    Adc1 {
+                        pack_voltage: m.batt_board_adc_pack_0_raw(),
+                        common_drain_voltage: m.batt_board_adc_common_drain_0_raw(),
+                        load_voltage: m.batt_board_adc_load_0_raw(),
+                    },
+                    now,
+                );
+            }
+
+            ModuleMessage::BattBoardAdc2(m) => {
+                self.adc2.set(
+                    Adc2 {
+                        rail_5v: m.batt_board_adc_5v_0_raw(),
+                        rail_12v: m.batt_board_adc_12v_0_raw(),
+                        rail_3v3: m.batt_board_adc_3v3_0_raw(),
+                    },
+                    now,
+                );
+            }
+
+            ModuleMessage::BattModHvStatus(m) => {
+                self.hv_status.set(
+                    HvStatus {
+                        voltage: m.batt_v_mod_0_raw(),
+                        current: m.batt_i_mod_filtered_0_raw(),
+                    },
+                    now,
+                );
+            }
+
+            ModuleMessage::BattModChgLimits(m) => {
+                self.charge_limit.set(
+                    ChargeLimit {
+                        voltage: m.batt_v_mod_chg_limit_0_raw(),
+                        current: m.batt_i_mod_chg_limit_cont_0_raw(),
+                        current_inst: m.batt_i_mod_chg_limit_inst_0_raw(),
+                        current_10s: m.batt_i_mod_chg_limit10s_0_raw(),
+                    },
+                    now,
+                );
+            }
+
+            ModuleMessage::BattModDchLimits(m) => {
+                self.discharge_limit.set(
+                    DischargeLimit {
+                        voltage: m.batt_v_mod_dch_limit_0_raw(),
+                        current_inst: m.batt_i_mod_dch_limit_inst_0_raw(),
+                        current_10s: m.batt_i_mod_dch_limit10s_0_raw(),
+                        current_cont: m.batt_i_mod_dch_limit_cont_0_raw(),
+                    },
+                    now,
+                );
+            }
+
+            ModuleMessage::BattModCapacity(m) => {
+                self.capacity.set(
+                    Capacity {
+                        full_charge_ah: m.batt_mod_full_charge_cap_ah_0_raw(),
+                        remaining_ah: m.batt_mod_remaining_cap_ah_0_raw(),
+                    },
+                    now,
+                );
+            }
+
+            ModuleMessage::BattModSoc(m) => {
+                self.soc.set(
+                    SOC {
+                        soc: m.batt_soc_mod_0_raw(),
+                        soh: m.batt_sohc_mod_0_raw(),
+                    },
+                    now,
+                );
+            }
+
+            ModuleMessage::BattModTemperaturesA(m) => {
+                self.temperatures_a.set(
+                    TemperaturesA {
+                        ambient: m.batt_t_ambient_0_raw(),
+                        module1: m.batt_t_module1_0_raw(),
+                        module2: m.batt_t_module2_0_raw(),
+                    },
+                    now,
+                );
+            }
+
+            ModuleMessage::BattModTemperaturesB(m) => {
+                self.temperatures_b.set(
+                    TemperaturesB {
+                        fet: m.batt_t_fet_0_raw(),
+                        shunt: m.batt_t_shunt_0_raw(),
+                    },
+                    now,
+                );
+            }
+
+            ModuleMessage::BattModMinMax(m) => {
+                self.min_max.set(
+                    MinMax {
+                        v_brick_min: Extreme::voltage(
+                            m.batt_v_brick_min_id_0_raw(),
+                            m.batt_v_brick_min_0_raw(),
+                        ),
+                        v_brick_max: Extreme::voltage(
+                            m.batt_v_brick_max_id_0_raw(),
+                            m.batt_v_brick_max_0_raw(),
+                        ),
+                        t_module_min: Extreme::temperature(
+                            m.batt_t_module_min_id_0_raw(),
+                            m.batt_t_module_min_0_raw(),
+                        ),
+                        t_module_max: Extreme::temperature(
+                            m.batt_t_module_max_id_0_raw(),
+                            m.batt_t_module_max_0_raw(),
+                        ),
+                    },
+                    now,
+                );
+            }
+
+            ModuleMessage::BattDiagnosticVBricksA(m) => {
+                self.v_bricks[0].set(m.batt_v_brick01_0_raw(), now);
+                self.v_bricks[1].set(m.batt_v_brick02_0_raw(), now);
+                self.v_bricks[2].set(m.batt_v_brick03_0_raw(), now);
+                self.v_bricks[3].set(m.batt_v_brick04_0_raw(), now);
+            }
+
+            ModuleMessage::BattDiagnosticVBricksB(m) => {
+                self.v_bricks[4].set(m.batt_v_brick05_0_raw(), now);
+                self.v_bricks[5].set(m.batt_v_brick06_0_raw(), now);
+                self.v_bricks[6].set(m.batt_v_brick07_0_raw(), now);
+                self.v_bricks[7].set(m.batt_v_brick08_0_raw(), now);
+            }
+
+            ModuleMessage::BattDiagnosticVBricksC(m) => {
+                self.v_bricks[8].set(m.batt_v_brick09_0_raw(), now);
+                self.v_bricks[9].set(m.batt_v_brick10_0_raw(), now);
+                self.v_bricks[10].set(m.batt_v_brick11_0_raw(), now);
+                self.v_bricks[11].set(m.batt_v_brick12_0_raw(), now);
+            }
+
+            ModuleMessage::BattDiagnosticVBricksD(m) => {
+                self.v_bricks[12].set(m.batt_v_brick13_0_raw(), now);
+                self.v_bricks[13].set(m.batt_v_brick14_0_raw(), now);
+            }
+
+            ModuleMessage::BattDiagnosticSocBricksA(m) => {
+                self.soc_bricks[0].set(m.batt_soc_brick01_0_raw(), now);
+                self.soc_bricks[1].set(m.batt_soc_brick02_0_raw(), now);
+                self.soc_bricks[2].set(m.batt_soc_brick03_0_raw(), now);
+                self.soc_bricks[3].set(m.batt_soc_brick04_0_raw(), now);
+            }
+
+            ModuleMessage::BattDiagnosticSocBricksB(m) => {
+                self.soc_bricks[4].set(m.batt_soc_brick05_0_raw(), now);
+                self.soc_bricks[5].set(m.batt_soc_brick06_0_raw(), now);
+                self.soc_bricks[6].set(m.batt_soc_brick07_0_raw(), now);
+                self.soc_bricks[7].set(m.batt_soc_brick08_0_raw(), now);
+            }
+
+            ModuleMessage::BattDiagnosticSocBricksC(m) => {
+                self.soc_bricks[8].set(m.batt_soc_brick09_0_raw(), now);
+                self.soc_bricks[9].set(m.batt_soc_brick10_0_raw(), now);
+                self.soc_bricks[10].set(m.batt_soc_brick11_0_raw(), now);
+                self.soc_bricks[11].set(m.batt_soc_brick12_0_raw(), now);
+            }
+
+            ModuleMessage::BattDiagnosticSocBricksD(m) => {
+                self.soc_bricks[12].set(m.batt_soc_brick13_0_raw(), now);
+                self.soc_bricks[13].set(m.batt_soc_brick14_0_raw(), now);
+            }
+
+            ModuleMessage::BattDiagnosticCapBricksA(m) => {
+                self.cap_bricks[0].set(m.batt_cap_brick01_0_raw(), now);
+                self.cap_bricks[1].set(m.batt_cap_brick02_0_raw(), now);
+                self.cap_bricks[2].set(m.batt_cap_brick03_0_raw(), now);
+                self.cap_bricks[3].set(m.batt_cap_brick04_0_raw(), now);
+            }
+
+            ModuleMessage::BattDiagnosticCapBricksB(m) => {
+                self.cap_bricks[4].set(m.batt_cap_brick05_0_raw(), now);
+                self.cap_bricks[5].set(m.batt_cap_brick06_0_raw(), now);
+                self.cap_bricks[6].set(m.batt_cap_brick07_0_raw(), now);
+                self.cap_bricks[7].set(m.batt_cap_brick08_0_raw(), now);
+            }
+
+            ModuleMessage::BattDiagnosticCapBricksC(m) => {
+                self.cap_bricks[8].set(m.batt_cap_brick09_0_raw(), now);
+                self.cap_bricks[9].set(m.batt_cap_brick10_0_raw(), now);
+                self.cap_bricks[10].set(m.batt_cap_brick11_0_raw(), now);
+                self.cap_bricks[11].set(m.batt_cap_brick12_0_raw(), now);
+            }
+
+            ModuleMessage::BattDiagnosticCapBricksD(m) => {
+                self.cap_bricks[12].set(m.batt_cap_brick13_0_raw(), now);
+                self.cap_bricks[13].set(m.batt_cap_brick14_0_raw(), now);
+            }
+
+            ModuleMessage::BattDiagnosticResBricksA(m) => {
+                self.res_bricks[0].set(m.batt_res_brick01_0_raw(), now);
+                self.res_bricks[1].set(m.batt_res_brick02_0_raw(), now);
+                self.res_bricks[2].set(m.batt_res_brick03_0_raw(), now);
+                self.res_bricks[3].set(m.batt_res_brick04_0_raw(), now);
+            }
+
+            ModuleMessage::BattDiagnosticResBricksB(m) => {
+                self.res_bricks[4].set(m.batt_res_brick05_0_raw(), now);
+                self.res_bricks[5].set(m.batt_res_brick06_0_raw(), now);
+                self.res_bricks[6].set(m.batt_res_brick07_0_raw(), now);
+                self.res_bricks[7].set(m.batt_res_brick08_0_raw(), now);
+            }
+
+            ModuleMessage::BattDiagnosticResBricksC(m) => {
+                self.res_bricks[8].set(m.batt_res_brick09_0_raw(), now);
+                self.res_bricks[9].set(m.batt_res_brick10_0_raw(), now);
+                self.res_bricks[10].set(m.batt_res_brick11_0_raw(), now);
+                self.res_bricks[11].set(m.batt_res_brick12_0_raw(), now);
+            }
+
+            ModuleMessage::BattDiagnosticResBricksD(m) => {
+                self.res_bricks[12].set(m.batt_res_brick13_0_raw(), now);
+                self.res_bricks[13].set(m.batt_res_brick14_0_raw(), now);
+            }
+
+            ModuleMessage::BattDiagnosticBalStatusBrick(m) => {
+                self.balancing.set(
+                    [
+                        m.batt_bal_status_brick01_0_raw(),
+                        m.batt_bal_status_brick02_0_raw(),
+                        m.batt_bal_status_brick03_0_raw(),
+                        m.batt_bal_status_brick04_0_raw(),
+                        m.batt_bal_status_brick05_0_raw(),
+                        m.batt_bal_status_brick06_0_raw(),
+                        m.batt_bal_status_brick07_0_raw(),
+                        m.batt_bal_status_brick08_0_raw(),
+                        m.batt_bal_status_brick09_0_raw(),
+                        m.batt_bal_status_brick10_0_raw(),
+                        m.batt_bal_status_brick11_0_raw(),
+                        m.batt_bal_status_brick12_0_raw(),
+                        m.batt_bal_status_brick13_0_raw(),
+                        m.batt_bal_status_brick14_0_raw(),
+                    ],
+                    now,
+                );
+            }
+
+            ModuleMessage::BattEventMatrixA(m) => {
+                self.faults.update(EventMatrix::A, m.raw(), now);
+            }
+
+            ModuleMessage::BattEventMatrixB(m) => {
+                self.faults.update(EventMatrix::B, m.raw(), now);
+            }
+
+            _ => (), // ignore all other messages
+        }
+
+        self.last_seen = Some(now);
+    }
+}
+
+// BATT_chargerControl(
+//     BATT_chargeError: false,
+//     BATT_chargerMode: 0,
+//     BATT_chargerConnected: true,
+//     BATT_chargeMode: 1,
+//     BATT_chargeStatus: 3,
+//     BATT_chargeEnable: true,
+//     BATT_chargingVoltage: 52.835 V,
+//     BATT_chargingCurrent: 12.0 A
+// )
+#[derive(Clone, Copy, Debug)]
+pub struct ChargeRequest {
+    pub voltage: f32,
+    pub current: f32,
+    pub enable: bool,
+}
+
+// BATT_packDiagnosticConnect(
+//     BATT_packModChgEntryConnOK_M: 3,
+//     BATT_packModDrvEntryConnOK_M: 3,
+//     BATT_packModChgStandbyConnOK_M: 0,
+//     BATT_packModDrvStandbyConnOK_M: 0
+// )
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PackDiagnosticConnect {
+    pub num_modules_connected_for_charge: u16,
+    pub num_modules_connected_for_drive: u16,
+    pub num_modules_standby_for_charge: u16,
+    pub num_modules_standby_for_drive: u16,
+}
+
+// BATT_packHvStatus(
+//     BATT_vPackLoad: 49.83 V,
+//     BATT_iPack: 8.2,
+//     BATT_vPack: 48.96 V,
+//     BATT_iPackFiltered: 8.2 A
+// )
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PackHvStatus {
+    pub voltage: f32,
+    pub current: f32,
+}
+
+// BATT_packSOC(
+//     BATT_packUserSOC: 20.1 %,
+//     BATT_packSOC: 22.5 %
+// )
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PackSOC {
+    pub soc: f32,
+    pub user_soc: f32,
+}
+
+// BATT_packChgLimits(
+//     BATT_vBusMax: 58.8 V,
+//     BATT_iPackChgLimitInst: 194.4 A,
+//     BATT_iPackChgLimit10s: 151.2 A,
+//     BATT_iPackChgLimitCont: 36.0 A
+// )
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PackChgLimits {
+    pub current_inst: f32,
+    pub current_10s: f32,
+    pub current_cont: f32,
+    pub bus_voltage_max: f32,
+}
+
+// BATT_packDchLimits(
+//     BATT_vBusMin: 40.0 V,
+//     BATT_iPackDchLimitInst: -600.0 A,
+//     BATT_iPackDchLimit10s: -450.0 A,
+//     BATT_iPackDchLimitCont: -360.0 A
+// )
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PackDchLimits {
+    pub current_inst: f32,
+    pub current_10s: f32,
+    pub current_cont: f32,
+    pub bus_voltage_min: f32,
+}
+
+// BATT_packPwrAvailable(
+//     BATT_pwrPackDchLimit: 17.5 kW,
+//     BATT_pwrPackChgLimit: 9.5 kW
+// )
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PackPwrAvailable {
+    pub charge_kw: f32,
+    pub discharge_kw: f32,
+}
+
+// BATT_packStatus(
+//     BATT_packAliveCntr: 7,
+//     BATT_packSpeakerInstanceID: 0,
+//     BATT_packNumModsDataValid: 3,
+//     BATT_packNumModsOnNetwork: 3,
+//     BATT_packModComStatusOK_M: 7,
+//     BATT_fetPackDsgWelded: false,
+//     BATT_fetPackChgWelded: false,
+//     BATT_packNumModsConfigured: 3,
+//     BATT_packNumModsOnHvBus: 1,
+//     BATT_packState: ConnectCharge
+// )
+#[derive(Clone, Copy, Debug)]
+pub struct PackStatus {
+    pub state: abs_alliance_can_messages::BattPackStatusBattPackState,
+    pub num_modules_on_hv_bus: u8,
+    pub num_modules_configured: u8,
+    pub num_modules_on_network: u8,
+    pub module_com_status_ok: u16,
+    pub dsg_fets_welded: bool,
+    pub chg_fets_welded: bool,
+}
+
+impl PackStatus {
+    pub fn state_name(&self) -> String {
+        match self.state {
+            abs_alliance_can_messages::BattPackStatusBattPackState::StandbyNotReady => {
+                String::from("Standby (not ready)")
+            }
+            abs_alliance_can_messages::BattPackStatusBattPackState::StandbyReady => {
+                String::from("Standby (ready)")
+            }
+            abs_alliance_can_messages::BattPackStatusBattPackState::Drive => String::from("Drive"),
+            abs_alliance_can_messages::BattPackStatusBattPackState::Charge => {
+                String::from("Charge")
+            }
+            abs_alliance_can_messages::BattPackStatusBattPackState::Reserved => {
+                String::from("Reserved")
+            }
+            abs_alliance_can_messages::BattPackStatusBattPackState::ChargeStandbyConnect => {
+                String::from("Charge standby connect")
+            }
+            abs_alliance_can_messages::BattPackStatusBattPackState::SelectDrive => {
+                String::from("Selecting modules for drive")
+            }
+            abs_alliance_can_messages::BattPackStatusBattPackState::SelectCharge => {
+                String::from("Selecting modules for charge")
+            }
+            abs_alliance_can_messages::BattPackStatusBattPackState::ConnectDrive => {
+                String::from("Connecting for drive")
+            }
+            abs_alliance_can_messages::BattPackStatusBattPackState::ConnectCharge => {
+                String::from("Connecting for charge")
+            }
+            abs_alliance_can_messages::BattPackStatusBattPackState::Disconnect => {
+                String::from("Disconnecting")
+            }
+            abs_alliance_can_messages::BattPackStatusBattPackState::_Other(x) => {
+                format!("Unknown ({x})")
+            }
+        }
+    }
+
+    pub fn fets_welded(&self) -> bool {
+        self.dsg_fets_welded || self.chg_fets_welded
+    }
+}
+
+/// One brick's voltage, and where in the pack it is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PackBrick {
+    pub module: usize,
+    pub brick: usize,
+    pub voltage: f32,
+}
+
+/// One module temperature sensor's reading, and where in the pack it
+/// is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PackTemperature {
+    pub module: usize,
+    pub sensor: usize,
+    pub temperature: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BatteryPack {
+    pub modules: [BatteryModule; NUM_MODULES],
+    pub charge_request: Timestamped<ChargeRequest>,
+    pub pack_status: Timestamped<PackStatus>,
+    pub pack_diagnostic_connect: Timestamped<PackDiagnosticConnect>,
+    pub pack_hv_status: Timestamped<PackHvStatus>,
+    pub pack_soc: Timestamped<PackSOC>,
+    pub pack_chg_limits: Timestamped<PackChgLimits>,
+    pub pack_dch_limits: Timestamped<PackDchLimits>,
+    pub pack_pwr_available: Timestamped<PackPwrAvailable>,
+    pub pack_min_max: Timestamped<MinMax>,
+}
+
+impl BatteryPack {
+    /// Decode a CAN frame and update the pack from it.  Frames that
+    /// aren't in the DBC are an error, messages we don't use are
+    /// ignored.
+    pub fn handle_frame(
+        &mut self,
+        id: embedded_can::Id,
+        data: &[u8],
+        now: std::time::Instant,
+    ) -> Result<(), eyre::Report> {
+        let msg = abs_alliance_can_messages::Messages::from_can_message(id, data)?;
+        self.handle_message(&msg, now);
+        Ok(())
+    }
+
+    pub fn handle_message(
+        &mut self,
+        msg: &abs_alliance_can_messages::Messages,
+        now: std::time::Instant,
+    ) {
+        if let Some((module_index, module_msg)) =
+            abs_alliance_module_messages::split_module_message(msg)
+        {
+            if let Some(module) = self.modules.get_mut(module_index) {
+                module.handle_message(&module_msg, now);
+            }
+            return;
+        }
+
+        match msg {
+            abs_alliance_can_messages::Messages::BattChargerControl(m) => {
+                self.charge_request.set(
+                    ChargeRequest {
+                        voltage: m.batt_charging_voltage(),
+                        current: m.batt_charging_current(),
+                        enable: m.batt_charge_enable(),
+                    },
+                    now,
+                );
+            }
+
+            abs_alliance_can_messages::Messages::BattPackStatus(m) => {
+                self.pack_status.set(
+                    PackStatus {
+                        state: m.batt_pack_state(),
+                        num_modules_on_hv_bus: m.batt_pack_num_mods_on_hv_bus(),
+                        num_modules_configured: m.batt_pack_num_mods_configured(),
+                        num_modules_on_network: m.batt_pack_num_mods_on_network(),
+                        module_com_status_ok: m.batt_pack_mod_com_status_ok_m(),
+                        dsg_fets_welded: m.batt_fet_pack_dsg_welded(),
+                        chg_fets_welded: m.batt_fet_pack_chg_welded(),
+                    },
+                    now,
+                );
+            }
+
+            abs_alliance_can_messages::Messages::BattPackDiagnosticConnect(m) => {
+                self.pack_diagnostic_connect.set(
+                    PackDiagnosticConnect {
+                        num_modules_connected_for_charge: m.batt_pack_mod_chg_entry_conn_ok_m(),
+                        num_modules_connected_for_drive: m.batt_pack_mod_drv_entry_conn_ok_m(),
+                        num_modules_standby_for_charge: m.batt_pack_mod_chg_standby_conn_ok_m(),
+                        num_modules_standby_for_drive: m.batt_pack_mod_drv_standby_conn_ok_m(),
+                    },
+                    now,
+                );
+            }
+
+            abs_alliance_can_messages::Messages::BattPackHvStatus(m) => {
+                self.pack_hv_status.set(
+                    PackHvStatus {
+                        voltage: m.batt_v_pack_raw(),
+                        current: m.batt_i_pack_filtered_raw(),
+                    },
+                    now,
+                );
+            }
+
+            abs_alliance_can_messages::Messages::BattPackSoc(m) => {
+                self.pack_soc.set(
+                    PackSOC {
+                        soc: m.batt_pack_soc_raw(),
+                        user_soc: m.batt_pack_user_soc_raw(),
+                    },
+                    now,
+                );
+            }
+
+            abs_alliance_can_messages::Messages::BattPackChgLimits(m) => {
+                self.pack_chg_limits.set(
+                    PackChgLimits {
+                        current_inst: m.batt_i_pack_chg_limit_inst_raw(),
+                        current_10s: m.batt_i_pack_chg_limit10s_raw(),
+                        current_cont: m.batt_i_pack_chg_limit_cont_raw(),
+                        bus_voltage_max: m.batt_v_bus_max_raw(),
+                    },
+                    now,
+                );
+            }
+
+            abs_alliance_can_messages::Messages::BattPackDchLimits(m) => {
+                self.pack_dch_limits.set(
+                    PackDchLimits {
+                        current_inst: m.batt_i_pack_dch_limit_inst_raw(),
+                        current_10s: m.batt_i_pack_dch_limit10s_raw(),
+                        current_cont: m.batt_i_pack_dch_limit_cont_raw(),
+                        bus_voltage_min: m.batt_v_bus_min_raw(),
+                    },
+                    now,
+                );
+            }
+
+            abs_alliance_can_messages::Messages::BattPackPwrAvailable(m) => {
+                self.pack_pwr_available.set(
+                    PackPwrAvailable {
+                        charge_kw: m.batt_pwr_pack_chg_limit_raw(),
+                        discharge_kw: m.batt_pwr_pack_dch_limit_raw(),
+                    },
+                    now,
+                );
+            }
+
+            abs_alliance_can_messages::Messages::BattPackMinMax(m) => {
+                self.pack_min_max.set(
+                    MinMax {
+                        v_brick_min: Extreme::voltage(
+                            m.batt_v_pack_module_min_id_raw(),
+                            m.batt_v_pack_brick_min_raw(),
+                        ),
+                        v_brick_max: Extreme::voltage(
+                            m.batt_v_pack_module_max_id_raw(),
+                            m.batt_v_pack_brick_max_raw(),
+                        ),
+                        t_module_min: Extreme::temperature(
+                            m.batt_t_pack_module_min_id_raw(),
+                            m.batt_t_pack_module_min_raw(),
+                        ),
+                        t_module_max: Extreme::temperature(
+                            m.batt_t_pack_module_max_id_raw(),
+                            m.batt_t_pack_module_max_raw(),
+                        ),
+                    },
+                    now,
+                );
+            }
+
+            _ => (), // ignore all other messages
+        }
+    }
+
+    /// Time out modules and the charge request that we haven't heard
+    /// from recently.  Returns true if anything timed out.
+    pub fn expire(&mut self, now: std::time::Instant) -> bool {
+        let mut expired = false;
+
+        for battery_module in &mut self.modules {
+            if let Some(last_seen) = battery_module.last_seen {
+                if now.saturating_duration_since(last_seen) >= MODULE_TIMEOUT {
+                    battery_module.last_seen = None;
+                    expired = true;
+                }
+            }
+        }
+
+        if let Some(age) = self.charge_request.age(now) {
+            if age >= CHARGE_REQUEST_TIMEOUT {
+                self.charge_request.clear();
+                expired = true;
+            }
+        }
+
+        expired
+    }
+
+    /// The lowest and highest fresh brick voltages in the whole pack,
+    /// or None if we have no fresh brick voltages.
+    pub fn v_brick_min_max(&self, now: std::time::Instant) -> Option<(PackBrick, PackBrick)> {
+        let mut min_max: Option<(PackBrick, PackBrick)> = None;
+        for (module, battery_module) in self.modules.iter().enumerate() {
+            let Some((module_min, module_max)) = battery_module.v_brick_min_max(now) else {
+                continue;
+            };
+            let min = PackBrick {
+                module,
+                brick: module_min.brick,
+                voltage: module_min.voltage,
+            };
+            let max = PackBrick {
+                module,
+                brick: module_max.brick,
+                voltage: module_max.voltage,
+            };
+            min_max = match min_max {
+                None => Some((min, max)),
+                Some((pack_min, pack_max)) => Some((
+                    match min.voltage < pack_min.voltage {
+                        true => min,
+                        false => pack_min,
+                    },
+                    match max.voltage > pack_max.voltage {
+                        true => max,
+                        false => pack_max,
+                    },
+                )),
+            };
+        }
+        min_max
+    }
+
+    /// The lowest and highest fresh module temperature sensors in the
+    /// whole pack, or None if we have no fresh module temperatures.
+    pub fn t_module_min_max(
+        &self,
+        now: std::time::Instant,
+    ) -> Option<(PackTemperature, PackTemperature)> {
+        let mut min_max: Option<(PackTemperature, PackTemperature)> = None;
+        for (module, battery_module) in self.modules.iter().enumerate() {
+            let Some((module_min, module_max)) = battery_module.t_module_min_max(now) else {
+                continue;
+            };
+            let min = PackTemperature {
+                module,
+                sensor: module_min.sensor,
+                temperature: module_min.temperature,
+            };
+            let max = PackTemperature {
+                module,
+                sensor: module_max.sensor,
+                temperature: module_max.temperature,
+            };
+            min_max = match min_max {
+                None => Some((min, max)),
+                Some((pack_min, pack_max)) => Some((
+                    match min.temperature < pack_min.temperature {
+                        true => min,
+                        false => pack_min,
+                    },
+                    match max.temperature > pack_max.temperature {
+                        true => max,
+                        false => pack_max,
+                    },
+                )),
+            };
+        }
+        min_max
+    }
+
+    /// True if any modules are present or the pack has a live charge
+    /// request, ie if there's something left for `expire()` to time
+    /// out.
+    pub fn has_live_data(&self) -> bool {
+        self.modules.iter().any(|m| m.is_present()) || self.charge_request.get().is_some()
+    }
+}
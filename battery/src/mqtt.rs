@@ -0,0 +1,510 @@
+// Publishes pack and module telemetry to an MQTT broker for Home
+// Assistant, and takes mode commands from it.
+//
+//     <prefix>/status        "online" or "offline" (retained, and our last will)
+//     <prefix>/pack          pack telemetry and the commanded mode (retained)
+//     <prefix>/module/<n>    telemetry of module n (retained)
+//     <prefix>/mode/set      publish "None", "Drive", "Charge" or "Sleep" here
+//
+// The telemetry topics are flat JSON objects, with null for anything
+// stale or never received.  Home Assistant discovery configs go to
+// `<discovery prefix>/<component>/<prefix>/<object>/config`, and are
+// sent again whenever Home Assistant announces that it's back online.
+//
+// Like the HTTP API, this doesn't touch the pack model directly, it
+// sends requests to the app over the control channel.
+
+use battery::model;
+
+use crate::control;
+
+/// How often to publish telemetry.
+const PUBLISH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// How long to wait before reconnecting to the broker.
+const RECONNECT_DELAY: std::time::Duration = std::time::Duration::from_secs(5);
+
+const MODES: [&str; 4] = ["None", "Drive", "Charge", "Sleep"];
+
+/// What a Home Assistant sensor measures, which sets its unit and
+/// device class.
+#[derive(Clone, Copy, Debug)]
+enum SensorKind {
+    StateOfCharge,
+    Percent,
+    Voltage,
+    Current,
+    Temperature,
+    Count,
+    Text,
+}
+
+impl SensorKind {
+    fn unit(&self) -> Option<&'static str> {
+        match self {
+            SensorKind::StateOfCharge | SensorKind::Percent => Some("%"),
+            SensorKind::Voltage => Some("V"),
+            SensorKind::Current => Some("A"),
+            SensorKind::Temperature => Some("°C"),
+            SensorKind::Count | SensorKind::Text => None,
+        }
+    }
+
+    fn device_class(&self) -> Option<&'static str> {
+        match self {
+            SensorKind::StateOfCharge => Some("battery"),
+            SensorKind::Voltage => Some("voltage"),
+            SensorKind::Current => Some("current"),
+            SensorKind::Temperature => Some("temperature"),
+            SensorKind::Percent | SensorKind::Count | SensorKind::Text => None,
+        }
+    }
+}
+
+/// Sensors for the fields of `<prefix>/pack`: field, name, kind.
+const PACK_SENSORS: [(&str, &str, SensorKind); 6] = [
+    ("soc", "SOC", SensorKind::StateOfCharge),
+    ("user_soc", "User SOC", SensorKind::StateOfCharge),
+    ("voltage", "Voltage", SensorKind::Voltage),
+    ("current", "Current", SensorKind::Current),
+    ("modules_present", "Modules present", SensorKind::Count),
+    ("state", "State", SensorKind::Text),
+];
+
+/// Sensors for the fields of `<prefix>/module/<n>`.
+const MODULE_SENSORS: [(&str, &str, SensorKind); 11] = [
+    ("soc", "SOC", SensorKind::StateOfCharge),
+    ("soh", "SOH", SensorKind::Percent),
+    ("voltage", "Voltage", SensorKind::Voltage),
+    ("current", "Current", SensorKind::Current),
+    (
+        "temperature_ambient",
+        "Ambient temperature",
+        SensorKind::Temperature,
+    ),
+    (
+        "temperature_module1",
+        "Module temperature 1",
+        SensorKind::Temperature,
+    ),
+    (
+        "temperature_module2",
+        "Module temperature 2",
+        SensorKind::Temperature,
+    ),
+    (
+        "temperature_fet",
+        "FET temperature",
+        SensorKind::Temperature,
+    ),
+    (
+        "temperature_shunt",
+        "Shunt temperature",
+        SensorKind::Temperature,
+    ),
+    (
+        "brick_voltage_min",
+        "Min brick voltage",
+        SensorKind::Voltage,
+    ),
+    (
+        "brick_voltage_max",
+        "Max brick voltage",
+        SensorKind::Voltage,
+    ),
+];
+
+#[derive(Clone)]
+pub struct Config {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+
+    /// Our topics all start with this.  Also used as the Home Assistant
+    /// node id, so it should be unique per pack.
+    pub prefix: String,
+
+    pub discovery_prefix: String,
+}
+
+// Leaves the password out, so the config is safe to log.
+impl std::fmt::Debug for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Config")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .field("prefix", &self.prefix)
+            .field("discovery_prefix", &self.discovery_prefix)
+            .finish()
+    }
+}
+
+/// Read the broker password from the first line of `path`.
+pub fn read_password(path: &std::path::Path) -> Result<String, eyre::Report> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| eyre::eyre!("failed to read {}: {e}", path.display()))?;
+    Ok(String::from(text.lines().next().unwrap_or_default()))
+}
+
+/// Parse a broker address like "localhost" or "mqtt.lan:1883".
+pub fn parse_broker(broker: &str) -> Result<(String, u16), eyre::Report> {
+    match broker.rsplit_once(':') {
+        Some((host, port)) => match port.parse() {
+            Ok(port) => Ok((String::from(host), port)),
+            Err(_) => Err(eyre::eyre!("invalid MQTT broker port in {broker:?}")),
+        },
+        None => Ok((String::from(broker), 1883)),
+    }
+}
+
+/// Connect to the broker and publish until the app goes away.  Broker
+/// trouble is retried forever, and logged to stdout if `log_errors`
+/// (it would mess up the TUI).
+pub async fn run(
+    config: Config,
+    request_tx: tokio::sync::mpsc::Sender<control::Request>,
+    log_errors: bool,
+) {
+    loop {
+        let Err(e) = session(&config, &request_tx, log_errors).await else {
+            return;
+        };
+        if request_tx.is_closed() {
+            return;
+        }
+        if log_errors {
+            println!("event kind=mqtt_error error={:?}", e.to_string());
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+// One connection to the broker, from a fresh client.  Returns Ok when
+// the app goes away, and an error if we have to start over.
+async fn session(
+    config: &Config,
+    request_tx: &tokio::sync::mpsc::Sender<control::Request>,
+    log_errors: bool,
+) -> Result<(), eyre::Report> {
+    let mut options = rumqttc::MqttOptions::new(
+        format!("{}-{}", config.prefix, std::process::id()),
+        &config.host,
+        config.port,
+    );
+    options.set_keep_alive(std::time::Duration::from_secs(30));
+    options.set_last_will(rumqttc::LastWill::new(
+        format!("{}/status", config.prefix),
+        "offline",
+        rumqttc::QoS::AtLeastOnce,
+        true,
+    ));
+    if let Some(username) = &config.username {
+        options.set_credentials(username, config.password.as_deref().unwrap_or_default());
+    }
+    let (client, mut event_loop) = rumqttc::AsyncClient::new(options, 256);
+
+    // Nothing gets sent unless the event loop is being polled, so it
+    // gets its own task, and passes the packets we care about back to
+    // us.  It mustn't ever wait on us: we may be waiting for room in the
+    // client's request queue, which only the event loop empties.
+    let (incoming_tx, mut incoming_rx) = tokio::sync::mpsc::unbounded_channel();
+    let event_loop_task = tokio::spawn(async move {
+        loop {
+            match event_loop.poll().await {
+                Ok(rumqttc::Event::Incoming(
+                    packet @ (rumqttc::Packet::ConnAck(_) | rumqttc::Packet::Publish(_)),
+                )) => {
+                    if incoming_tx.send(packet).is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => (),
+                Err(e) => {
+                    if log_errors {
+                        println!("event kind=mqtt_error error={:?}", e.to_string());
+                    }
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                }
+            }
+        }
+    });
+
+    let mut publisher = Publisher {
+        client,
+        config: config.clone(),
+        request_tx: request_tx.clone(),
+        announced_modules: Default::default(),
+    };
+    let result = publisher.serve(&mut incoming_rx).await;
+    event_loop_task.abort();
+    result
+}
+
+struct Publisher {
+    client: rumqttc::AsyncClient,
+    config: Config,
+    request_tx: tokio::sync::mpsc::Sender<control::Request>,
+
+    /// The serial number each module's discovery config was sent with,
+    /// so it's sent again if a module is swapped.
+    announced_modules: [Option<String>; model::NUM_MODULES],
+}
+
+impl Publisher {
+    async fn serve(
+        &mut self,
+        incoming_rx: &mut tokio::sync::mpsc::UnboundedReceiver<rumqttc::Packet>,
+    ) -> Result<(), eyre::Report> {
+        let mut interval = tokio::time::interval(PUBLISH_INTERVAL);
+        loop {
+            tokio::select! {
+                packet = incoming_rx.recv() => match packet {
+                    Some(rumqttc::Packet::ConnAck(_)) => self.connected().await?,
+                    Some(rumqttc::Packet::Publish(publish)) => self.handle_publish(&publish).await?,
+                    Some(_) => (),
+                    None => return Ok(()),
+                },
+                _ = interval.tick() => self.publish_telemetry().await?,
+            }
+        }
+    }
+
+    // The broker forgets our subscriptions when we reconnect, and Home
+    // Assistant may have missed our discovery configs.
+    async fn connected(&mut self) -> Result<(), eyre::Report> {
+        self.client
+            .subscribe(self.mode_command_topic(), rumqttc::QoS::AtLeastOnce)
+            .await?;
+        self.client
+            .subscribe(self.ha_status_topic(), rumqttc::QoS::AtLeastOnce)
+            .await?;
+        self.publish(&format!("{}/status", self.config.prefix), "online")
+            .await?;
+        self.announce_pack().await?;
+        self.announced_modules = Default::default();
+        self.publish_telemetry().await
+    }
+
+    async fn handle_publish(&mut self, publish: &rumqttc::Publish) -> Result<(), eyre::Report> {
+        let payload = String::from_utf8_lossy(&publish.payload);
+        let payload = payload.trim();
+
+        if publish.topic == self.mode_command_topic() {
+            // Anything that isn't a mode is ignored.
+            if let Some(mode) = control::parse_mode(payload) {
+                self.ask(control::Command::SetMode(mode)).await?;
+                // Let Home Assistant see the new mode right away.
+                self.publish_telemetry().await?;
+            }
+        } else if publish.topic == self.ha_status_topic() && payload == "online" {
+            self.announce_pack().await?;
+            self.announced_modules = Default::default();
+            self.publish_telemetry().await?;
+        }
+        Ok(())
+    }
+
+    async fn publish_telemetry(&mut self) -> Result<(), eyre::Report> {
+        let pack: serde_json::Value =
+            serde_json::from_str(&self.ask(control::Command::Pack).await?)?;
+        let pack_state = serde_json::json!({
+            "mode": pack["mode"],
+            "state": fresh(&pack, "status")["state"],
+            "voltage": fresh(&pack, "hv_status")["voltage"],
+            "current": fresh(&pack, "hv_status")["current"],
+            "soc": fresh(&pack, "soc")["soc"],
+            "user_soc": fresh(&pack, "soc")["user_soc"],
+            "modules_present": pack["modules_present"].as_array().map_or(0, |modules| modules.len()),
+        });
+        self.publish(
+            &format!("{}/pack", self.config.prefix),
+            &pack_state.to_string(),
+        )
+        .await?;
+
+        for module_index in 0..model::NUM_MODULES {
+            let module: serde_json::Value =
+                serde_json::from_str(&self.ask(control::Command::Module(module_index)).await?)?;
+
+            // Leave out slots that have never had a module in them.
+            if module["last_seen_s_ago"].is_null() {
+                continue;
+            }
+
+            let serial = match &module["serial_number"]["value"] {
+                serde_json::Value::Number(serial) => serial.to_string(),
+                _ => String::new(),
+            };
+            if self.announced_modules[module_index].as_ref() != Some(&serial) {
+                self.announce_module(module_index, &serial).await?;
+                self.announced_modules[module_index] = Some(serial.clone());
+            }
+
+            let v_bricks: Vec<f64> = module["v_bricks"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter(|v_brick| v_brick["stale"] == false)
+                .filter_map(|v_brick| v_brick["value"].as_f64())
+                .collect();
+            let module_state = serde_json::json!({
+                "present": module["present"],
+                "serial": serial,
+                "voltage": fresh(&module, "hv_status")["voltage"],
+                "current": fresh(&module, "hv_status")["current"],
+                "soc": fresh(&module, "soc")["soc"],
+                "soh": fresh(&module, "soc")["soh"],
+                "temperature_ambient": fresh(&module, "temperatures_a")["ambient"],
+                "temperature_module1": fresh(&module, "temperatures_a")["module1"],
+                "temperature_module2": fresh(&module, "temperatures_a")["module2"],
+                "temperature_fet": fresh(&module, "temperatures_b")["fet"],
+                "temperature_shunt": fresh(&module, "temperatures_b")["shunt"],
+                "brick_voltage_min": v_bricks.iter().copied().reduce(f64::min),
+                "brick_voltage_max": v_bricks.iter().copied().reduce(f64::max),
+            });
+            self.publish(
+                &format!("{}/module/{module_index}", self.config.prefix),
+                &module_state.to_string(),
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn announce_pack(&self) -> Result<(), eyre::Report> {
+        let prefix = &self.config.prefix;
+        let device = serde_json::json!({
+            "identifiers": [prefix],
+            "name": "Battery pack",
+            "manufacturer": "American Battery Solutions",
+            "model": "Alliance E48-2.0",
+        });
+        let state_topic = format!("{prefix}/pack");
+
+        for sensor in &PACK_SENSORS {
+            self.announce_sensor(&device, &state_topic, "pack", sensor)
+                .await?;
+        }
+
+        let config = serde_json::json!({
+            "name": "Mode",
+            "unique_id": format!("{prefix}_mode"),
+            "device": device,
+            "availability_topic": format!("{prefix}/status"),
+            "state_topic": state_topic,
+            "value_template": "{{ value_json.mode }}",
+            "command_topic": self.mode_command_topic(),
+            "options": MODES,
+        });
+        self.announce("select", "mode", &config).await
+    }
+
+    async fn announce_module(&self, module_index: usize, serial: &str) -> Result<(), eyre::Report> {
+        let prefix = &self.config.prefix;
+        let mut device = serde_json::json!({
+            "identifiers": [format!("{prefix}_module{module_index}")],
+            "name": format!("Battery module {module_index}"),
+            "manufacturer": "American Battery Solutions",
+            "model": "Alliance E48-2.0",
+            "via_device": prefix,
+        });
+        if !serial.is_empty() {
+            device["serial_number"] = serde_json::json!(serial);
+        }
+        let state_topic = format!("{prefix}/module/{module_index}");
+
+        for sensor in &MODULE_SENSORS {
+            self.announce_sensor(
+                &device,
+                &state_topic,
+                &format!("module{module_index}"),
+                sensor,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    // Announce a sensor for one field of the JSON on `state_topic`, with
+    // an object id starting with `object_prefix`.
+    async fn announce_sensor(
+        &self,
+        device: &serde_json::Value,
+        state_topic: &str,
+        object_prefix: &str,
+        (field, name, kind): &(&str, &str, SensorKind),
+    ) -> Result<(), eyre::Report> {
+        let object_id = format!("{object_prefix}_{field}");
+        let mut config = serde_json::json!({
+            "name": name,
+            "unique_id": format!("{}_{object_id}", self.config.prefix),
+            "device": device,
+            "availability_topic": format!("{}/status", self.config.prefix),
+            "state_topic": state_topic,
+            "value_template": format!("{{{{ value_json.{field} }}}}"),
+        });
+        if let Some(unit) = kind.unit() {
+            config["unit_of_measurement"] = serde_json::json!(unit);
+        }
+        if let Some(device_class) = kind.device_class() {
+            config["device_class"] = serde_json::json!(device_class);
+        }
+        if !matches!(kind, SensorKind::Text) {
+            config["state_class"] = serde_json::json!("measurement");
+        }
+        self.announce("sensor", &object_id, &config).await
+    }
+
+    async fn announce(
+        &self,
+        component: &str,
+        object_id: &str,
+        config: &serde_json::Value,
+    ) -> Result<(), eyre::Report> {
+        let topic = format!(
+            "{}/{component}/{}/{object_id}/config",
+            self.config.discovery_prefix, self.config.prefix
+        );
+        self.publish(&topic, &config.to_string()).await
+    }
+
+    async fn publish(&self, topic: &str, payload: &str) -> Result<(), eyre::Report> {
+        self.client
+            .publish(topic, rumqttc::QoS::AtLeastOnce, true, payload)
+            .await?;
+        Ok(())
+    }
+
+    // Pass a command to the app and wait for its answer.
+    async fn ask(&self, command: control::Command) -> Result<String, eyre::Report> {
+        let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+        self.request_tx
+            .send(control::Request {
+                command,
+                reply: reply_tx,
+            })
+            .await?;
+        reply_rx.await?.map_err(|e| eyre::eyre!(e))
+    }
+
+    fn mode_command_topic(&self) -> String {
+        format!("{}/mode/set", self.config.prefix)
+    }
+
+    fn ha_status_topic(&self) -> String {
+        format!("{}/status", self.config.discovery_prefix)
+    }
+}
+
+// The value out of one of the API's {"value": ..., "stale": ...}
+// wrappers, or null if it's stale or was never received.
+fn fresh<'a>(json: &'a serde_json::Value, field: &str) -> &'a serde_json::Value {
+    match json[field]["stale"].as_bool() {
+        Some(false) => &json[field]["value"],
+        _ => &serde_json::Value::Null,
+    }
+}
@@ -0,0 +1,82 @@
+// Metrics in the Prometheus text exposition format, for the apps'
+// `/metrics` endpoints:
+//
+// # HELP battery_module_soc_percent Module state of charge.
+// # TYPE battery_module_soc_percent gauge
+// battery_module_soc_percent{module="3",serial="1234567"} 81.5
+//
+// Samples can be added in any order, they're grouped by metric when the
+// text is rendered, the way Prometheus wants them.
+
+/// The Content-Type to serve `Metrics::render()` with.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+#[derive(Debug)]
+struct Family {
+    name: String,
+    help: String,
+    samples: Vec<String>,
+}
+
+/// A set of gauges, rendered with `render()`.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    families: Vec<Family>,
+}
+
+impl Metrics {
+    /// Add one sample of the gauge `name`.
+    pub fn gauge(&mut self, name: &str, help: &str, labels: &[(&str, &str)], value: f64) {
+        let family = match self.families.iter().position(|family| family.name == name) {
+            Some(index) => &mut self.families[index],
+            None => {
+                self.families.push(Family {
+                    name: String::from(name),
+                    help: String::from(help),
+                    samples: vec![],
+                });
+                self.families.last_mut().unwrap()
+            }
+        };
+
+        let labels: Vec<String> = labels
+            .iter()
+            .map(|(label, value)| format!("{label}=\"{}\"", escape_label_value(value)))
+            .collect();
+        let value = match value {
+            v if v.is_nan() => String::from("NaN"),
+            v if v == f64::INFINITY => String::from("+Inf"),
+            v if v == f64::NEG_INFINITY => String::from("-Inf"),
+            v => v.to_string(),
+        };
+        family.samples.push(match labels.is_empty() {
+            true => format!("{name} {value}"),
+            false => format!("{name}{{{}}} {value}", labels.join(",")),
+        });
+    }
+
+    /// Add a gauge that's 1 for true and 0 for false.
+    pub fn flag(&mut self, name: &str, help: &str, labels: &[(&str, &str)], value: bool) {
+        self.gauge(name, help, labels, f64::from(u8::from(value)));
+    }
+
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        for family in &self.families {
+            text += &format!("# HELP {} {}\n", family.name, family.help);
+            text += &format!("# TYPE {} gauge\n", family.name);
+            for sample in &family.samples {
+                text += sample;
+                text += "\n";
+            }
+        }
+        text
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
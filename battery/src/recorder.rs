@@ -0,0 +1,208 @@
+// Recording CAN traffic and decoded telemetry to disk, for long
+// unattended runs.
+//
+// Every received frame goes to a candump log (readable by `canplayer`
+// and `battery --replay`), and the apps' decoded telemetry goes to a
+// parallel text log, one timestamped line per record:
+//
+// (1712345678.123456) pack state="Charge" voltage=47.850 current=1.250 soc=81.0
+//
+// Both logs are split into files of bounded size, and only the newest
+// files are kept.
+
+use crate::candump;
+
+/// A log split across files named `<prefix>-<UTC start time>.<extension>`,
+/// starting a new file once the current one reaches `max_bytes` and
+/// deleting the oldest once there are more than `max_files`.
+#[derive(Debug)]
+pub struct RotatingFile {
+    dir: std::path::PathBuf,
+    prefix: String,
+    extension: String,
+    max_bytes: u64,
+    max_files: usize,
+    file: Option<std::io::BufWriter<std::fs::File>>,
+    bytes_written: u64,
+}
+
+impl RotatingFile {
+    pub fn new(
+        dir: &std::path::Path,
+        prefix: &str,
+        extension: &str,
+        max_bytes: u64,
+        max_files: usize,
+    ) -> Result<Self, eyre::Report> {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| eyre::eyre!("failed to create {}: {e}", dir.display()))?;
+        Ok(RotatingFile {
+            dir: dir.to_path_buf(),
+            prefix: String::from(prefix),
+            extension: String::from(extension),
+            max_bytes,
+            max_files: max_files.max(1),
+            file: None,
+            bytes_written: 0,
+        })
+    }
+
+    pub fn write_line(&mut self, line: &str) -> Result<(), eyre::Report> {
+        use std::io::Write;
+
+        if self.bytes_written >= self.max_bytes {
+            self.flush()?;
+            self.file = None;
+        }
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => self.open()?,
+        };
+        writeln!(file, "{line}")?;
+        self.bytes_written += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), eyre::Report> {
+        use std::io::Write;
+
+        if let Some(file) = &mut self.file {
+            file.flush()?;
+        }
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<&mut std::io::BufWriter<std::fs::File>, eyre::Report> {
+        // If we rotate more than once in the same second, number the
+        // extra files so they still sort in order.
+        let start_time = utc_file_time(unix_time());
+        let mut serial = 0;
+        let (path, file) = loop {
+            let name = match serial {
+                0 => format!("{}-{start_time}.{}", self.prefix, self.extension),
+                _ => format!(
+                    "{}-{start_time}_{serial:04}.{}",
+                    self.prefix, self.extension
+                ),
+            };
+            let path = self.dir.join(name);
+            match std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(file) => break (path, file),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => serial += 1,
+                Err(e) => return Err(eyre::eyre!("failed to open {}: {e}", path.display())),
+            }
+        };
+        self.bytes_written = 0;
+        self.prune().map_err(|e| {
+            eyre::eyre!(
+                "failed to prune old recordings before {}: {e}",
+                path.display()
+            )
+        })?;
+        Ok(self.file.insert(std::io::BufWriter::new(file)))
+    }
+
+    // Delete our oldest files, keeping `max_files` including the one
+    // we're writing.  The file names sort by start time.
+    fn prune(&self) -> Result<(), eyre::Report> {
+        let name_prefix = format!("{}-", self.prefix);
+        let name_suffix = format!(".{}", self.extension);
+        let mut names: Vec<String> = std::fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| name.starts_with(&name_prefix) && name.ends_with(&name_suffix))
+            .collect();
+        names.sort();
+        let excess = names.len().saturating_sub(self.max_files);
+        for name in &names[..excess] {
+            std::fs::remove_file(self.dir.join(name))?;
+        }
+        Ok(())
+    }
+}
+
+/// The candump log and telemetry log of one app.
+#[derive(Debug)]
+pub struct Recorder {
+    interface: String,
+    candump: RotatingFile,
+    telemetry: RotatingFile,
+}
+
+impl Recorder {
+    /// Record into `dir`, with file names starting with `prefix` (the
+    /// app name).  `interface` is the CAN interface name to put in the
+    /// candump log.
+    pub fn new(
+        dir: &std::path::Path,
+        prefix: &str,
+        interface: &str,
+        max_bytes: u64,
+        max_files: usize,
+    ) -> Result<Self, eyre::Report> {
+        Ok(Recorder {
+            interface: String::from(interface),
+            candump: RotatingFile::new(dir, prefix, "candump", max_bytes, max_files)?,
+            telemetry: RotatingFile::new(dir, prefix, "telemetry", max_bytes, max_files)?,
+        })
+    }
+
+    pub fn record_frame(&mut self, id: embedded_can::Id, data: &[u8]) -> Result<(), eyre::Report> {
+        let line = candump::format_line(unix_time(), &self.interface, id, data);
+        self.candump.write_line(&line)
+    }
+
+    /// Record one line of telemetry, like `pack voltage=47.850`.  The
+    /// timestamp is added here.
+    pub fn record_telemetry(&mut self, line: &str) -> Result<(), eyre::Report> {
+        let timestamp = unix_time();
+        self.telemetry.write_line(&format!(
+            "({}.{:06}) {line}",
+            timestamp.as_secs(),
+            timestamp.subsec_micros()
+        ))
+    }
+
+    /// Push everything out to disk.  Call this periodically, so a crash
+    /// or power loss only loses the last little bit.
+    pub fn flush(&mut self) -> Result<(), eyre::Report> {
+        self.candump.flush()?;
+        self.telemetry.flush()
+    }
+}
+
+fn unix_time() -> std::time::Duration {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+// Formats a Unix time as "2024-04-05_123456" in UTC, like candump's
+// default log file names.
+fn utc_file_time(timestamp: std::time::Duration) -> String {
+    let secs = timestamp.as_secs();
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+
+    // Days since 1970-01-01 to a civil date, from Howard Hinnant's
+    // "chrono-Compatible Low-Level Date Algorithms".
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}_{:02}{:02}{:02}",
+        secs_of_day / 3600,
+        (secs_of_day % 3600) / 60,
+        secs_of_day % 60
+    )
+}
@@ -0,0 +1,154 @@
+// Plays a candump log into the battery monitor in place of a live CAN
+// interface.
+//
+// Frames come out with their original spacing, scaled by the playback
+// speed.  The model is driven by a virtual clock that follows the log
+// (not the wall clock), so timeouts behave the same at any speed and
+// nothing goes stale while playback is paused.
+
+use battery::candump;
+
+#[derive(Debug)]
+pub struct Replay {
+    name: String,
+    frames: Vec<candump::CandumpFrame>,
+
+    /// Index of the next frame to play.
+    next: usize,
+
+    speed: f64,
+    paused: bool,
+
+    /// Playback position (time since the first frame in the log) as of
+    /// `wall_anchor`.
+    log_anchor: std::time::Duration,
+    wall_anchor: std::time::Instant,
+
+    /// The virtual clock reads `epoch` at the first frame of the log.
+    epoch: std::time::Instant,
+}
+
+impl Replay {
+    pub fn open(path: &std::path::Path, speed: f64) -> Result<Self, eyre::Report> {
+        if !(speed > 0.0 && speed.is_finite()) {
+            return Err(eyre::eyre!("invalid replay speed {speed}"));
+        }
+        let frames = candump::read_file(path)?;
+        if frames.is_empty() {
+            return Err(eyre::eyre!("{} has no frames", path.display()));
+        }
+        let now = std::time::Instant::now();
+        Ok(Replay {
+            name: path.display().to_string(),
+            frames,
+            next: 0,
+            speed,
+            paused: false,
+            log_anchor: std::time::Duration::ZERO,
+            wall_anchor: now,
+            epoch: now,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.frames.len()
+    }
+
+    /// The length of the log, from its first frame to its last.
+    pub fn duration(&self) -> std::time::Duration {
+        self.frame_time(self.frames.len() - 1)
+    }
+
+    /// The playback position, as time since the first frame in the log.
+    pub fn position(&self, wall_now: std::time::Instant) -> std::time::Duration {
+        if self.paused {
+            return self.log_anchor;
+        }
+        let elapsed = wall_now.saturating_duration_since(self.wall_anchor);
+        (self.log_anchor + elapsed.mul_f64(self.speed)).min(self.duration())
+    }
+
+    /// The virtual clock to hand to the model.
+    pub fn clock(&self, wall_now: std::time::Instant) -> std::time::Instant {
+        self.epoch + self.position(wall_now)
+    }
+
+    /// Wait until the next frame is due and return it, with its time
+    /// on the virtual clock.  Never returns while paused or after the
+    /// last frame.  This is cancel safe, the frame is only consumed once
+    /// it's returned.
+    pub async fn next_frame(&mut self) -> (std::time::Instant, candump::CandumpFrame) {
+        if self.paused || self.is_finished() {
+            futures::future::pending::<()>().await;
+        }
+
+        let frame_time = self.frame_time(self.next);
+        let position = self.position(std::time::Instant::now());
+        if frame_time > position {
+            tokio::time::sleep((frame_time - position).div_f64(self.speed)).await;
+        }
+
+        let frame = self.frames[self.next].clone();
+        self.next += 1;
+        (self.epoch + frame_time, frame)
+    }
+
+    pub fn toggle_pause(&mut self, wall_now: std::time::Instant) {
+        self.reanchor(wall_now);
+        self.paused = !self.paused;
+    }
+
+    pub fn set_speed(&mut self, speed: f64, wall_now: std::time::Instant) {
+        self.reanchor(wall_now);
+        self.speed = speed;
+    }
+
+    /// Move the playback position to `target` (time since the first
+    /// frame).  The frames before the new position count as played,
+    /// the caller is expected to rebuild its state from `played()`.
+    pub fn seek(&mut self, target: std::time::Duration, wall_now: std::time::Instant) {
+        let target = target.min(self.duration());
+        self.log_anchor = target;
+        self.wall_anchor = wall_now;
+        self.next = self
+            .frames
+            .iter()
+            .position(|frame| self.time_since_start(frame) > target)
+            .unwrap_or(self.frames.len());
+    }
+
+    /// The frames already played, with their times on the virtual
+    /// clock.
+    pub fn played(&self) -> impl Iterator<Item = (std::time::Instant, &candump::CandumpFrame)> {
+        self.frames[..self.next]
+            .iter()
+            .map(|frame| (self.epoch + self.time_since_start(frame), frame))
+    }
+
+    fn reanchor(&mut self, wall_now: std::time::Instant) {
+        self.log_anchor = self.position(wall_now);
+        self.wall_anchor = wall_now;
+    }
+
+    fn frame_time(&self, index: usize) -> std::time::Duration {
+        self.time_since_start(&self.frames[index])
+    }
+
+    // Captures aren't always perfectly in order, frames stamped before
+    // the first one play at the start.
+    fn time_since_start(&self, frame: &candump::CandumpFrame) -> std::time::Duration {
+        frame.timestamp.saturating_sub(self.frames[0].timestamp)
+    }
+}
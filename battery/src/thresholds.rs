@@ -0,0 +1,450 @@
+// Warning and critical limits for the values we watch, and the alarms
+// they raise.  The TUI colours values by them and the headless
+// controller logs an event when an alarm is raised or cleared.
+//
+// The limits come from a TOML file (`--thresholds`) so they can change
+// with the season or the chemistry without a recompile.  Each section
+// is one value, and any of its four limits can be left out:
+//
+// [brick_voltage]
+// warning_low = 3.1
+// warning_high = 3.9
+// critical_low = 3.0
+// critical_high = 4.0
+//
+// A section in the file replaces that value's built-in limits as a
+// whole, sections left out keep the built-in limits.  See
+// `thresholds.toml` for all of them.
+//
+// An alarm doesn't clear, or drop from critical to warning, until the
+// value is back inside the limit by the signal's clear margin, so a
+// value sitting on a limit doesn't raise and clear it on alternate
+// ticks.
+
+use crate::model;
+
+/// How far out of range a value is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Normal,
+    Warning,
+    Critical,
+}
+
+impl Level {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Level::Normal => "Normal",
+            Level::Warning => "Warning",
+            Level::Critical => "Critical",
+        }
+    }
+}
+
+/// The limits for one value.  A value below a `_low` limit or above a
+/// `_high` one is out of range, one that's exactly on the limit isn't.
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    pub warning_low: Option<f32>,
+    pub warning_high: Option<f32>,
+    pub critical_low: Option<f32>,
+    pub critical_high: Option<f32>,
+}
+
+impl Limits {
+    fn new(warning: (f32, f32), critical: (f32, f32)) -> Self {
+        Limits {
+            warning_low: Some(warning.0),
+            warning_high: Some(warning.1),
+            critical_low: Some(critical.0),
+            critical_high: Some(critical.1),
+        }
+    }
+
+    fn high(warning: f32, critical: f32) -> Self {
+        Limits {
+            warning_high: Some(warning),
+            critical_high: Some(critical),
+            ..Limits::default()
+        }
+    }
+
+    fn low(warning: f32, critical: f32) -> Self {
+        Limits {
+            warning_low: Some(warning),
+            critical_low: Some(critical),
+            ..Limits::default()
+        }
+    }
+
+    /// The limits have to go critical_low < warning_low < warning_high
+    /// < critical_high, leaving out any that aren't set.  Otherwise
+    /// some level could never be reached.
+    fn validate(&self) -> Result<(), String> {
+        let limits = [
+            ("critical_low", self.critical_low),
+            ("warning_low", self.warning_low),
+            ("warning_high", self.warning_high),
+            ("critical_high", self.critical_high),
+        ];
+        let mut below: Option<(&str, f32)> = None;
+        for (name, limit) in limits {
+            let Some(limit) = limit else {
+                continue;
+            };
+            if let Some((below_name, below_limit)) = below {
+                if limit <= below_limit {
+                    return Err(format!(
+                        "{name} {limit} has to be above {below_name} {below_limit}"
+                    ));
+                }
+            }
+            below = Some((name, limit));
+        }
+        Ok(())
+    }
+
+    /// Like `check()`, but a value that was at `previous` only drops
+    /// to a lower level once it's inside that level's limits by
+    /// `margin`.
+    pub fn check_from(&self, value: f32, previous: Level, margin: f32) -> Level {
+        let level = self.check(value);
+        if level >= previous {
+            return level;
+        }
+        let narrowed = Limits {
+            warning_low: self.warning_low.map(|limit| limit + margin),
+            warning_high: self.warning_high.map(|limit| limit - margin),
+            critical_low: self.critical_low.map(|limit| limit + margin),
+            critical_high: self.critical_high.map(|limit| limit - margin),
+        };
+        level.max(narrowed.check(value).min(previous))
+    }
+
+    pub fn check(&self, value: f32) -> Level {
+        let below = |limit: Option<f32>| limit.is_some_and(|limit| value < limit);
+        let above = |limit: Option<f32>| limit.is_some_and(|limit| value > limit);
+        if below(self.critical_low) || above(self.critical_high) {
+            Level::Critical
+        } else if below(self.warning_low) || above(self.warning_high) {
+            Level::Warning
+        } else {
+            Level::Normal
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Thresholds {
+    /// Each brick, in V.
+    pub brick_voltage: Limits,
+
+    /// A module's highest brick voltage minus its lowest, in mV.
+    pub brick_delta: Limits,
+
+    /// Each of a module's two cell temperature sensors, in °C.
+    pub module_temperature: Limits,
+
+    /// In °C.
+    pub fet_temperature: Limits,
+    pub shunt_temperature: Limits,
+
+    /// A module's supply rails, in V.
+    pub rail_5v: Limits,
+    pub rail_12v: Limits,
+    pub rail_3v3: Limits,
+
+    /// In A, positive is charging.
+    pub pack_current: Limits,
+
+    /// The pack SOC, in %.
+    pub soc: Limits,
+}
+
+impl Default for Thresholds {
+    /// The limits we use without a thresholds file.  The pack current
+    /// depends on how many modules there are and what they're wired
+    /// to, so it has none.
+    fn default() -> Self {
+        Thresholds {
+            brick_voltage: Limits::new((3.1, 3.9), (3.0, 4.0)),
+            brick_delta: Limits::high(50.0, 100.0),
+            module_temperature: Limits::new((0.0, 45.0), (-10.0, 55.0)),
+            fet_temperature: Limits::high(80.0, 100.0),
+            shunt_temperature: Limits::high(70.0, 90.0),
+            rail_5v: Limits::new((4.75, 5.25), (4.5, 5.5)),
+            rail_12v: Limits::new((11.4, 12.6), (10.8, 13.2)),
+            rail_3v3: Limits::new((3.2, 3.4), (3.1, 3.5)),
+            pack_current: Limits::default(),
+            soc: Limits::low(20.0, 10.0),
+        }
+    }
+}
+
+/// The values we raise alarms on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Signal {
+    BrickVoltage,
+    BrickDelta,
+    ModuleTemperature,
+    FetTemperature,
+    ShuntTemperature,
+    Rail5v,
+    Rail12v,
+    Rail3v3,
+    PackCurrent,
+    Soc,
+}
+
+impl Signal {
+    /// The signal's name in the thresholds file and in events.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Signal::BrickVoltage => "brick_voltage",
+            Signal::BrickDelta => "brick_delta",
+            Signal::ModuleTemperature => "module_temperature",
+            Signal::FetTemperature => "fet_temperature",
+            Signal::ShuntTemperature => "shunt_temperature",
+            Signal::Rail5v => "rail_5v",
+            Signal::Rail12v => "rail_12v",
+            Signal::Rail3v3 => "rail_3v3",
+            Signal::PackCurrent => "pack_current",
+            Signal::Soc => "soc",
+        }
+    }
+
+    pub fn unit(&self) -> &'static str {
+        match self {
+            Signal::BrickVoltage | Signal::Rail5v | Signal::Rail12v | Signal::Rail3v3 => "V",
+            Signal::BrickDelta => "mV",
+            Signal::ModuleTemperature | Signal::FetTemperature | Signal::ShuntTemperature => "°C",
+            Signal::PackCurrent => "A",
+            Signal::Soc => "%",
+        }
+    }
+
+    /// How far back inside a limit the value has to be to clear an
+    /// alarm, in the signal's unit.  About what the value jitters by
+    /// when it's steady.
+    pub fn clear_margin(&self) -> f32 {
+        match self {
+            Signal::BrickVoltage => 0.01,
+            Signal::BrickDelta => 5.0,
+            Signal::ModuleTemperature | Signal::FetTemperature | Signal::ShuntTemperature => 1.0,
+            Signal::Rail5v | Signal::Rail12v | Signal::Rail3v3 => 0.02,
+            Signal::PackCurrent => 1.0,
+            Signal::Soc => 1.0,
+        }
+    }
+
+    /// How many decimal places are worth showing.
+    pub fn precision(&self) -> usize {
+        match self {
+            Signal::BrickVoltage | Signal::Rail5v | Signal::Rail12v | Signal::Rail3v3 => 3,
+            Signal::BrickDelta => 0,
+            Signal::ModuleTemperature
+            | Signal::FetTemperature
+            | Signal::ShuntTemperature
+            | Signal::PackCurrent
+            | Signal::Soc => 1,
+        }
+    }
+}
+
+/// A value that's outside its warning or critical limits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Alarm {
+    pub signal: Signal,
+
+    /// Where the value comes from: a module, and a brick in it, or
+    /// neither for the pack's own values.
+    pub module: Option<usize>,
+    pub brick: Option<usize>,
+
+    pub value: f32,
+    pub level: Level,
+}
+
+impl Alarm {
+    /// Two alarms with the same key are about the same value.
+    pub fn key(&self) -> (Signal, Option<usize>, Option<usize>) {
+        (self.signal, self.module, self.brick)
+    }
+
+    /// Where the alarm is, like "module 3 brick 7", or "pack".
+    pub fn location(&self) -> String {
+        match (self.module, self.brick) {
+            (Some(module), Some(brick)) => format!("module {module} brick {}", brick + 1),
+            (Some(module), None) => format!("module {module}"),
+            _ => String::from("pack"),
+        }
+    }
+}
+
+impl std::fmt::Display for Alarm {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {:.*}{}",
+            self.location(),
+            self.signal.name(),
+            self.signal.precision(),
+            self.value,
+            self.signal.unit()
+        )
+    }
+}
+
+impl Thresholds {
+    /// Read the thresholds from a TOML file.
+    pub fn load(path: &std::path::Path) -> Result<Self, eyre::Report> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| eyre::eyre!("failed to read {}: {e}", path.display()))?;
+        Thresholds::parse(&text).map_err(|e| eyre::eyre!("{}: {e}", path.display()))
+    }
+
+    /// Parse a thresholds file, and check that each section's limits
+    /// are in order.
+    pub fn parse(text: &str) -> Result<Self, eyre::Report> {
+        let thresholds: Thresholds =
+            toml::from_str(text).map_err(|e| eyre::eyre!("bad thresholds: {e}"))?;
+        for (signal, limits) in thresholds.sections() {
+            limits
+                .validate()
+                .map_err(|e| eyre::eyre!("bad thresholds in [{}]: {e}", signal.name()))?;
+        }
+        Ok(thresholds)
+    }
+
+    fn sections(&self) -> [(Signal, &Limits); 10] {
+        [
+            (Signal::BrickVoltage, &self.brick_voltage),
+            (Signal::BrickDelta, &self.brick_delta),
+            (Signal::ModuleTemperature, &self.module_temperature),
+            (Signal::FetTemperature, &self.fet_temperature),
+            (Signal::ShuntTemperature, &self.shunt_temperature),
+            (Signal::Rail5v, &self.rail_5v),
+            (Signal::Rail12v, &self.rail_12v),
+            (Signal::Rail3v3, &self.rail_3v3),
+            (Signal::PackCurrent, &self.pack_current),
+            (Signal::Soc, &self.soc),
+        ]
+    }
+
+    /// Check all the fresh values in the pack against their limits,
+    /// and return an alarm for each one that's outside them.  A
+    /// module temperature alarm is for the worse of its two sensors.
+    ///
+    /// `previous` is what this returned last time, the alarms in it
+    /// only clear once their value is back inside the limit by the
+    /// signal's clear margin.
+    pub fn alarms(
+        &self,
+        battery_pack: &model::BatteryPack,
+        previous: &[Alarm],
+        now: std::time::Instant,
+    ) -> Vec<Alarm> {
+        let mut alarms = vec![];
+        let mut check = |signal: Signal,
+                         module: Option<usize>,
+                         brick: Option<usize>,
+                         limits: &Limits,
+                         value: f32| {
+            let previous_level = previous
+                .iter()
+                .find(|alarm| alarm.key() == (signal, module, brick))
+                .map_or(Level::Normal, |alarm| alarm.level);
+            let level = limits.check_from(value, previous_level, signal.clear_margin());
+            if level != Level::Normal {
+                alarms.push(Alarm {
+                    signal,
+                    module,
+                    brick,
+                    value,
+                    level,
+                });
+            }
+        };
+
+        for (module_index, battery_module) in battery_pack.modules.iter().enumerate() {
+            if !battery_module.is_present() {
+                continue;
+            }
+            let module = Some(module_index);
+
+            for (brick_index, v_brick) in battery_module.v_bricks.iter().enumerate() {
+                if let Some(v) = v_brick.fresh(now) {
+                    check(
+                        Signal::BrickVoltage,
+                        module,
+                        Some(brick_index),
+                        &self.brick_voltage,
+                        v,
+                    );
+                }
+            }
+            if let Some((min, max)) = battery_module.v_brick_min_max(now) {
+                check(
+                    Signal::BrickDelta,
+                    module,
+                    None,
+                    &self.brick_delta,
+                    (max.voltage - min.voltage) * 1000.0,
+                );
+            }
+            if let Some(temperatures_a) = battery_module.temperatures_a.fresh(now) {
+                let worst = match self.module_temperature.check(temperatures_a.module2)
+                    > self.module_temperature.check(temperatures_a.module1)
+                {
+                    true => temperatures_a.module2,
+                    false => temperatures_a.module1,
+                };
+                check(
+                    Signal::ModuleTemperature,
+                    module,
+                    None,
+                    &self.module_temperature,
+                    worst,
+                );
+            }
+            if let Some(temperatures_b) = battery_module.temperatures_b.fresh(now) {
+                check(
+                    Signal::FetTemperature,
+                    module,
+                    None,
+                    &self.fet_temperature,
+                    temperatures_b.fet,
+                );
+                check(
+                    Signal::ShuntTemperature,
+                    module,
+                    None,
+                    &self.shunt_temperature,
+                    temperatures_b.shunt,
+                );
+            }
+            if let Some(adc2) = battery_module.adc2.fresh(now) {
+                check(Signal::Rail5v, module, None, &self.rail_5v, adc2.rail_5v);
+                check(Signal::Rail12v, module, None, &self.rail_12v, adc2.rail_12v);
+                check(Signal::Rail3v3, module, None, &self.rail_3v3, adc2.rail_3v3);
+            }
+        }
+
+        if let Some(pack_hv_status) = battery_pack.pack_hv_status.fresh(now) {
+            check(
+                Signal::PackCurrent,
+                None,
+                None,
+                &self.pack_current,
+                pack_hv_status.current,
+            );
+        }
+        if let Some(pack_soc) = battery_pack.pack_soc.fresh(now) {
+            check(Signal::Soc, None, None, &self.soc, pack_soc.soc);
+        }
+
+        alarms
+    }
+}
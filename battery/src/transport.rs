@@ -0,0 +1,188 @@
+// Where CAN frames come from and go to.  The apps and simulators talk
+// to a `Transport` rather than a socket, so the same logic runs on a
+// real or virtual SocketCAN interface, an in-memory bus, or a candump
+// log.
+//
+// - `SocketCan`: a SocketCAN interface like can0 or vcan0.
+// - `loopback()`: a pair of connected in-memory endpoints, for tests.
+// - `CandumpReader`: receives the frames in a candump log, and keeps
+//   what's sent to it.
+
+use futures_util::stream::StreamExt;
+
+use crate::candump;
+
+/// A CAN frame's id and payload.
+pub type Frame = (embedded_can::Id, Vec<u8>);
+
+pub trait Transport {
+    /// Send one frame.
+    fn send(
+        &mut self,
+        id: embedded_can::Id,
+        data: &[u8],
+    ) -> impl std::future::Future<Output = Result<(), eyre::Report>>;
+
+    /// Wait for the next received frame.  Returns None once no more
+    /// frames will come.  This is cancel safe, so it can be used in
+    /// `tokio::select!`.
+    fn recv(&mut self) -> impl std::future::Future<Output = Option<Result<Frame, eyre::Report>>>;
+}
+
+/// Send a generated message.
+pub async fn send_message(
+    transport: &mut impl Transport,
+    message: &impl embedded_can::Frame,
+) -> Result<(), eyre::Report> {
+    transport.send(message.id(), message.data()).await
+}
+
+/// A SocketCAN interface.
+///
+/// We send and receive on separate sockets, so we see our own frames
+/// the same way the other nodes on the bus do, and they end up in
+/// recordings.
+#[derive(Debug)]
+pub struct SocketCan {
+    rx: tokio_socketcan::CANSocket,
+    tx: tokio_socketcan::CANSocket,
+}
+
+impl SocketCan {
+    pub fn open(interface: &str) -> Result<Self, eyre::Report> {
+        Ok(SocketCan {
+            rx: tokio_socketcan::CANSocket::open(interface)?,
+            tx: tokio_socketcan::CANSocket::open(interface)?,
+        })
+    }
+}
+
+impl Transport for SocketCan {
+    async fn send(&mut self, id: embedded_can::Id, data: &[u8]) -> Result<(), eyre::Report> {
+        let id: u32 = match id {
+            embedded_can::Id::Standard(standard_id) => standard_id.as_raw() as u32,
+            embedded_can::Id::Extended(extended_id) => extended_id.as_raw(),
+        };
+        let raw_frame = tokio_socketcan::CANFrame::new(id, data, false, false)?;
+        self.tx.write_frame(raw_frame)?.await?;
+        Ok(())
+    }
+
+    async fn recv(&mut self) -> Option<Result<Frame, eyre::Report>> {
+        match self.rx.next().await? {
+            Ok(frame) => Some(frame_id(&frame).map(|id| (id, frame.data().to_vec()))),
+            Err(e) => Some(Err(e.into())),
+        }
+    }
+}
+
+fn frame_id(frame: &tokio_socketcan::CANFrame) -> Result<embedded_can::Id, eyre::Report> {
+    if frame.is_extended() {
+        match embedded_can::ExtendedId::new(frame.id()) {
+            Some(id) => Ok(embedded_can::Id::Extended(id)),
+            None => Err(eyre::eyre!("invalid extended frame id {}", frame.id())),
+        }
+    } else {
+        match embedded_can::StandardId::new(frame.id() as u16) {
+            Some(id) => Ok(embedded_can::Id::Standard(id)),
+            None => Err(eyre::eyre!("invalid standard frame id {}", frame.id())),
+        }
+    }
+}
+
+/// One end of an in-memory bus, made by `loopback()`.
+#[derive(Debug)]
+pub struct Loopback {
+    tx: tokio::sync::mpsc::UnboundedSender<Frame>,
+    rx: tokio::sync::mpsc::UnboundedReceiver<Frame>,
+}
+
+/// Two connected endpoints: what one sends, the other receives.
+/// Sending never blocks, and fails once the other end is dropped.
+pub fn loopback() -> (Loopback, Loopback) {
+    let (a_tx, b_rx) = tokio::sync::mpsc::unbounded_channel();
+    let (b_tx, a_rx) = tokio::sync::mpsc::unbounded_channel();
+    (
+        Loopback { tx: a_tx, rx: a_rx },
+        Loopback { tx: b_tx, rx: b_rx },
+    )
+}
+
+impl Loopback {
+    /// Take a frame if one is waiting, without blocking.
+    pub fn try_recv(&mut self) -> Option<Frame> {
+        self.rx.try_recv().ok()
+    }
+}
+
+impl Transport for Loopback {
+    async fn send(&mut self, id: embedded_can::Id, data: &[u8]) -> Result<(), eyre::Report> {
+        self.tx
+            .send((id, data.to_vec()))
+            .map_err(|_| eyre::eyre!("the other end of the loopback is gone"))
+    }
+
+    async fn recv(&mut self) -> Option<Result<Frame, eyre::Report>> {
+        self.rx.recv().await.map(Ok)
+    }
+}
+
+/// Receives the frames from a candump log, then None.  Frames sent to
+/// it are kept, see `sent()`.
+#[derive(Debug)]
+pub struct CandumpReader {
+    frames: Vec<candump::CandumpFrame>,
+
+    /// Index of the next frame to receive.
+    next: usize,
+
+    /// With `paced`, frames come out with their original spacing,
+    /// counted from when the first one was received.  Otherwise as fast
+    /// as they're asked for.
+    paced: bool,
+    started: Option<tokio::time::Instant>,
+
+    sent: Vec<Frame>,
+}
+
+impl CandumpReader {
+    pub fn open(path: &std::path::Path, paced: bool) -> Result<Self, eyre::Report> {
+        Ok(Self::new(candump::read_file(path)?, paced))
+    }
+
+    pub fn new(frames: Vec<candump::CandumpFrame>, paced: bool) -> Self {
+        CandumpReader {
+            frames,
+            next: 0,
+            paced,
+            started: None,
+            sent: vec![],
+        }
+    }
+
+    /// Everything sent so far, oldest first.
+    pub fn sent(&self) -> &[Frame] {
+        &self.sent
+    }
+}
+
+impl Transport for CandumpReader {
+    async fn send(&mut self, id: embedded_can::Id, data: &[u8]) -> Result<(), eyre::Report> {
+        self.sent.push((id, data.to_vec()));
+        Ok(())
+    }
+
+    async fn recv(&mut self) -> Option<Result<Frame, eyre::Report>> {
+        let frame = self.frames.get(self.next)?;
+        if self.paced {
+            let started = *self.started.get_or_insert_with(tokio::time::Instant::now);
+            // Captures aren't always perfectly in order, frames stamped
+            // before the first one come out right away.
+            let offset = frame.timestamp.saturating_sub(self.frames[0].timestamp);
+            tokio::time::sleep_until(started + offset).await;
+        }
+        let frame = self.frames[self.next].clone();
+        self.next += 1;
+        Some(Ok((frame.id, frame.data)))
+    }
+}
@@ -0,0 +1,45 @@
+// The sample buffers behind the TUI's sparklines and charts.
+
+use battery::history;
+
+fn secs(secs: u64) -> std::time::Duration {
+    std::time::Duration::from_secs(secs)
+}
+
+#[test]
+fn old_samples_fall_off() {
+    let start = std::time::Instant::now();
+    let mut history = history::History::new(secs(60));
+    assert!(history.is_empty());
+    assert_eq!(history.latest(), None);
+
+    for i in 0..=90 {
+        history.push(start + secs(i), i as f32);
+    }
+
+    // Only the last minute is left, inclusive of its start.
+    let values: Vec<f32> = history.samples().map(|(_, value)| value).collect();
+    assert_eq!(values.len(), 61);
+    assert_eq!(values.first(), Some(&30.0));
+    assert_eq!(history.latest(), Some(90.0));
+
+    history.clear();
+    assert!(history.is_empty());
+}
+
+#[test]
+fn since_returns_the_window() {
+    let start = std::time::Instant::now();
+    let mut history = history::History::new(secs(3600));
+    for i in 0..100 {
+        history.push(start + secs(i), i as f32);
+    }
+
+    let values: Vec<f32> = history
+        .since(start + secs(95))
+        .map(|(_, value)| value)
+        .collect();
+    assert_eq!(values, vec![95.0, 96.0, 97.0, 98.0, 99.0]);
+    assert_eq!(history.since(start + secs(100)).count(), 0);
+    assert_eq!(history.since(start).count(), 100);
+}
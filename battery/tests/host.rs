@@ -0,0 +1,168 @@
+// The mode commands we send the pack, checked from the pack's end of an
+// in-memory bus.
+
+use battery::abs_alliance_can_messages;
+use battery::candump;
+use battery::host;
+use battery::model;
+use battery::transport;
+use battery::transport::Transport;
+
+/// The mode in a frame, which had better be HOST_batteryRequest.
+fn decode_mode(
+    frame: transport::Frame,
+) -> abs_alliance_can_messages::HostBatteryRequestHostStateRequest {
+    let (id, data) = frame;
+    match abs_alliance_can_messages::Messages::from_can_message(id, &data) {
+        Ok(abs_alliance_can_messages::Messages::HostBatteryRequest(m)) => m.host_state_request(),
+        _ => panic!("expected HOST_batteryRequest"),
+    }
+}
+
+#[tokio::test]
+async fn mode_command_is_sent() {
+    let (mut host_end, mut pack_end) = transport::loopback();
+
+    for mode in [
+        abs_alliance_can_messages::HostBatteryRequestHostStateRequest::None,
+        abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Charge,
+        abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Drive,
+    ] {
+        host::send_mode_command(&mut host_end, mode).await.unwrap();
+        assert_eq!(decode_mode(pack_end.try_recv().unwrap()), mode);
+        assert!(pack_end.try_recv().is_none());
+    }
+}
+
+#[tokio::test]
+async fn sleep_suppresses_keep_alives() {
+    let (mut host_end, mut pack_end) = transport::loopback();
+
+    host::send_mode_command(
+        &mut host_end,
+        abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Sleep,
+    )
+    .await
+    .unwrap();
+    assert!(pack_end.try_recv().is_none());
+
+    // Going to sleep in the first place has to go out.
+    host::send_mode_command_raw(
+        &mut host_end,
+        abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Sleep,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        decode_mode(pack_end.try_recv().unwrap()),
+        abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Sleep
+    );
+}
+
+#[tokio::test]
+async fn loopback_goes_both_ways() {
+    let (mut host_end, mut pack_end) = transport::loopback();
+
+    host::send_mode_command(
+        &mut host_end,
+        abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Charge,
+    )
+    .await
+    .unwrap();
+    let frame = pack_end.recv().await.unwrap().unwrap();
+    assert_eq!(
+        decode_mode(frame),
+        abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Charge
+    );
+
+    let msg = abs_alliance_can_messages::BattChargerControl::new(
+        false, 0, true, 1, 3, true, 52.835, 12.0,
+    )
+    .unwrap();
+    transport::send_message(&mut pack_end, &msg).await.unwrap();
+    let (id, data) = host_end.recv().await.unwrap().unwrap();
+    let mut battery_pack = model::BatteryPack::default();
+    battery_pack
+        .handle_frame(id, &data, std::time::Instant::now())
+        .unwrap();
+    assert!(battery_pack.charge_request.get().unwrap().enable);
+
+    // Once one end is gone the other sees the end of the bus.
+    drop(pack_end);
+    assert!(host_end.recv().await.is_none());
+    assert!(host::send_mode_command(
+        &mut host_end,
+        abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Charge
+    )
+    .await
+    .is_err());
+}
+
+#[tokio::test]
+async fn candump_reader_plays_the_log_and_keeps_what_we_send() {
+    let path =
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/charge.candump");
+    let num_frames = candump::read_file(&path).unwrap().len();
+    let mut reader = transport::CandumpReader::open(&path, false).unwrap();
+
+    let mut modes = vec![];
+    for _ in 0..num_frames {
+        let frame = reader.recv().await.unwrap().unwrap();
+        if let Ok(abs_alliance_can_messages::Messages::HostBatteryRequest(m)) =
+            abs_alliance_can_messages::Messages::from_can_message(frame.0, &frame.1)
+        {
+            modes.push(m.host_state_request());
+        }
+    }
+    assert_eq!(
+        modes,
+        vec![
+            abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Charge,
+            abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Charge
+        ]
+    );
+    assert!(reader.recv().await.is_none());
+
+    host::send_mode_command(
+        &mut reader,
+        abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Drive,
+    )
+    .await
+    .unwrap();
+    host::send_mode_command(
+        &mut reader,
+        abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Sleep,
+    )
+    .await
+    .unwrap();
+    assert_eq!(reader.sent().len(), 1);
+    assert_eq!(
+        decode_mode(reader.sent()[0].clone()),
+        abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Drive
+    );
+}
+
+#[tokio::test]
+async fn keep_alives_continue_without_pack_traffic() {
+    let (mut host_end, mut pack_end) = transport::loopback();
+    let mut battery_pack = model::BatteryPack::default();
+
+    // Nothing heard from the pack, at startup or after the bus drops
+    // out: the headless loop keeps ticking, and each tick sends the
+    // keep-alive.
+    let mode = abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Drive;
+    for _ in 0..3 {
+        assert!(host::needs_tick(&battery_pack, mode));
+        host::send_mode_command(&mut host_end, mode).await.unwrap();
+    }
+    for _ in 0..3 {
+        assert_eq!(decode_mode(pack_end.try_recv().unwrap()), mode);
+    }
+    assert!(pack_end.try_recv().is_none());
+
+    // In Sleep the ticks stop once there's nothing left to time out.
+    let sleep = abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Sleep;
+    assert!(!host::needs_tick(&battery_pack, sleep));
+    battery_pack.modules[0].last_seen = Some(std::time::Instant::now());
+    assert!(host::needs_tick(&battery_pack, sleep));
+}
@@ -0,0 +1,316 @@
+// Feeds hand-built and recorded frames into `model::BatteryPack` and
+// checks what it makes of them.  Time is simulated: every frame gets a
+// `now` relative to the start of the test, nothing sleeps.
+
+use battery::abs_alliance_can_messages;
+use battery::candump;
+use battery::model;
+
+fn ms(millis: u64) -> std::time::Duration {
+    std::time::Duration::from_millis(millis)
+}
+
+fn assert_close(actual: f32, expected: f32) {
+    assert!(
+        (actual - expected).abs() < 0.001,
+        "expected {expected}, got {actual}"
+    );
+}
+
+fn handle(
+    battery_pack: &mut model::BatteryPack,
+    msg: &impl embedded_can::Frame,
+    now: std::time::Instant,
+) {
+    battery_pack
+        .handle_frame(msg.id(), msg.data(), now)
+        .unwrap();
+}
+
+fn module_2_hv_status() -> abs_alliance_can_messages::BattModHvStatus2 {
+    abs_alliance_can_messages::BattModHvStatus2::new(47.85, 47.83, 0.085, 0.015).unwrap()
+}
+
+fn charger_control(volts: f32, amps: f32) -> abs_alliance_can_messages::BattChargerControl {
+    abs_alliance_can_messages::BattChargerControl::new(false, 0, true, 1, 3, true, volts, amps)
+        .unwrap()
+}
+
+/// Play a candump log into `battery_pack`, with the first frame at
+/// `start`.  Returns when the last frame was played.
+fn play(
+    battery_pack: &mut model::BatteryPack,
+    frames: &[candump::CandumpFrame],
+    start: std::time::Instant,
+) -> std::time::Instant {
+    let mut now = start;
+    for frame in frames {
+        now = start + frame.timestamp.saturating_sub(frames[0].timestamp);
+        battery_pack
+            .handle_frame(frame.id, &frame.data, now)
+            .unwrap();
+    }
+    now
+}
+
+fn fixture(name: &str) -> Vec<candump::CandumpFrame> {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    candump::read_file(&path).unwrap()
+}
+
+#[test]
+fn module_appears_on_its_first_message() {
+    let start = std::time::Instant::now();
+    let mut battery_pack = model::BatteryPack::default();
+    assert!(!battery_pack.has_live_data());
+
+    handle(&mut battery_pack, &module_2_hv_status(), start);
+
+    for (module_index, battery_module) in battery_pack.modules.iter().enumerate() {
+        assert_eq!(battery_module.is_present(), module_index == 2);
+    }
+    assert!(battery_pack.has_live_data());
+    let hv_status = battery_pack.modules[2].hv_status.fresh(start).unwrap();
+    assert_close(hv_status.voltage, 47.85);
+    assert_close(hv_status.current, 0.015);
+}
+
+#[test]
+fn module_times_out_after_2s() {
+    let start = std::time::Instant::now();
+    let mut battery_pack = model::BatteryPack::default();
+    handle(&mut battery_pack, &module_2_hv_status(), start);
+
+    assert!(!battery_pack.expire(start + model::MODULE_TIMEOUT - ms(1)));
+    assert!(battery_pack.modules[2].is_present());
+
+    assert!(battery_pack.expire(start + model::MODULE_TIMEOUT));
+    assert!(!battery_pack.modules[2].is_present());
+    assert!(!battery_pack.has_live_data());
+
+    // Nothing left to time out.
+    assert!(!battery_pack.expire(start + model::MODULE_TIMEOUT + ms(1000)));
+}
+
+#[test]
+fn any_module_message_keeps_it_present() {
+    let start = std::time::Instant::now();
+    let mut battery_pack = model::BatteryPack::default();
+    handle(&mut battery_pack, &module_2_hv_status(), start);
+
+    let soc = abs_alliance_can_messages::BattModSoc2::new(91.0, 62.5, 62.5, 3).unwrap();
+    handle(&mut battery_pack, &soc, start + ms(1500));
+
+    assert!(!battery_pack.expire(start + ms(3000)));
+    assert!(battery_pack.modules[2].is_present());
+    assert_close(
+        battery_pack.modules[2]
+            .soc
+            .fresh(start + ms(3000))
+            .unwrap()
+            .soc,
+        62.5,
+    );
+
+    // The HV status is stale by now, but we still have it.
+    assert!(battery_pack.modules[2].hv_status.is_stale(start + ms(3000)));
+    assert!(battery_pack.modules[2].hv_status.get().is_some());
+
+    assert!(battery_pack.expire(start + ms(3500)));
+    assert!(!battery_pack.modules[2].is_present());
+}
+
+#[test]
+fn charge_request_expires_after_2s() {
+    let start = std::time::Instant::now();
+    let mut battery_pack = model::BatteryPack::default();
+    handle(&mut battery_pack, &charger_control(52.835, 12.0), start);
+
+    let charge_request = battery_pack.charge_request.fresh(start).unwrap();
+    assert_close(charge_request.voltage, 52.835);
+    assert_close(charge_request.current, 12.0);
+    assert!(charge_request.enable);
+    assert!(battery_pack.has_live_data());
+
+    assert!(!battery_pack.expire(start + model::CHARGE_REQUEST_TIMEOUT - ms(1)));
+    assert!(battery_pack.charge_request.get().is_some());
+
+    assert!(battery_pack.expire(start + model::CHARGE_REQUEST_TIMEOUT));
+    assert!(battery_pack.charge_request.get().is_none());
+    assert!(!battery_pack.has_live_data());
+}
+
+#[test]
+fn charge_request_is_renewed_by_each_frame() {
+    let start = std::time::Instant::now();
+    let mut battery_pack = model::BatteryPack::default();
+    handle(&mut battery_pack, &charger_control(52.835, 12.0), start);
+    handle(
+        &mut battery_pack,
+        &charger_control(52.835, 24.0),
+        start + ms(1500),
+    );
+
+    assert!(!battery_pack.expire(start + ms(3000)));
+    assert_close(battery_pack.charge_request.get().unwrap().current, 24.0);
+
+    assert!(battery_pack.expire(start + ms(3500)));
+    assert!(battery_pack.charge_request.get().is_none());
+}
+
+#[test]
+fn frames_not_in_the_dbc_are_errors() {
+    let mut battery_pack = model::BatteryPack::default();
+    let id = embedded_can::Id::Standard(embedded_can::StandardId::new(0x7ff).unwrap());
+    assert!(battery_pack
+        .handle_frame(id, &[0; 8], std::time::Instant::now())
+        .is_err());
+    assert!(!battery_pack.has_live_data());
+}
+
+#[test]
+fn recorded_charge_request() {
+    let start = std::time::Instant::now();
+    let mut battery_pack = model::BatteryPack::default();
+    let end = play(&mut battery_pack, &fixture("charge.candump"), start);
+    assert_eq!(end, start + ms(1200));
+
+    assert!(battery_pack.modules[2].is_present());
+    let hv_status = battery_pack.modules[2].hv_status.fresh(end).unwrap();
+    assert_close(hv_status.voltage, 47.85);
+    let charge_request = battery_pack.charge_request.fresh(end).unwrap();
+    assert_close(charge_request.voltage, 52.835);
+    assert_close(charge_request.current, 12.0);
+    assert!(charge_request.enable);
+
+    // The module was last heard from at 1.1s, the charge request at
+    // 1.2s.
+    assert!(battery_pack.expire(start + ms(3100)));
+    assert!(!battery_pack.modules[2].is_present());
+    assert!(battery_pack.charge_request.get().is_some());
+
+    assert!(battery_pack.expire(start + ms(3200)));
+    assert!(!battery_pack.has_live_data());
+}
+
+#[test]
+fn pack_brick_min_max_spans_modules() {
+    let start = std::time::Instant::now();
+    let mut battery_pack = model::BatteryPack::default();
+    assert_eq!(battery_pack.v_brick_min_max(start), None);
+
+    for module_index in [1, 4] {
+        for v_brick in &mut battery_pack.modules[module_index].v_bricks {
+            v_brick.set(3.35, start);
+        }
+    }
+    battery_pack.modules[4].v_bricks[9].set(3.21, start);
+    battery_pack.modules[1].v_bricks[0].set(3.40, start);
+
+    let (min, max) = battery_pack.v_brick_min_max(start).unwrap();
+    assert_eq!((min.module, min.brick), (4, 9));
+    assert_close(min.voltage, 3.21);
+    assert_eq!((max.module, max.brick), (1, 0));
+    assert_close(max.voltage, 3.40);
+
+    // Stale bricks don't count.
+    let later = start + model::STALE_TIMEOUT + ms(1);
+    for (brick_index, v_brick) in battery_pack.modules[4].v_bricks.iter_mut().enumerate() {
+        if brick_index != 9 {
+            v_brick.set(3.35, later);
+        }
+    }
+    let (min, max) = battery_pack.v_brick_min_max(later).unwrap();
+    assert_eq!(min.module, 4);
+    assert_close(min.voltage, 3.35);
+    assert_close(max.voltage, 3.35);
+}
+
+#[test]
+fn pack_temperature_min_max_spans_modules() {
+    let start = std::time::Instant::now();
+    let mut battery_pack = model::BatteryPack::default();
+    assert_eq!(battery_pack.t_module_min_max(start), None);
+
+    let temperatures = |module1, module2| model::TemperaturesA {
+        ambient: 20.0,
+        module1,
+        module2,
+    };
+    battery_pack.modules[2]
+        .temperatures_a
+        .set(temperatures(24.0, 21.5), start);
+    battery_pack.modules[7]
+        .temperatures_a
+        .set(temperatures(22.0, 31.0), start);
+
+    let (min, max) = battery_pack.t_module_min_max(start).unwrap();
+    assert_eq!((min.module, min.sensor), (2, 1));
+    assert_close(min.temperature, 21.5);
+    assert_eq!((max.module, max.sensor), (7, 1));
+    assert_close(max.temperature, 31.0);
+
+    let (module_min, module_max) = battery_pack.modules[2].t_module_min_max(start).unwrap();
+    assert_eq!(module_min.sensor, 1);
+    assert_eq!(module_max.sensor, 0);
+    assert_close(module_max.temperature, 24.0);
+}
+
+#[test]
+fn pack_min_max_is_decoded() {
+    let start = std::time::Instant::now();
+    let mut battery_pack = model::BatteryPack::default();
+
+    // BATT_packMinMax: the brick voltages in 2mV counts and the
+    // temperatures in 0.1°C, each 12 bits with its 4-bit module ID
+    // above it.  The max temperature is SNA.
+    let data: u64 =
+        2047 | 15 << 12 | 213 << 16 | 2 << 28 | 1701 << 32 | 1 << 44 | 1600 << 48 | 3 << 60;
+    let id = embedded_can::StandardId::new(293).unwrap();
+    battery_pack
+        .handle_frame(id.into(), &data.to_le_bytes(), start)
+        .unwrap();
+
+    let pack_min_max = battery_pack.pack_min_max.fresh(start).unwrap();
+    let v_brick_min = pack_min_max.v_brick_min.unwrap();
+    assert_eq!(v_brick_min.id, 3);
+    assert_eq!(v_brick_min.index(model::NUM_MODULES), Some(2));
+    assert_close(v_brick_min.value, 3.2);
+    let v_brick_max = pack_min_max.v_brick_max.unwrap();
+    assert_eq!(v_brick_max.id, 1);
+    assert_close(v_brick_max.value, 3.402);
+    let t_module_min = pack_min_max.t_module_min.unwrap();
+    assert_eq!(t_module_min.id, 2);
+    assert_close(t_module_min.value, 21.3);
+    assert_eq!(pack_min_max.t_module_max, None);
+}
+
+#[test]
+fn module_min_max_is_decoded() {
+    let start = std::time::Instant::now();
+    let mut battery_pack = model::BatteryPack::default();
+
+    // BATT_modMinMax_2, laid out like BATT_packMinMax but with the
+    // voltages first.  The max temperature is SNA.
+    let data: u64 =
+        1649 | 7 << 12 | 1653 << 16 | 12 << 28 | 238 << 32 | 1 << 44 | 2047 << 48 | 15 << 60;
+    let id = embedded_can::ExtendedId::new(0x04940002).unwrap();
+    battery_pack
+        .handle_frame(id.into(), &data.to_le_bytes(), start)
+        .unwrap();
+
+    assert!(battery_pack.modules[2].is_present());
+    let min_max = battery_pack.modules[2].min_max.fresh(start).unwrap();
+    let v_brick_min = min_max.v_brick_min.unwrap();
+    assert_eq!(v_brick_min.index(model::NUM_BRICKS), Some(6));
+    assert_close(v_brick_min.value, 3.298);
+    let v_brick_max = min_max.v_brick_max.unwrap();
+    assert_eq!(v_brick_max.index(model::NUM_BRICKS), Some(11));
+    assert_close(v_brick_max.value, 3.306);
+    let t_module_min = min_max.t_module_min.unwrap();
+    assert_eq!(t_module_min.id, 1);
+    assert_close(t_module_min.value, 23.8);
+    assert_eq!(min_max.t_module_max, None);
+}
@@ -0,0 +1,184 @@
+// The alarm thresholds: the example file, how limits are applied, and
+// which values in a pack raise alarms.
+
+use battery::model;
+use battery::thresholds;
+
+#[test]
+fn example_file_matches_the_defaults() {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("thresholds.toml");
+    assert_eq!(
+        thresholds::Thresholds::load(&path).unwrap(),
+        thresholds::Thresholds::default()
+    );
+}
+
+#[test]
+fn limits_are_exclusive() {
+    let brick_voltage = thresholds::Thresholds::default().brick_voltage;
+    assert_eq!(brick_voltage.check(3.5), thresholds::Level::Normal);
+    assert_eq!(brick_voltage.check(3.1), thresholds::Level::Normal);
+    assert_eq!(brick_voltage.check(3.09), thresholds::Level::Warning);
+    assert_eq!(brick_voltage.check(3.95), thresholds::Level::Warning);
+    assert_eq!(brick_voltage.check(2.99), thresholds::Level::Critical);
+    assert_eq!(brick_voltage.check(4.01), thresholds::Level::Critical);
+}
+
+#[test]
+fn a_section_replaces_the_defaults() {
+    let thresholds = thresholds::Thresholds::parse(
+        "
+        [soc]
+        critical_low = 5
+        ",
+    )
+    .unwrap();
+    assert_eq!(thresholds.soc.warning_low, None);
+    assert_eq!(thresholds.soc.check(15.0), thresholds::Level::Normal);
+    assert_eq!(thresholds.soc.check(4.0), thresholds::Level::Critical);
+    assert_eq!(
+        thresholds.brick_voltage,
+        thresholds::Thresholds::default().brick_voltage
+    );
+}
+
+#[test]
+fn typos_are_errors() {
+    assert!(thresholds::Thresholds::parse("[soc]\nwarning_lo = 20\n").is_err());
+    assert!(thresholds::Thresholds::parse("[brick_volts]\nwarning_low = 3\n").is_err());
+}
+
+#[test]
+fn limits_out_of_order_are_errors() {
+    let error = thresholds::Thresholds::parse(
+        "
+        [brick_voltage]
+        warning_low = 3.1
+        critical_low = 3.2
+        ",
+    )
+    .unwrap_err();
+    assert!(error.to_string().contains("[brick_voltage]"), "{error}");
+
+    let error = thresholds::Thresholds::parse(
+        "
+        [fet_temperature]
+        warning_high = 100
+        critical_high = 80
+        ",
+    )
+    .unwrap_err();
+    assert!(error.to_string().contains("[fet_temperature]"), "{error}");
+
+    // Leaving limits out is fine.
+    assert!(thresholds::Thresholds::parse("[soc]\ncritical_low = 5\n").is_ok());
+}
+
+#[test]
+fn alarms_in_a_pack() {
+    let now = std::time::Instant::now();
+    let thresholds = thresholds::Thresholds::default();
+    let mut battery_pack = model::BatteryPack::default();
+    assert!(thresholds.alarms(&battery_pack, &[], now).is_empty());
+
+    let battery_module = &mut battery_pack.modules[3];
+    battery_module.last_seen = Some(now);
+    for v_brick in &mut battery_module.v_bricks {
+        v_brick.set(3.5, now);
+    }
+    battery_module.v_bricks[6].set(3.46, now);
+    battery_module.v_bricks[9].set(2.95, now);
+    battery_module.temperatures_b.set(
+        model::TemperaturesB {
+            fet: 85.0,
+            shunt: 25.0,
+        },
+        now,
+    );
+
+    let alarms: Vec<_> = thresholds
+        .alarms(&battery_pack, &[], now)
+        .iter()
+        .map(|alarm| (alarm.signal, alarm.module, alarm.brick, alarm.level))
+        .collect();
+    assert_eq!(
+        alarms,
+        vec![
+            (
+                thresholds::Signal::BrickVoltage,
+                Some(3),
+                Some(9),
+                thresholds::Level::Critical
+            ),
+            (
+                thresholds::Signal::BrickDelta,
+                Some(3),
+                None,
+                thresholds::Level::Critical
+            ),
+            (
+                thresholds::Signal::FetTemperature,
+                Some(3),
+                None,
+                thresholds::Level::Warning
+            ),
+        ]
+    );
+
+    // Stale values don't raise alarms.
+    let later = now + model::STALE_TIMEOUT + std::time::Duration::from_millis(1);
+    assert!(thresholds.alarms(&battery_pack, &[], later).is_empty());
+}
+
+#[test]
+fn alarms_clear_with_a_margin() {
+    let now = std::time::Instant::now();
+    let thresholds = thresholds::Thresholds::default();
+    let mut battery_pack = model::BatteryPack::default();
+    let battery_module = &mut battery_pack.modules[3];
+    battery_module.last_seen = Some(now);
+    for v_brick in &mut battery_module.v_bricks {
+        v_brick.set(3.5, now);
+    }
+
+    let levels = |battery_pack: &model::BatteryPack, previous: &[thresholds::Alarm]| {
+        let alarms = thresholds.alarms(battery_pack, previous, now);
+        let levels: Vec<_> = alarms
+            .iter()
+            .filter(|alarm| alarm.signal == thresholds::Signal::BrickVoltage)
+            .map(|alarm| (alarm.brick, alarm.level))
+            .collect();
+        (alarms, levels)
+    };
+
+    // A brick jittering around warning_low raises the alarm once, and
+    // keeps it.
+    battery_pack.modules[3].v_bricks[7].set(3.098, now);
+    let (alarms, levels_now) = levels(&battery_pack, &[]);
+    assert_eq!(levels_now, vec![(Some(7), thresholds::Level::Warning)]);
+    battery_pack.modules[3].v_bricks[7].set(3.102, now);
+    let (alarms, levels_now) = levels(&battery_pack, &alarms);
+    assert_eq!(levels_now, vec![(Some(7), thresholds::Level::Warning)]);
+
+    // Critical only drops to warning once it's back above critical_low
+    // by the margin.
+    battery_pack.modules[3].v_bricks[7].set(2.99, now);
+    let (alarms, levels_now) = levels(&battery_pack, &alarms);
+    assert_eq!(levels_now, vec![(Some(7), thresholds::Level::Critical)]);
+    battery_pack.modules[3].v_bricks[7].set(3.005, now);
+    let (alarms, levels_now) = levels(&battery_pack, &alarms);
+    assert_eq!(levels_now, vec![(Some(7), thresholds::Level::Critical)]);
+    battery_pack.modules[3].v_bricks[7].set(3.05, now);
+    let (alarms, levels_now) = levels(&battery_pack, &alarms);
+    assert_eq!(levels_now, vec![(Some(7), thresholds::Level::Warning)]);
+
+    // And it clears once it's well clear of warning_low.
+    battery_pack.modules[3].v_bricks[7].set(3.115, now);
+    let (_, levels_now) = levels(&battery_pack, &alarms);
+    assert!(levels_now.is_empty());
+
+    // Without the previous alarm there's nothing to hold on to.
+    battery_pack.modules[3].v_bricks[7].set(3.102, now);
+    let (_, levels_now) = levels(&battery_pack, &[]);
+    assert!(levels_now.is_empty());
+}
@@ -0,0 +1,321 @@
+// A simulated Delta-Q ICL 1500-058 charger: the CANopen side (boots
+// Pre-operational, goes Operational on NMT Start, expects our heartbeat
+// back), a CC/CV output stage charging a crude battery model, and the
+// TPDOs the real charger reports it all in.
+//
+// The charger follows the Voltage_Request and Charge_Current_Request in
+// `DeltaQ_RPDO1_0x20a` while Battery_Status is Enabled, limited to its
+// rated power.  It raises the errors the host can cause:
+//
+// - E-0-3-2 (heartbeat lost) when `Heartbeat_Response` goes quiet
+//   while Operational,
+// - E-0-3-3 (target voltage too high) for a Voltage_Request above
+//   `max_volts`,
+// - E-0-3-1 (reference out of range) for a Charge_Current_Request
+//   above `max_amps`,
+//
+// and turns its output off while any of them is active.  They clear
+// once the host behaves again.
+
+use charger::delta_q_can_messages;
+use charger::error_codes::DeltaQError;
+use charger::nmt;
+
+/// How long the charger waits for the host's `Heartbeat_Response`
+/// before raising E-0-3-2.
+const HEARTBEAT_RESPONSE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// How long a charge request is good for.  The charger app sends one
+/// every second.
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3);
+
+const RATED_WATTS: f32 = 1500.0;
+const AC_VOLTS: f32 = 240.0;
+
+// Charge_Indication values.
+const INDICATION_INACTIVE: u8 = 0;
+const INDICATION_BELOW_80: u8 = 1;
+const INDICATION_ABOVE_80: u8 = 2;
+const INDICATION_FINISHING: u8 = 3;
+const INDICATION_COMPLETE: u8 = 4;
+
+/// In constant voltage, the charge is complete once the current drops
+/// below this.
+const COMPLETE_AMPS: f32 = 0.5;
+
+// The battery on the output: open circuit voltage linear in SOC, from a
+// 14s LFP pack's empty to full.
+const BATTERY_EMPTY_VOLTS: f32 = 44.8;
+const BATTERY_FULL_VOLTS: f32 = 53.2;
+const BATTERY_RESISTANCE_OHMS: f32 = 0.05;
+
+#[derive(Clone, Copy, Debug)]
+struct Request {
+    volts: f32,
+    amps: f32,
+    enabled: bool,
+    cycle_type: u8,
+    received: std::time::Instant,
+}
+
+#[derive(Debug)]
+pub struct SimCharger {
+    max_volts: f32,
+    max_amps: f32,
+
+    operational: bool,
+    last_heartbeat_response: std::time::Instant,
+    request: Option<Request>,
+    error: Option<DeltaQError>,
+
+    battery_soc: f32,
+    battery_capacity_ah: f32,
+
+    output_current: f32,
+    derating: bool,
+    constant_voltage: bool,
+
+    elapsed: std::time::Duration,
+    ah_returned: f32,
+    wh_returned: f32,
+}
+
+impl SimCharger {
+    /// `soc` is 0.0 to 1.0.
+    pub fn new(
+        max_volts: f32,
+        max_amps: f32,
+        soc: f32,
+        capacity_ah: f32,
+        now: std::time::Instant,
+    ) -> Self {
+        SimCharger {
+            max_volts,
+            max_amps,
+            operational: false,
+            last_heartbeat_response: now,
+            request: None,
+            error: None,
+            battery_soc: soc.clamp(0.0, 1.0),
+            battery_capacity_ah: capacity_ah,
+            output_current: 0.0,
+            derating: false,
+            constant_voltage: false,
+            elapsed: std::time::Duration::ZERO,
+            ah_returned: 0.0,
+            wh_returned: 0.0,
+        }
+    }
+
+    /// Handle a frame from the host, returning descriptions of what
+    /// changed.
+    pub fn handle_frame(
+        &mut self,
+        id: embedded_can::Id,
+        data: &[u8],
+        now: std::time::Instant,
+    ) -> Vec<String> {
+        let mut events = vec![];
+        match delta_q_can_messages::Messages::from_can_message(id, data) {
+            // Node 0 addresses every node.
+            Ok(delta_q_can_messages::Messages::NmtStart(m))
+                if m.nmt_command_raw() == nmt::NMT_COMMAND_START
+                    && (m.nmt_node() == nmt::CHARGER_NODE_ID || m.nmt_node() == 0)
+                    && !self.operational =>
+            {
+                self.operational = true;
+                self.last_heartbeat_response = now;
+                events.push(format!("NMT state: {}", nmt::NmtState::Operational));
+            }
+
+            Ok(delta_q_can_messages::Messages::HeartbeatResponse(_)) => {
+                self.last_heartbeat_response = now;
+            }
+
+            // PDOs are ignored until NMT Start.
+            Ok(delta_q_can_messages::Messages::DeltaQRpdo10x20a(m)) if self.operational => {
+                self.request = Some(Request {
+                    volts: m.voltage_request(),
+                    amps: m.charge_current_request(),
+                    enabled: m.battery_status_raw() == 1,
+                    cycle_type: m.batt_charge_cycle_type_raw(),
+                    received: now,
+                });
+            }
+
+            _ => (), // RPDO2 is informational, and everything else isn't for us.
+        }
+        events.extend(self.check_errors(now));
+        events
+    }
+
+    /// Advance the simulation by `dt`, returning descriptions of what
+    /// changed.
+    pub fn step(&mut self, dt: std::time::Duration, now: std::time::Instant) -> Vec<String> {
+        let mut events = self.check_errors(now);
+
+        let setpoint = match self.request {
+            Some(request) if self.error.is_none() && request.enabled => Some(request),
+            _ => None,
+        };
+        let was_charging = self.output_current > 0.0;
+        match setpoint {
+            Some(request) => {
+                // Constant current until the battery's terminal voltage
+                // reaches the request, then constant voltage.  Both are
+                // limited by the charger's rated power.
+                let ocv = self.open_circuit_voltage();
+                let cv_amps = ((request.volts - ocv) / BATTERY_RESISTANCE_OHMS).max(0.0);
+                let power_amps = RATED_WATTS / request.volts.max(1.0);
+                let amps = request.amps.min(cv_amps);
+                self.constant_voltage = cv_amps < request.amps;
+                self.derating = power_amps < amps;
+                self.output_current = amps.min(power_amps);
+
+                let hours = dt.as_secs_f32() / 3600.0;
+                self.elapsed += dt;
+                self.ah_returned += self.output_current * hours;
+                self.wh_returned += self.output_current * self.output_voltage() * hours;
+                self.battery_soc = (self.battery_soc
+                    + self.output_current * hours / self.battery_capacity_ah)
+                    .min(1.0);
+            }
+            None => {
+                self.output_current = 0.0;
+                self.derating = false;
+                self.constant_voltage = false;
+            }
+        }
+
+        let charging = self.output_current > 0.0;
+        if charging != was_charging {
+            events.push(format!(
+                "output {} at {:.2}V, battery SOC {:.1}%",
+                match charging {
+                    true => "on",
+                    false => "off",
+                },
+                self.output_voltage(),
+                self.battery_soc * 100.0
+            ));
+        }
+        events
+    }
+
+    /// Raise and clear errors as the host's behaviour changes.
+    fn check_errors(&mut self, now: std::time::Instant) -> Vec<String> {
+        let mut events = vec![];
+
+        if let Some(request) = self.request {
+            if now.saturating_duration_since(request.received) > REQUEST_TIMEOUT {
+                self.request = None;
+                events.push(String::from("charge request timed out"));
+            }
+        }
+
+        let heartbeat_lost = self.operational
+            && now.saturating_duration_since(self.last_heartbeat_response)
+                > HEARTBEAT_RESPONSE_TIMEOUT;
+        let error = match (heartbeat_lost, self.request) {
+            (true, _) => Some(DeltaQError::HeartbeatLost),
+            (false, Some(request)) if request.volts > self.max_volts => {
+                Some(DeltaQError::TargetVoltageTooHigh)
+            }
+            (false, Some(request)) if request.amps > self.max_amps => {
+                Some(DeltaQError::ReferenceOutOfRange)
+            }
+            _ => None,
+        };
+        if error != self.error {
+            if let Some(old) = self.error {
+                events.push(format!("error cleared: {old}"));
+            }
+            if let Some(new) = error {
+                events.push(format!("error raised: {new}"));
+            }
+            self.error = error;
+        }
+
+        events
+    }
+
+    fn open_circuit_voltage(&self) -> f32 {
+        BATTERY_EMPTY_VOLTS + (BATTERY_FULL_VOLTS - BATTERY_EMPTY_VOLTS) * self.battery_soc
+    }
+
+    /// The voltage at the charger's output terminals.
+    fn output_voltage(&self) -> f32 {
+        self.open_circuit_voltage() + self.output_current * BATTERY_RESISTANCE_OHMS
+    }
+
+    fn charge_indication(&self) -> u8 {
+        if self.output_current == 0.0 {
+            match self.constant_voltage {
+                true => INDICATION_COMPLETE,
+                false => INDICATION_INACTIVE,
+            }
+        } else if self.constant_voltage && self.output_current < COMPLETE_AMPS {
+            INDICATION_COMPLETE
+        } else if self.constant_voltage {
+            INDICATION_FINISHING
+        } else if self.battery_soc < 0.8 {
+            INDICATION_BELOW_80
+        } else {
+            INDICATION_ABOVE_80
+        }
+    }
+
+    /// The heartbeat, sent once a second in every NMT state.
+    pub fn heartbeat_frame(&self) -> Result<(embedded_can::Id, Vec<u8>), eyre::Report> {
+        let state = match self.operational {
+            true => nmt::NmtState::Operational,
+            false => nmt::NmtState::PreOperational,
+        };
+        Ok(frame(&nmt::heartbeat(state)?))
+    }
+
+    /// The TPDOs, sent once a second while Operational.
+    pub fn tpdo_frames(&self) -> Result<Vec<(embedded_can::Id, Vec<u8>)>, eyre::Report> {
+        let mut frames = vec![];
+        if !self.operational {
+            return Ok(frames);
+        }
+
+        let cycle_type = match self.request {
+            Some(request) if request.enabled => request.cycle_type,
+            _ => 0,
+        };
+        let m = delta_q_can_messages::DeltaQTpdo10x18a::new(
+            0,
+            self.output_current.min(255.99),
+            self.output_current > 0.0,
+            false,
+            self.derating,
+            self.charge_indication(),
+            cycle_type,
+            self.output_voltage().min(255.99),
+            true,
+        )?;
+        frames.push(frame(&m));
+
+        let m = delta_q_can_messages::DeltaQTpdo20x28a::new(
+            self.wh_returned.min(4095.0),
+            self.elapsed.as_secs().min(655350) as u32,
+            self.ah_returned,
+        )?;
+        frames.push(frame(&m));
+
+        let m = delta_q_can_messages::DeltaQTpdo30x38a::new(
+            self.error.map(|error| error.raw()).unwrap_or(0),
+            (self.battery_soc * 100.0).round() as u8,
+            AC_VOLTS,
+        )?;
+        frames.push(frame(&m));
+
+        Ok(frames)
+    }
+}
+
+fn frame(msg: &impl embedded_can::Frame) -> (embedded_can::Id, Vec<u8>) {
+    (msg.id(), msg.data().to_vec())
+}
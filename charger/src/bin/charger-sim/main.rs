@@ -0,0 +1,96 @@
+use clap::Parser;
+
+use battery::transport;
+use battery::transport::Transport;
+
+mod icl;
+
+/// How often the simulation steps.
+const TICK: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// The heartbeat and TPDOs go out every this many ticks.
+const FRAME_TICKS: u32 = 10;
+
+/// Simulate a Delta-Q ICL 1500-058 charger on a CAN interface, usually
+/// a vcan.
+#[derive(clap::Parser, Debug)]
+#[command(version, about, long_about=None)]
+struct Args {
+    #[arg(long, short = 'c', default_value_t = String::from("vcan0"))]
+    can_interface: String,
+
+    /// Voltage requests above this raise E-0-3-3, "Target voltage
+    /// configuration too high".
+    #[arg(long, default_value_t = 58.0)]
+    max_volts: f32,
+
+    /// Current requests above this raise E-0-3-1, "Reference out of
+    /// range".
+    #[arg(long, default_value_t = 27.0)]
+    max_amps: f32,
+
+    /// Starting SOC of the battery on the charger's output, in percent.
+    #[arg(long, short = 's', default_value_t = 50.0)]
+    soc: f32,
+
+    /// Capacity of the battery on the charger's output.
+    #[arg(long, default_value_t = 100.0)]
+    capacity_ah: f32,
+}
+
+async fn send_frames(
+    transport: &mut impl transport::Transport,
+    frames: Vec<transport::Frame>,
+) -> Result<(), eyre::Report> {
+    for (id, data) in frames {
+        transport.send(id, &data).await?;
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), eyre::Report> {
+    let args = Args::parse();
+    println!("config: {args:#?}");
+
+    let mut transport = transport::SocketCan::open(&args.can_interface)?;
+
+    let mut sim_charger = icl::SimCharger::new(
+        args.max_volts,
+        args.max_amps,
+        args.soc / 100.0,
+        args.capacity_ah,
+        std::time::Instant::now(),
+    );
+    println!("NMT state: Pre-operational");
+
+    let mut interval = tokio::time::interval(TICK);
+    let mut ticks: u32 = 0;
+
+    loop {
+        tokio::select! {
+            maybe_frame = transport.recv() => {
+                match maybe_frame {
+                    Some(Ok((id, data))) => {
+                        for event in sim_charger.handle_frame(id, &data, std::time::Instant::now()) {
+                            println!("{event}");
+                        }
+                    }
+                    Some(Err(e)) => return Err(e),
+                    None => return Ok(()),
+                }
+            }
+
+            _ = interval.tick() => {
+                for event in sim_charger.step(TICK, std::time::Instant::now()) {
+                    println!("{event}");
+                }
+                if ticks % FRAME_TICKS == 0 {
+                    send_frames(&mut transport, vec![sim_charger.heartbeat_frame()?]).await?;
+                    send_frames(&mut transport, sim_charger.tpdo_frames()?).await?;
+                }
+                ticks = ticks.wrapping_add(1);
+            }
+        }
+    }
+}
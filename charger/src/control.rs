@@ -0,0 +1,108 @@
+// Changing the charger's setpoint while it runs.  Commands come one per
+// line, typed on stdin (`--interactive`) or sent to a Unix socket
+// (`--control-socket`, see `battery::control_socket`), and each is
+// answered with one or more lines, the last of which is "ok" or starts
+// with "error:".
+//
+// $ echo "amps 12" | socat - UNIX-CONNECT:/run/charger.sock
+// ok
+//
+// Commands:
+//     volts <V>          Change the charge voltage (the ceiling when following).
+//     amps <A>           Ramp to this current (the ceiling when following).
+//     temperature <C>    Change the battery temperature we report.
+//     soc <%>            Change the battery SOC we report.
+//     ramp <A/s>         Change the current ramp rate, 0 to step.
+//     status             Print the setpoint and the charger telemetry.
+
+use std::io::BufRead;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Command {
+    SetVolts(f32),
+    SetAmps(f32),
+    SetTemperature(f32),
+    SetSoc(u8),
+    SetRamp(f32),
+    Status,
+}
+
+/// A command from stdin or a control client, and where to send the
+/// answer.
+pub type Request = battery::control_socket::Request<Command>;
+
+/// Parse `value` as an f32 in `min..=max`.  The limits are what the
+/// RPDO fields can carry.
+fn parse_value(name: &str, value: &str, min: f32, max: f32) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(x) if (min..=max).contains(&x) => Ok(x),
+        Ok(_) => Err(format!("{name} must be between {min} and {max}")),
+        Err(_) => Err(format!("bad {name} {value:?}")),
+    }
+}
+
+/// Parse a charge voltage, for the `volts` command and `--volts`.
+pub fn parse_volts(value: &str) -> Result<f32, String> {
+    parse_value("volts", value, 0.0, 255.0)
+}
+
+/// Parse a charge current, for the `amps` command and `--amps`.
+pub fn parse_amps(value: &str) -> Result<f32, String> {
+    parse_value("amps", value, 0.0, 255.0)
+}
+
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        ["volts", value] => Ok(Command::SetVolts(parse_volts(value)?)),
+        ["amps", value] => Ok(Command::SetAmps(parse_amps(value)?)),
+        ["temperature", value] => Ok(Command::SetTemperature(parse_value(
+            "temperature",
+            value,
+            -40.0,
+            85.0,
+        )?)),
+        ["soc", value] => match value.parse::<u8>() {
+            Ok(soc) if soc <= 100 => Ok(Command::SetSoc(soc)),
+            _ => Err(format!("soc must be between 0 and 100, not {value:?}")),
+        },
+        ["ramp", value] => Ok(Command::SetRamp(parse_value("ramp", value, 0.0, 1000.0)?)),
+        ["status"] => Ok(Command::Status),
+        _ => Err(format!("unknown command {line:?}")),
+    }
+}
+
+/// Read commands from stdin until it closes.  This is a plain thread
+/// rather than a task because a blocking read of the terminal can't be
+/// cancelled, and a task stuck in one would hold up the runtime's
+/// shutdown on Ctrl-C until someone pressed Enter.
+pub fn read_stdin(request_tx: tokio::sync::mpsc::Sender<Request>) {
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let answer = match parse_command(&line) {
+                Err(e) => Err(e),
+                Ok(command) => {
+                    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+                    let request = Request {
+                        command,
+                        reply: reply_tx,
+                    };
+                    if request_tx.blocking_send(request).is_err() {
+                        break;
+                    }
+                    match reply_rx.blocking_recv() {
+                        Ok(answer) => answer,
+                        Err(_) => break,
+                    }
+                }
+            };
+            print!("{}", battery::control_socket::format_answer(answer));
+        }
+    });
+}
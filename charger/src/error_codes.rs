@@ -0,0 +1,315 @@
+// The Delta-Q error codes, from the `Error_Codes` value table in
+// delta_q.dbc.  The charger reports the active error in
+// `DeltaQ_TPDO3_0x38a.Current_Error`, zero means no error.
+//
+// Codes starting with "E-" are recoverable errors, codes starting with
+// "F-" are charger hardware faults.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeltaQError {
+    /// E-0-2-3: High AC voltage error ( >270VAC )
+    HighAcVoltage,
+    /// E-0-2-4: Charger failed to initialize
+    InitializationFailed,
+    /// E-0-2-5: Low AC voltage oscillation error
+    LowAcVoltageOscillation,
+    /// E-0-2-6: USB Script Error
+    UsbScript,
+    /// E-0-2-7: USB Over Current
+    UsbOverCurrent,
+    /// E-0-2-8: Incompatible algorithm error
+    IncompatibleAlgorithm,
+    /// E-0-2-9: Communication CAN-bus error
+    CanBus,
+    /// E-0-3-0: Communication battery module error
+    BatteryModuleCommunication,
+    /// E-0-3-1: Reference out of range error
+    ReferenceOutOfRange,
+    /// E-0-3-2: Communication heartbeat lost error
+    HeartbeatLost,
+    /// E-0-3-3: Target voltage configuration too high
+    TargetVoltageTooHigh,
+    /// E-0-3-4: Battery capacity configuration not set
+    BatteryCapacityNotSet,
+    /// E-0-3-5: Target voltage configuration too low
+    TargetVoltageTooLow,
+    /// E-0-3-6: Battery temperature sensor not installed
+    BatteryTemperatureSensorNotInstalled,
+    /// E-0-3-7: CAN Download Failed
+    CanDownloadFailed,
+    /// E-0-3-8: Fan error
+    Fan,
+    /// E-0-3-9: Button stuck down
+    ButtonStuckDown,
+    /// E-0-4-0: Fan Supply Voltage Low
+    FanSupplyVoltageLow,
+    /// E-0-4-1: Software Internal Error
+    SoftwareInternal,
+    /// E-0-4-2: CAN Configuration Error
+    CanConfiguration,
+    /// E-0-4-3: PDO CRC Error
+    PdoCrc,
+    /// E-0-4-4: PDO Sequence Count Error
+    PdoSequenceCount,
+    /// E-0-4-5: Battery Disconnected Alarm
+    BatteryDisconnected,
+    /// E-0-4-6: Invalid PDO Length
+    InvalidPdoLength,
+    /// F-0-0-1: Output Stage Error
+    OutputStage,
+    /// F-0-0-2: Input Stage Error
+    InputStage2,
+    /// F-0-0-3: Input Stage Error
+    InputStage3,
+    /// F-0-0-4: Current Measurement Error
+    CurrentMeasurement,
+    /// F-0-0-5: DC Output Relay Test Error (High voltage across closed relay)
+    DcOutputRelayTest,
+    /// F-0-0-6: Output Current Error
+    OutputCurrent,
+    /// An error code that's not in the DBC.
+    Unknown(u32),
+}
+
+impl DeltaQError {
+    /// Decode `Current_Error`.  Returns None if the charger reports no
+    /// error.
+    pub fn from_raw(raw: u32) -> Option<Self> {
+        match raw {
+            0 => None,
+            0x17809000 => Some(DeltaQError::HighAcVoltage),
+            0x18801000 => Some(DeltaQError::InitializationFailed),
+            0x19809000 => Some(DeltaQError::LowAcVoltageOscillation),
+            0x1a800000 => Some(DeltaQError::UsbScript),
+            0x1b800000 => Some(DeltaQError::UsbOverCurrent),
+            0x1c801000 => Some(DeltaQError::IncompatibleAlgorithm),
+            0x1d809000 => Some(DeltaQError::CanBus),
+            0x1e808130 => Some(DeltaQError::BatteryModuleCommunication),
+            0x1f801000 => Some(DeltaQError::ReferenceOutOfRange),
+            0x20808130 => Some(DeltaQError::HeartbeatLost),
+            0x21801000 => Some(DeltaQError::TargetVoltageTooHigh),
+            0x22801000 => Some(DeltaQError::BatteryCapacityNotSet),
+            0x23801000 => Some(DeltaQError::TargetVoltageTooLow),
+            0x24809000 => Some(DeltaQError::BatteryTemperatureSensorNotInstalled),
+            0x25806000 => Some(DeltaQError::CanDownloadFailed),
+            0x26809000 => Some(DeltaQError::Fan),
+            0x27801000 => Some(DeltaQError::ButtonStuckDown),
+            0x28801000 => Some(DeltaQError::FanSupplyVoltageLow),
+            0x29806000 => Some(DeltaQError::SoftwareInternal),
+            0x2a806000 => Some(DeltaQError::CanConfiguration),
+            0x2b809000 => Some(DeltaQError::PdoCrc),
+            0x2c809000 => Some(DeltaQError::PdoSequenceCount),
+            0x2d809000 => Some(DeltaQError::BatteryDisconnected),
+            0x2e808210 => Some(DeltaQError::InvalidPdoLength),
+            0x01c05000 => Some(DeltaQError::OutputStage),
+            0x02c05000 => Some(DeltaQError::InputStage2),
+            0x03c05000 => Some(DeltaQError::InputStage3),
+            0x04c05000 => Some(DeltaQError::CurrentMeasurement),
+            0x05c05000 => Some(DeltaQError::DcOutputRelayTest),
+            0x500006c0 => Some(DeltaQError::OutputCurrent),
+            x => Some(DeltaQError::Unknown(x)),
+        }
+    }
+
+    pub fn raw(&self) -> u32 {
+        match self {
+            DeltaQError::HighAcVoltage => 0x17809000,
+            DeltaQError::InitializationFailed => 0x18801000,
+            DeltaQError::LowAcVoltageOscillation => 0x19809000,
+            DeltaQError::UsbScript => 0x1a800000,
+            DeltaQError::UsbOverCurrent => 0x1b800000,
+            DeltaQError::IncompatibleAlgorithm => 0x1c801000,
+            DeltaQError::CanBus => 0x1d809000,
+            DeltaQError::BatteryModuleCommunication => 0x1e808130,
+            DeltaQError::ReferenceOutOfRange => 0x1f801000,
+            DeltaQError::HeartbeatLost => 0x20808130,
+            DeltaQError::TargetVoltageTooHigh => 0x21801000,
+            DeltaQError::BatteryCapacityNotSet => 0x22801000,
+            DeltaQError::TargetVoltageTooLow => 0x23801000,
+            DeltaQError::BatteryTemperatureSensorNotInstalled => 0x24809000,
+            DeltaQError::CanDownloadFailed => 0x25806000,
+            DeltaQError::Fan => 0x26809000,
+            DeltaQError::ButtonStuckDown => 0x27801000,
+            DeltaQError::FanSupplyVoltageLow => 0x28801000,
+            DeltaQError::SoftwareInternal => 0x29806000,
+            DeltaQError::CanConfiguration => 0x2a806000,
+            DeltaQError::PdoCrc => 0x2b809000,
+            DeltaQError::PdoSequenceCount => 0x2c809000,
+            DeltaQError::BatteryDisconnected => 0x2d809000,
+            DeltaQError::InvalidPdoLength => 0x2e808210,
+            DeltaQError::OutputStage => 0x01c05000,
+            DeltaQError::InputStage2 => 0x02c05000,
+            DeltaQError::InputStage3 => 0x03c05000,
+            DeltaQError::CurrentMeasurement => 0x04c05000,
+            DeltaQError::DcOutputRelayTest => 0x05c05000,
+            DeltaQError::OutputCurrent => 0x500006c0,
+            DeltaQError::Unknown(x) => *x,
+        }
+    }
+
+    // (Delta-Q code, description, CANopen error class)
+    fn info(&self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            DeltaQError::HighAcVoltage => (
+                "E-0-2-3",
+                "High AC voltage error ( >270VAC )",
+                "9000h External error - generic",
+            ),
+            DeltaQError::InitializationFailed => (
+                "E-0-2-4",
+                "Charger failed to initialize",
+                "1000h Generic error",
+            ),
+            DeltaQError::LowAcVoltageOscillation => (
+                "E-0-2-5",
+                "Low AC voltage oscillation error",
+                "9000h External error - generic",
+            ),
+            DeltaQError::UsbScript => ("E-0-2-6", "USB Script Error", "0000h error"),
+            DeltaQError::UsbOverCurrent => ("E-0-2-7", "USB Over Current", "0000h error"),
+            DeltaQError::IncompatibleAlgorithm => (
+                "E-0-2-8",
+                "Incompatible algorithm error",
+                "1000h Generic error",
+            ),
+            DeltaQError::CanBus => (
+                "E-0-2-9",
+                "Communication CAN-bus error",
+                "9000h External error - generic",
+            ),
+            DeltaQError::BatteryModuleCommunication => (
+                "E-0-3-0",
+                "Communication battery module error",
+                "8130h Monitoring - Comms - Heartbeat Error",
+            ),
+            DeltaQError::ReferenceOutOfRange => (
+                "E-0-3-1",
+                "Reference out of range error",
+                "1000h Generic error",
+            ),
+            DeltaQError::HeartbeatLost => (
+                "E-0-3-2",
+                "Communication heartbeat lost error",
+                "8130h Monitoring - Comms - Heartbeat Error",
+            ),
+            DeltaQError::TargetVoltageTooHigh => (
+                "E-0-3-3",
+                "Target voltage configuration too high",
+                "1000h Generic error",
+            ),
+            DeltaQError::BatteryCapacityNotSet => (
+                "E-0-3-4",
+                "Battery capacity configuration not set",
+                "1000h Generic error",
+            ),
+            DeltaQError::TargetVoltageTooLow => (
+                "E-0-3-5",
+                "Target voltage configuration too low",
+                "1000h Generic error",
+            ),
+            DeltaQError::BatteryTemperatureSensorNotInstalled => (
+                "E-0-3-6",
+                "Battery temperature sensor not installed",
+                "9000h External error - generic",
+            ),
+            DeltaQError::CanDownloadFailed => {
+                ("E-0-3-7", "CAN Download Failed", "6000h SW Generic error")
+            }
+            DeltaQError::Fan => ("E-0-3-8", "Fan error", "9000h External error - generic"),
+            DeltaQError::ButtonStuckDown => ("E-0-3-9", "Button stuck down", "1000h Generic error"),
+            DeltaQError::FanSupplyVoltageLow => {
+                ("E-0-4-0", "Fan Supply Voltage Low", "1000h Generic error")
+            }
+            DeltaQError::SoftwareInternal => (
+                "E-0-4-1",
+                "Software Internal Error",
+                "6000h SW Generic error",
+            ),
+            DeltaQError::CanConfiguration => (
+                "E-0-4-2",
+                "CAN Configuration Error",
+                "6000h SW Generic error",
+            ),
+            DeltaQError::PdoCrc => ("E-0-4-3", "PDO CRC Error", "9000h External error - generic"),
+            DeltaQError::PdoSequenceCount => (
+                "E-0-4-4",
+                "PDO Sequence Count Error",
+                "9000h External error - generic",
+            ),
+            DeltaQError::BatteryDisconnected => (
+                "E-0-4-5",
+                "Battery Disconnected Alarm",
+                "9000h External error - generic",
+            ),
+            DeltaQError::InvalidPdoLength => (
+                "E-0-4-6",
+                "Invalid PDO Length",
+                "8210h Monitoring - Protocol - PDO Length Error",
+            ),
+            DeltaQError::OutputStage => (
+                "F-0-0-1",
+                "Output Stage Error",
+                "5000h CANopen Device Hardware",
+            ),
+            DeltaQError::InputStage2 => (
+                "F-0-0-2",
+                "Input Stage Error",
+                "5000h CANopen Device Hardware",
+            ),
+            DeltaQError::InputStage3 => (
+                "F-0-0-3",
+                "Input Stage Error",
+                "5000h CANopen Device Hardware",
+            ),
+            DeltaQError::CurrentMeasurement => (
+                "F-0-0-4",
+                "Current Measurement Error",
+                "5000h CANopen Device Hardware",
+            ),
+            DeltaQError::DcOutputRelayTest => (
+                "F-0-0-5",
+                "DC Output Relay Test Error (High voltage across closed relay)",
+                "5000h CANopen Device Hardware",
+            ),
+            DeltaQError::OutputCurrent => (
+                "F-0-0-6",
+                "Output Current Error",
+                "5000h CANopen Device Hardware",
+            ),
+            DeltaQError::Unknown(_) => ("?-?-?-?", "Unknown error", "unknown"),
+        }
+    }
+
+    /// The Delta-Q error code, for example "E-0-3-2".
+    pub fn code(&self) -> &'static str {
+        self.info().0
+    }
+
+    pub fn description(&self) -> &'static str {
+        self.info().1
+    }
+
+    /// The CANopen emergency error code and class, for example
+    /// "8130h Monitoring - Comms - Heartbeat Error".
+    pub fn canopen_class(&self) -> &'static str {
+        self.info().2
+    }
+
+    /// True for the "F-" charger hardware faults.
+    pub fn is_hardware_fault(&self) -> bool {
+        self.code().starts_with("F-")
+    }
+}
+
+impl std::fmt::Display for DeltaQError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} [{}] (0x{:08x})",
+            self.code(),
+            self.description(),
+            self.canopen_class(),
+            self.raw()
+        )
+    }
+}
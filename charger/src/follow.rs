@@ -0,0 +1,138 @@
+// Closed-loop control of the charger from the battery pack's
+// BATT_chargerControl message.
+//
+// BATT_chargerControl(
+//     BATT_chargeError: false,
+//     BATT_chargerMode: 0,
+//     BATT_chargerConnected: true,
+//     BATT_chargeMode: 1,
+//     BATT_chargeStatus: 3,
+//     BATT_chargeEnable: true,
+//     BATT_chargingVoltage: 52.835 V,
+//     BATT_chargingCurrent: 12.0 A
+// )
+
+/// How long a charge request from the pack is good for.  This matches
+/// the timeout the battery app uses for BATT_chargerControl.
+pub const CHARGE_REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+#[derive(Clone, Copy, Debug)]
+pub struct ChargeRequest {
+    pub voltage: f32,
+    pub current: f32,
+    pub enable: bool,
+}
+
+impl ChargeRequest {
+    /// Returns the charge request if the frame is a BATT_chargerControl
+    /// message, None for any other frame.
+    pub fn from_frame(id: embedded_can::Id, data: &[u8]) -> Option<Self> {
+        match battery::abs_alliance_can_messages::Messages::from_can_message(id, data) {
+            Ok(battery::abs_alliance_can_messages::Messages::BattChargerControl(m)) => {
+                Some(ChargeRequest {
+                    voltage: m.batt_charging_voltage(),
+                    current: m.batt_charging_current(),
+                    enable: m.batt_charge_enable(),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FollowState {
+    /// No charge request seen from the pack yet.
+    WaitingForRequest,
+
+    /// The pack is sending charge requests but doesn't want charge.
+    Disabled,
+
+    /// Charging at the pack's request, clamped to our ceilings.
+    Charging { volts: f32, amps: f32 },
+
+    /// The pack stopped sending charge requests.
+    TimedOut,
+}
+
+impl std::fmt::Display for FollowState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FollowState::WaitingForRequest => write!(f, "waiting for charge request from pack"),
+            FollowState::Disabled => write!(f, "pack does not want charge"),
+            FollowState::Charging { volts, amps } => {
+                write!(f, "charging at {:.3}V {:.3}A", volts, amps)
+            }
+            FollowState::TimedOut => write!(f, "charge request timed out, charger stopped"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Follower {
+    max_volts: f32,
+    max_amps: f32,
+    state: FollowState,
+}
+
+impl Follower {
+    pub fn new(max_volts: f32, max_amps: f32) -> Self {
+        Follower {
+            max_volts,
+            max_amps,
+            state: FollowState::WaitingForRequest,
+        }
+    }
+
+    /// Change the ceilings.  They apply from the pack's next charge
+    /// request, which is never more than a moment away.
+    pub fn set_ceilings(&mut self, max_volts: f32, max_amps: f32) {
+        self.max_volts = max_volts;
+        self.max_amps = max_amps;
+    }
+
+    pub fn state(&self) -> FollowState {
+        self.state
+    }
+
+    /// True while we have a live charge request from the pack, ie
+    /// while the request timeout should be armed.
+    pub fn is_following(&self) -> bool {
+        matches!(
+            self.state,
+            FollowState::Disabled | FollowState::Charging { .. }
+        )
+    }
+
+    /// Returns true if the loop state changed.
+    pub fn handle_request(&mut self, request: ChargeRequest) -> bool {
+        let state = if request.enable && request.current > 0.0 {
+            FollowState::Charging {
+                volts: request.voltage.clamp(0.0, self.max_volts),
+                amps: request.current.clamp(0.0, self.max_amps),
+            }
+        } else {
+            FollowState::Disabled
+        };
+        self.set_state(state)
+    }
+
+    /// Returns true if the loop state changed.
+    pub fn time_out(&mut self) -> bool {
+        self.set_state(FollowState::TimedOut)
+    }
+
+    /// The voltage and current to command the charger to.
+    pub fn setpoint(&self) -> (f32, f32) {
+        match self.state {
+            FollowState::Charging { volts, amps } => (volts, amps),
+            _ => (0.0, 0.0),
+        }
+    }
+
+    fn set_state(&mut self, state: FollowState) -> bool {
+        let changed = state != self.state;
+        self.state = state;
+        changed
+    }
+}
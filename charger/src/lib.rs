@@ -0,0 +1,6 @@
+pub mod delta_q_can_messages;
+pub mod error_codes;
+pub mod follow;
+pub mod nmt;
+pub mod setpoint;
+pub mod telemetry;
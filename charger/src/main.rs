@@ -1,18 +1,28 @@
-use embedded_can::Frame;
-use futures_util::stream::StreamExt;
-
 use clap::Parser;
 
-mod delta_q_can_messages;
+use battery::transport;
+use battery::transport::Transport;
+
+use charger::delta_q_can_messages;
+use charger::follow;
+use charger::nmt;
+use charger::setpoint;
+use charger::telemetry;
+
+mod control;
+mod metrics;
+
+/// Exit status when the charger reports an "F-" hardware fault.
+const EXIT_HARDWARE_FAULT: i32 = 3;
 
 /// Command voltage and current from a DeltaQ ICL 1500-058 charger.
 #[derive(clap::Parser, Debug)]
 #[command(version, about, long_about=None)]
 struct Args {
-    #[arg(long, short = 'v')]
+    #[arg(long, short = 'v', value_parser = control::parse_volts)]
     volts: f32,
 
-    #[arg(long, short = 'a')]
+    #[arg(long, short = 'a', value_parser = control::parse_amps)]
     amps: f32,
 
     #[arg(long, short = 't', default_value_t = 20.0)]
@@ -21,44 +31,94 @@ struct Args {
     #[arg(long, short = 's', default_value_t = 50)]
     soc: u8,
 
+    /// Ramp the current to a new `amps` setpoint at this many A/s, 0 to
+    /// step straight to it.
+    #[arg(long, default_value_t = 1.0)]
+    ramp: f32,
+
+    /// Take setpoint commands (`volts 54`, `amps 12`, `status`, ...)
+    /// typed on stdin, one per line.
+    #[arg(long, short = 'i')]
+    interactive: bool,
+
+    /// Take setpoint commands on this Unix socket.
+    #[arg(long)]
+    control_socket: Option<std::path::PathBuf>,
+
     #[arg(long, short = 'c', default_value_t = String::from("can0"))]
     can_interface: String,
+
+    /// Follow the charge voltage, current and enable requested by the
+    /// battery pack's BATT_chargerControl message.  `--volts` and
+    /// `--amps` are the ceilings for what the pack may request.
+    #[arg(long, short = 'f')]
+    follow_battery: bool,
+
+    /// Record every received frame (in candump format) and the decoded
+    /// telemetry to files in this directory.
+    #[arg(long)]
+    record: Option<std::path::PathBuf>,
+
+    /// Start a new recording file when the current one reaches this
+    /// size.
+    #[arg(long, default_value_t = 64)]
+    record_file_mb: u64,
+
+    /// Keep at most this many recording files of each kind, deleting
+    /// the oldest.
+    #[arg(long, default_value_t = 100)]
+    record_max_files: usize,
+
+    /// Serve Prometheus metrics at /metrics on this address, like
+    /// 0.0.0.0:9101.
+    #[arg(long)]
+    http: Option<std::net::SocketAddr>,
 }
 
-#[allow(dead_code)]
-fn handle_can_frame(frame: tokio_socketcan::CANFrame) -> Result<(), eyre::Report> {
-    let id: embedded_can::Id = if frame.is_extended() {
-        match embedded_can::ExtendedId::new(frame.id()) {
-            Some(id) => embedded_can::Id::Extended(id),
-            None => return Err(eyre::eyre!("invalid extended frame id {}", frame.id())),
+/// Run `f` on the recorder, if we're recording.  If it fails we stop
+/// recording rather than stop charging.
+fn record(
+    recorder: &mut Option<battery::recorder::Recorder>,
+    f: impl FnOnce(&mut battery::recorder::Recorder) -> Result<(), eyre::Report>,
+) {
+    if let Some(r) = recorder {
+        if let Err(e) = f(r) {
+            println!("recording stopped: {e}");
+            *recorder = None;
         }
-    } else {
-        match embedded_can::StandardId::new(frame.id() as u16) {
-            Some(id) => embedded_can::Id::Standard(id),
-            None => return Err(eyre::eyre!("invalid standard frame id {}", frame.id())),
-        }
-    };
+    }
+}
 
-    let msg = delta_q_can_messages::Messages::from_can_message(id, frame.data());
-    println!("{:#?}", msg);
+/// Print a change in the charger or follow state, and record it.
+fn log_event(recorder: &mut Option<battery::recorder::Recorder>, message: &str) {
+    println!("{message}");
+    record(recorder, |r| {
+        r.record_telemetry(&format!("event message={message:?}"))
+    });
+}
 
-    Ok(())
+/// The voltage and current to command the charger to: what the pack
+/// asks for when we're following it, our setpoint when not.
+fn commanded(
+    follow_battery: bool,
+    follower: &follow::Follower,
+    setpoint: &setpoint::Setpoint,
+) -> (f32, f32) {
+    match follow_battery {
+        true => follower.setpoint(),
+        false => (setpoint.volts(), setpoint.amps()),
+    }
 }
 
 async fn send_command(
-    can_socket_tx: &tokio_socketcan::CANSocket,
+    transport: &mut impl transport::Transport,
     volts: f32,
     amps: f32,
     temperature: f32,
     soc: u8,
 ) -> Result<(), eyre::Report> {
     let frame = delta_q_can_messages::DeltaQRpdo20x30a::new(amps, volts, temperature)?;
-    let id: u32 = match frame.id() {
-        embedded_can::Id::Standard(standard_id) => standard_id.as_raw() as u32,
-        embedded_can::Id::Extended(extended_id) => extended_id.as_raw(),
-    };
-    let raw_frame = tokio_socketcan::CANFrame::new(id, frame.raw(), false, false)?;
-    can_socket_tx.write_frame(raw_frame)?.await?;
+    transport::send_message(transport, &frame).await?;
 
     let batt_charge_cycle_time = match amps {
         0.0 => delta_q_can_messages::DeltaQRpdo10x20aBattChargeCycleType::NoActiveCycle,
@@ -77,12 +137,7 @@ async fn send_command(
         amps,
         battery_status.into(),
     )?;
-    let id: u32 = match frame.id() {
-        embedded_can::Id::Standard(standard_id) => standard_id.as_raw() as u32,
-        embedded_can::Id::Extended(extended_id) => extended_id.as_raw(),
-    };
-    let raw_frame = tokio_socketcan::CANFrame::new(id, frame.raw(), false, false)?;
-    can_socket_tx.write_frame(raw_frame)?.await?;
+    transport::send_message(transport, &frame).await?;
 
     Ok(())
 }
@@ -92,12 +147,59 @@ async fn main() -> Result<(), eyre::Report> {
     let args = Args::parse();
     println!("config: {args:#?}");
 
-    let mut can_socket_rx = tokio_socketcan::CANSocket::open(&args.can_interface)?;
-    let can_socket_tx = tokio_socketcan::CANSocket::open(&args.can_interface)?;
+    let mut recorder = match &args.record {
+        Some(dir) => Some(battery::recorder::Recorder::new(
+            dir,
+            "charger",
+            &args.can_interface,
+            args.record_file_mb * 1024 * 1024,
+            args.record_max_files,
+        )?),
+        None => None,
+    };
+
+    let (metrics_tx, metrics_rx) = tokio::sync::watch::channel(String::new());
+    if let Some(addr) = args.http {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(|e| eyre::eyre!("failed to bind {addr}: {e}"))?;
+        tokio::spawn(metrics::serve(listener, metrics_rx));
+    }
+
+    let mut transport = transport::SocketCan::open(&args.can_interface)?;
 
     let timeout = tokio::time::sleep(tokio::time::Duration::from_secs(1));
     tokio::pin!(timeout);
 
+    let mut charger_state = telemetry::ChargerState::default();
+
+    // Start the charger.  We send it again below if the charger's
+    // heartbeat says it's still Pre-operational.
+    let mut nmt = nmt::Nmt::default();
+    transport::send_message(&mut transport, &nmt::nmt_start()?).await?;
+
+    let mut setpoint =
+        setpoint::Setpoint::new(args.volts, args.amps, args.temperature, args.soc, args.ramp);
+    let mut last_step = tokio::time::Instant::now();
+
+    // Setpoint commands from stdin and the control socket.
+    let (request_tx, mut request_rx) = tokio::sync::mpsc::channel::<control::Request>(16);
+    if args.interactive {
+        control::read_stdin(request_tx.clone());
+    }
+    if let Some(path) = &args.control_socket {
+        battery::control_socket::listen(path, control::parse_command, request_tx.clone())?;
+    }
+    drop(request_tx);
+
+    let mut follower = follow::Follower::new(args.volts, args.amps);
+    if args.follow_battery {
+        log_event(&mut recorder, &format!("follow: {}", follower.state()));
+    }
+
+    let request_timeout = tokio::time::sleep(follow::CHARGE_REQUEST_TIMEOUT);
+    tokio::pin!(request_timeout);
+
     let (ctrlc_tx, mut ctrlc_rx) = tokio::sync::mpsc::channel::<()>(1);
     ctrlc::set_handler(move || {
         let _ = ctrlc_tx.try_send(());
@@ -109,24 +211,151 @@ async fn main() -> Result<(), eyre::Report> {
                 println!();
                 println!("Goodbye!");
                 // Shut down the charger.
-                let _ = send_command(&can_socket_tx, 0.0, 0.0, args.temperature, args.soc).await;
+                let _ = send_command(&mut transport, 0.0, 0.0, setpoint.temperature(), setpoint.soc()).await;
+                record(&mut recorder, |r| r.flush());
                 break;
             }
 
-            maybe_frame = can_socket_rx.next() => {
+            Some(request) = request_rx.recv() => {
+                let reply = match request.command {
+                    control::Command::SetVolts(volts) => {
+                        setpoint.set_volts(volts);
+                        Ok(String::new())
+                    }
+                    control::Command::SetAmps(amps) => {
+                        setpoint.set_amps(amps);
+                        Ok(String::new())
+                    }
+                    control::Command::SetTemperature(temperature) => {
+                        setpoint.set_temperature(temperature);
+                        Ok(String::new())
+                    }
+                    control::Command::SetSoc(soc) => {
+                        setpoint.set_soc(soc);
+                        Ok(String::new())
+                    }
+                    control::Command::SetRamp(ramp) => {
+                        setpoint.set_ramp(ramp);
+                        Ok(String::new())
+                    }
+                    control::Command::Status => {
+                        let (volts, amps) = commanded(args.follow_battery, &follower, &setpoint);
+                        let mut lines = vec![setpoint.to_string()];
+                        if args.follow_battery {
+                            lines.push(format!("follow: {}", follower.state()));
+                        }
+                        lines.push(format!("charger NMT state: {}", nmt.state()));
+                        lines.push(charger_state.report(volts, amps, std::time::Instant::now()));
+                        Ok(lines.join("\n"))
+                    }
+                };
+                if !matches!(request.command, control::Command::Status) {
+                    // When following, `volts` and `amps` are the
+                    // ceilings.
+                    follower.set_ceilings(setpoint.volts(), setpoint.target_amps());
+                    log_event(&mut recorder, &setpoint.to_string());
+                    // Changes apply right away, don't wait for the
+                    // next tick.  A current change starts its ramp from
+                    // where we are, so this doesn't step it.
+                    if nmt.is_operational() {
+                        let (volts, amps) = commanded(args.follow_battery, &follower, &setpoint);
+                        let _ = send_command(&mut transport, volts, amps, setpoint.temperature(), setpoint.soc()).await;
+                    }
+                }
+                // The requester may have given up, that's fine.
+                let _ = request.reply.send(reply);
+            }
+
+            maybe_frame = transport.recv() => {
                 match maybe_frame {
-                    Some(Ok(_frame)) => {
-                        // let _ = handle_can_frame(frame);
+                    Some(Ok((id, data))) => {
+                        let now = std::time::Instant::now();
+                        record(&mut recorder, |r| r.record_frame(id, &data));
+
+                        let old_nmt_state = nmt.state();
+                        if nmt.handle_can_frame(id, &data, now) {
+                            let _ = transport::send_message(&mut transport, &nmt::heartbeat_response()?).await;
+                            if nmt.state() != old_nmt_state {
+                                log_event(&mut recorder, &format!("charger NMT state: {}", nmt.state()));
+                            }
+                            if nmt.state() == nmt::NmtState::PreOperational {
+                                let _ = transport::send_message(&mut transport, &nmt::nmt_start()?).await;
+                            }
+                        }
+
+                        let events = charger_state.handle_can_frame(id, &data, now).unwrap_or_default();
+                        for event in events {
+                            log_event(&mut recorder, &format!("charger: {event}"));
+                            if let telemetry::ChargerEvent::ErrorRaised(error) = event {
+                                if error.is_hardware_fault() {
+                                    log_event(&mut recorder, "charger hardware fault, shutting down");
+                                    let _ = send_command(&mut transport, 0.0, 0.0, setpoint.temperature(), setpoint.soc()).await;
+                                    record(&mut recorder, |r| r.flush());
+                                    std::process::exit(EXIT_HARDWARE_FAULT);
+                                }
+                            }
+                        }
+                        if !args.follow_battery {
+                            continue;
+                        }
+                        if let Some(request) = follow::ChargeRequest::from_frame(id, &data) {
+                            request_timeout.as_mut().reset(tokio::time::Instant::now() + follow::CHARGE_REQUEST_TIMEOUT);
+                            if follower.handle_request(request) {
+                                // Follow changes right away, don't wait
+                                // for the next tick.
+                                log_event(&mut recorder, &format!("follow: {}", follower.state()));
+                                if nmt.is_operational() {
+                                    let (volts, amps) = follower.setpoint();
+                                    let _ = send_command(&mut transport, volts, amps, setpoint.temperature(), setpoint.soc()).await;
+                                }
+                            }
+                        }
                     }
                     _ => ()
                 }
             }
+
+            _ = &mut request_timeout, if follower.is_following() => {
+                if follower.time_out() {
+                    log_event(&mut recorder, &format!("follow: {}", follower.state()));
+                    let _ = send_command(&mut transport, 0.0, 0.0, setpoint.temperature(), setpoint.soc()).await;
+                }
+            }
+
             _ = &mut timeout => {
-                let _ = send_command(&can_socket_tx, args.volts, args.amps, args.temperature, args.soc).await;
+                let now = tokio::time::Instant::now();
+
+                let old_nmt_state = nmt.state();
+                nmt.check_timeout(now.into_std());
+                if nmt.state() != old_nmt_state {
+                    log_event(&mut recorder, &format!("charger NMT state: {}", nmt.state()));
+                }
+
+                setpoint.step(now - last_step);
+                last_step = now;
+
+                let (volts, amps) = commanded(args.follow_battery, &follower, &setpoint);
+                if nmt.is_operational() {
+                    let _ = send_command(&mut transport, volts, amps, setpoint.temperature(), setpoint.soc()).await;
+                    println!("{}", charger_state.report(volts, amps, now.into_std()));
+                } else {
+                    // Don't command current until the charger is
+                    // Operational.
+                    println!("charger not Operational ({}), not sending commands", nmt.state());
+                }
+                record(&mut recorder, |r| {
+                    r.record_telemetry(&charger_state.telemetry_line(volts, amps, nmt.state(), now.into_std()))?;
+                    r.flush()
+                });
+                metrics_tx.send_replace(charger_state.metrics(volts, amps, nmt.state(), now.into_std()));
                 timeout.set(tokio::time::sleep(tokio::time::Duration::from_secs(1)));
             }
         }
     }
 
+    if let Some(path) = &args.control_socket {
+        let _ = std::fs::remove_file(path);
+    }
+
     Ok(())
 }
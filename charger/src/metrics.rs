@@ -0,0 +1,31 @@
+// Serves the charger's telemetry to Prometheus at `/metrics`.  The main
+// loop renders the metrics on every tick and hands them over through a
+// watch channel, so scrapes never wait on the CAN bus.
+
+/// Serve `/metrics` on `listener`, answering with the latest text from
+/// `metrics_rx`.  If the server fails, that's logged and the charger
+/// carries on without it.
+pub async fn serve(
+    listener: tokio::net::TcpListener,
+    metrics_rx: tokio::sync::watch::Receiver<String>,
+) {
+    let router = axum::Router::new()
+        .route("/metrics", axum::routing::get(get_metrics))
+        .with_state(metrics_rx);
+    if let Err(e) = axum::serve(listener, router).await {
+        println!("metrics server stopped: {e}");
+    }
+}
+
+async fn get_metrics(
+    axum::extract::State(metrics_rx): axum::extract::State<tokio::sync::watch::Receiver<String>>,
+) -> impl axum::response::IntoResponse {
+    let metrics = metrics_rx.borrow().clone();
+    (
+        [(
+            axum::http::header::CONTENT_TYPE,
+            battery::prometheus::CONTENT_TYPE,
+        )],
+        metrics,
+    )
+}
@@ -0,0 +1,142 @@
+// CANopen network management of the Delta-Q charger.
+//
+// The charger boots into Pre-operational and only acts on our RPDOs
+// once we send it NMT Start.  It produces a heartbeat with its NMT
+// state, and expects a heartbeat back from the battery side, or it
+// raises "E-0-3-2 Communication heartbeat lost error".
+//
+// DeltaQ_Heartbeat_0x70a(
+//     Heartbeat: 5
+// )
+
+use crate::delta_q_can_messages;
+
+/// The charger's CANopen node id.
+pub const CHARGER_NODE_ID: u8 = 0x0a;
+
+/// How long the charger's heartbeat is good for before we consider it
+/// lost.
+pub const HEARTBEAT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// The NMT command that moves a node to Operational.
+pub const NMT_COMMAND_START: u8 = 1;
+
+const HEARTBEAT_OPERATIONAL: u8 = 5;
+const HEARTBEAT_PRE_OPERATIONAL: u8 = 127;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NmtState {
+    /// No heartbeat seen from the charger yet.
+    Unknown,
+    PreOperational,
+    Operational,
+    Other(u8),
+    /// The charger stopped sending heartbeats.
+    Lost,
+}
+
+impl NmtState {
+    /// The state a node's heartbeat says it's in.
+    pub fn from_heartbeat(heartbeat: u8) -> Self {
+        match heartbeat {
+            HEARTBEAT_OPERATIONAL => NmtState::Operational,
+            HEARTBEAT_PRE_OPERATIONAL => NmtState::PreOperational,
+            x => NmtState::Other(x),
+        }
+    }
+}
+
+impl std::fmt::Display for NmtState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            NmtState::Unknown => write!(f, "no heartbeat yet"),
+            NmtState::PreOperational => write!(f, "Pre-operational"),
+            NmtState::Operational => write!(f, "Operational"),
+            NmtState::Other(x) => write!(f, "unknown NMT state {x}"),
+            NmtState::Lost => write!(f, "heartbeat lost"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Nmt {
+    state: NmtState,
+    last_heartbeat: Option<std::time::Instant>,
+}
+
+impl Default for Nmt {
+    fn default() -> Self {
+        Nmt {
+            state: NmtState::Unknown,
+            last_heartbeat: None,
+        }
+    }
+}
+
+impl Nmt {
+    pub fn state(&self) -> NmtState {
+        self.state
+    }
+
+    pub fn is_operational(&self) -> bool {
+        self.state == NmtState::Operational
+    }
+
+    /// Returns true if the frame is the charger's heartbeat.
+    pub fn handle_can_frame(
+        &mut self,
+        id: embedded_can::Id,
+        data: &[u8],
+        now: std::time::Instant,
+    ) -> bool {
+        match delta_q_can_messages::Messages::from_can_message(id, data) {
+            Ok(delta_q_can_messages::Messages::DeltaQHeartbeat0x70a(m)) => {
+                self.state = NmtState::from_heartbeat(m.heartbeat_raw());
+                self.last_heartbeat = Some(now);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Call periodically to notice when the charger's heartbeat goes
+    /// quiet.
+    pub fn check_timeout(&mut self, now: std::time::Instant) {
+        if let Some(last_heartbeat) = self.last_heartbeat {
+            if now.saturating_duration_since(last_heartbeat) > HEARTBEAT_TIMEOUT {
+                self.state = NmtState::Lost;
+            }
+        }
+    }
+}
+
+/// NMT Start, moves the charger from Pre-operational to Operational.
+pub fn nmt_start() -> Result<delta_q_can_messages::NmtStart, eyre::Report> {
+    Ok(delta_q_can_messages::NmtStart::new(
+        CHARGER_NODE_ID,
+        NMT_COMMAND_START,
+    )?)
+}
+
+/// The charger's heartbeat in `state`, for the simulator.  Unknown and
+/// Lost are only ever our view of the charger, they have no heartbeat.
+pub fn heartbeat(
+    state: NmtState,
+) -> Result<delta_q_can_messages::DeltaQHeartbeat0x70a, eyre::Report> {
+    let heartbeat = match state {
+        NmtState::PreOperational => HEARTBEAT_PRE_OPERATIONAL,
+        NmtState::Operational => HEARTBEAT_OPERATIONAL,
+        NmtState::Other(x) => x,
+        NmtState::Unknown | NmtState::Lost => {
+            return Err(eyre::eyre!("there's no heartbeat for {state}"))
+        }
+    };
+    Ok(delta_q_can_messages::DeltaQHeartbeat0x70a::new(heartbeat)?)
+}
+
+/// The battery-side heartbeat the charger expects from us.
+pub fn heartbeat_response() -> Result<delta_q_can_messages::HeartbeatResponse, eyre::Report> {
+    Ok(delta_q_can_messages::HeartbeatResponse::new(
+        HEARTBEAT_OPERATIONAL,
+    )?)
+}
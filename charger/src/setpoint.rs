@@ -0,0 +1,121 @@
+// What we command the charger with when we're not following the pack:
+// voltage, current, and the battery temperature and SOC we report to
+// it.  All of it can change while we run.  Current changes ramp at a
+// limited rate instead of stepping, so that tracking something that
+// moves around (like solar surplus) doesn't jerk the charger around.
+
+#[derive(Clone, Copy, Debug)]
+pub struct Setpoint {
+    volts: f32,
+    amps: f32,
+    target_amps: f32,
+    temperature: f32,
+    soc: u8,
+
+    /// A/s, 0 for no ramp.
+    ramp: f32,
+}
+
+impl Setpoint {
+    /// Start out at `amps` right away, later changes ramp at `ramp`
+    /// A/s.
+    pub fn new(volts: f32, amps: f32, temperature: f32, soc: u8, ramp: f32) -> Self {
+        Setpoint {
+            volts,
+            amps,
+            target_amps: amps,
+            temperature,
+            soc,
+            ramp,
+        }
+    }
+
+    pub fn volts(&self) -> f32 {
+        self.volts
+    }
+
+    /// The current to command right now, somewhere on the ramp to
+    /// `target_amps()`.
+    pub fn amps(&self) -> f32 {
+        self.amps
+    }
+
+    pub fn target_amps(&self) -> f32 {
+        self.target_amps
+    }
+
+    pub fn temperature(&self) -> f32 {
+        self.temperature
+    }
+
+    pub fn soc(&self) -> u8 {
+        self.soc
+    }
+
+    pub fn ramp(&self) -> f32 {
+        self.ramp
+    }
+
+    pub fn is_ramping(&self) -> bool {
+        self.amps != self.target_amps
+    }
+
+    pub fn set_volts(&mut self, volts: f32) {
+        self.volts = volts;
+    }
+
+    /// Ramp to `amps` from wherever we are now.
+    pub fn set_amps(&mut self, amps: f32) {
+        self.target_amps = amps;
+        if self.ramp <= 0.0 {
+            self.amps = amps;
+        }
+    }
+
+    pub fn set_temperature(&mut self, temperature: f32) {
+        self.temperature = temperature;
+    }
+
+    pub fn set_soc(&mut self, soc: u8) {
+        self.soc = soc;
+    }
+
+    /// Change the ramp rate.  A ramp in progress carries on at the new
+    /// rate, or finishes right away if the new rate is 0.
+    pub fn set_ramp(&mut self, ramp: f32) {
+        self.ramp = ramp;
+        if ramp <= 0.0 {
+            self.amps = self.target_amps;
+        }
+    }
+
+    /// Move the current `dt` further along the ramp.  Returns true if
+    /// it changed.
+    pub fn step(&mut self, dt: std::time::Duration) -> bool {
+        if !self.is_ramping() {
+            return false;
+        }
+        let old_amps = self.amps;
+        let max_step = self.ramp * dt.as_secs_f32();
+        let delta = (self.target_amps - self.amps).clamp(-max_step, max_step);
+        self.amps = match (self.target_amps - self.amps - delta).abs() < 0.001 {
+            true => self.target_amps,
+            false => self.amps + delta,
+        };
+        self.amps != old_amps
+    }
+}
+
+impl std::fmt::Display for Setpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "setpoint: {:.3}V {:.3}A", self.volts, self.amps)?;
+        if self.is_ramping() {
+            write!(
+                f,
+                " (ramping to {:.3}A at {}A/s)",
+                self.target_amps, self.ramp
+            )?;
+        }
+        write!(f, ", reporting {:.1}°C {}% SOC", self.temperature, self.soc)
+    }
+}
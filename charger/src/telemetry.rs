@@ -0,0 +1,368 @@
+// Telemetry reported by the Delta-Q charger in its TPDOs.
+//
+// DeltaQ_TPDO1_0x18a(
+//     Override_Status: 0,
+//     Charging_Current: 1.99609375 A,
+//     Charger_Status: true,
+//     Charger_Hardware_Shutdown_Status: false,
+//     Charger_Derating_Status: false,
+//     Charge_Indication: 1,
+//     Charge_Cycle_Type: 1,
+//     Battery_Voltage: 50.01171875 V,
+//     AC_Connection_Status: true
+// )
+//
+// DeltaQ_TPDO2_0x28a(
+//     Wh_Returned: 12.5 Wh,
+//     Elapsed_Time: 480 s,
+//     Ah_Returned: 0.25 Ah
+// )
+//
+// DeltaQ_TPDO3_0x38a(
+//     Current_Error: 0,
+//     Charger_SOC: 0 %,
+//     AC_Voltage: 241.0625 VAC
+// )
+//
+// Fault_Register(
+//     Fault_Register: 0
+// )
+
+use crate::delta_q_can_messages;
+use crate::error_codes::DeltaQError;
+
+/// How long charger telemetry is good for before we call it stale.
+pub const TELEMETRY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Changes in the charger's error state, returned from
+/// `ChargerState::handle_can_frame()` so the caller can log them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChargerEvent {
+    ErrorRaised(DeltaQError),
+    ErrorCleared(DeltaQError),
+    FaultRegisterChanged { old: u64, new: u64 },
+}
+
+impl std::fmt::Display for ChargerEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ChargerEvent::ErrorRaised(e) => write!(f, "error raised: {e}"),
+            ChargerEvent::ErrorCleared(e) => write!(f, "error cleared: {e}"),
+            ChargerEvent::FaultRegisterChanged { old, new } => {
+                write!(f, "fault register changed: 0x{old:016x} -> 0x{new:016x}")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ChargerState {
+    pub output_voltage: f32,
+    pub output_current: f32,
+    pub enabled: bool,
+    pub derating: bool,
+    pub hardware_shutdown: bool,
+    pub ac_detected: bool,
+    pub charge_indication: u8,
+
+    pub elapsed_time_s: u32,
+    pub ah_returned: f32,
+    pub wh_returned: f32,
+
+    pub ac_voltage: f32,
+    pub charger_soc: u8,
+    pub current_error: Option<DeltaQError>,
+    pub fault_register: u64,
+
+    pub last_seen: Option<std::time::Instant>,
+}
+
+impl ChargerState {
+    /// Update the charger state from a CAN frame, returning any changes
+    /// to the charger's error state.
+    pub fn handle_can_frame(
+        &mut self,
+        id: embedded_can::Id,
+        data: &[u8],
+        now: std::time::Instant,
+    ) -> Result<Vec<ChargerEvent>, eyre::Report> {
+        let msg = delta_q_can_messages::Messages::from_can_message(id, data)?;
+        let mut events = Vec::new();
+
+        match msg {
+            delta_q_can_messages::Messages::DeltaQTpdo10x18a(m) => {
+                self.output_voltage = m.battery_voltage();
+                self.output_current = m.charging_current();
+                self.enabled = m.charger_status_raw();
+                self.derating = m.charger_derating_status_raw();
+                self.hardware_shutdown = m.charger_hardware_shutdown_status_raw();
+                self.ac_detected = m.ac_connection_status_raw();
+                self.charge_indication = m.charge_indication_raw();
+                self.last_seen = Some(now);
+            }
+
+            delta_q_can_messages::Messages::DeltaQTpdo20x28a(m) => {
+                self.elapsed_time_s = m.elapsed_time() as u32;
+                self.ah_returned = m.ah_returned();
+                self.wh_returned = m.wh_returned();
+                self.last_seen = Some(now);
+            }
+
+            delta_q_can_messages::Messages::DeltaQTpdo30x38a(m) => {
+                self.ac_voltage = m.ac_voltage();
+                self.charger_soc = m.charger_soc();
+                let error = DeltaQError::from_raw(m.current_error_raw());
+                if error != self.current_error {
+                    if let Some(old) = self.current_error {
+                        events.push(ChargerEvent::ErrorCleared(old));
+                    }
+                    if let Some(new) = error {
+                        events.push(ChargerEvent::ErrorRaised(new));
+                    }
+                    self.current_error = error;
+                }
+                self.last_seen = Some(now);
+            }
+
+            delta_q_can_messages::Messages::FaultRegister(m) => {
+                let fault_register = m.fault_register();
+                if fault_register != self.fault_register {
+                    events.push(ChargerEvent::FaultRegisterChanged {
+                        old: self.fault_register,
+                        new: fault_register,
+                    });
+                    self.fault_register = fault_register;
+                }
+            }
+
+            _ => (), // ignore all other messages
+        }
+
+        Ok(events)
+    }
+
+    pub fn is_stale(&self, now: std::time::Instant) -> bool {
+        match self.last_seen {
+            None => true,
+            Some(last_seen) => now.saturating_duration_since(last_seen) > TELEMETRY_TIMEOUT,
+        }
+    }
+
+    pub fn charge_indication_name(&self) -> String {
+        match self.charge_indication {
+            0 => String::from("Inactive"),
+            1 => String::from("Less than 80%"),
+            2 => String::from("More than 80%"),
+            3 => String::from("Finishing"),
+            4 => String::from("Complete"),
+            5 => String::from("Resting"),
+            6 => String::from("Equalize"),
+            7 => String::from("Power Supply Mode"),
+            x => format!("Unknown ({x})"),
+        }
+    }
+
+    /// The charger state as one line of `key=value` pairs for the
+    /// recorder, next to what we commanded and the charger's NMT
+    /// state.
+    pub fn telemetry_line(
+        &self,
+        commanded_volts: f32,
+        commanded_amps: f32,
+        nmt_state: crate::nmt::NmtState,
+        now: std::time::Instant,
+    ) -> String {
+        let mut fields = vec![
+            String::from("charger"),
+            format!("nmt={:?}", nmt_state.to_string()),
+            format!("commanded_voltage={commanded_volts:.3}"),
+            format!("commanded_current={commanded_amps:.3}"),
+        ];
+        if !self.is_stale(now) {
+            fields.push(format!("voltage={:.3}", self.output_voltage));
+            fields.push(format!("current={:.3}", self.output_current));
+            fields.push(format!("enabled={}", self.enabled));
+            fields.push(format!("derating={}", self.derating));
+            fields.push(format!("hardware_shutdown={}", self.hardware_shutdown));
+            fields.push(format!("ac_detected={}", self.ac_detected));
+            fields.push(format!("ac_voltage={:.1}", self.ac_voltage));
+            fields.push(format!("indication={:?}", self.charge_indication_name()));
+            fields.push(format!("soc={}", self.charger_soc));
+            fields.push(format!("ah_returned={:.3}", self.ah_returned));
+            fields.push(format!("wh_returned={:.1}", self.wh_returned));
+            fields.push(format!("elapsed_time={}", self.elapsed_time_s));
+        }
+        if let Some(error) = self.current_error {
+            fields.push(format!("error={}", error.code()));
+        }
+        fields.push(format!("fault_register=0x{:016x}", self.fault_register));
+        fields.join(" ")
+    }
+
+    /// The charger state and what we commanded, in the Prometheus text
+    /// format.  The measured values are left out while stale.
+    pub fn metrics(
+        &self,
+        commanded_volts: f32,
+        commanded_amps: f32,
+        nmt_state: crate::nmt::NmtState,
+        now: std::time::Instant,
+    ) -> String {
+        let mut metrics = battery::prometheus::Metrics::default();
+
+        metrics.gauge(
+            "charger_commanded_voltage_volts",
+            "Charge voltage we're commanding.",
+            &[],
+            commanded_volts.into(),
+        );
+        metrics.gauge(
+            "charger_commanded_current_amperes",
+            "Charge current we're commanding.",
+            &[],
+            commanded_amps.into(),
+        );
+        metrics.flag(
+            "charger_operational",
+            "1 if the charger is in the NMT Operational state.",
+            &[],
+            nmt_state == crate::nmt::NmtState::Operational,
+        );
+        metrics.flag(
+            "charger_telemetry_fresh",
+            "1 if the charger's TPDOs are arriving.",
+            &[],
+            !self.is_stale(now),
+        );
+
+        if !self.is_stale(now) {
+            metrics.gauge(
+                "charger_voltage_volts",
+                "Measured battery voltage at the charger output.",
+                &[],
+                self.output_voltage.into(),
+            );
+            metrics.gauge(
+                "charger_current_amperes",
+                "Measured charger output current.",
+                &[],
+                self.output_current.into(),
+            );
+            metrics.flag(
+                "charger_enabled",
+                "1 if the charger output is on.",
+                &[],
+                self.enabled,
+            );
+            metrics.flag(
+                "charger_derating",
+                "1 if the charger is derating its output.",
+                &[],
+                self.derating,
+            );
+            metrics.flag(
+                "charger_hardware_shutdown",
+                "1 if the charger has shut down its hardware.",
+                &[],
+                self.hardware_shutdown,
+            );
+            metrics.flag(
+                "charger_ac_detected",
+                "1 if the charger sees AC input.",
+                &[],
+                self.ac_detected,
+            );
+            metrics.gauge(
+                "charger_ac_voltage_volts",
+                "AC input voltage.",
+                &[],
+                self.ac_voltage.into(),
+            );
+            metrics.gauge(
+                "charger_soc_percent",
+                "The charger's estimate of battery state of charge.",
+                &[],
+                self.charger_soc.into(),
+            );
+            metrics.gauge(
+                "charger_returned_amp_hours",
+                "Charge returned this cycle.",
+                &[],
+                self.ah_returned.into(),
+            );
+            metrics.gauge(
+                "charger_returned_watt_hours",
+                "Energy returned this cycle.",
+                &[],
+                self.wh_returned.into(),
+            );
+            metrics.gauge(
+                "charger_cycle_elapsed_seconds",
+                "Time since the start of this charge cycle.",
+                &[],
+                self.elapsed_time_s.into(),
+            );
+        }
+        if let Some(error) = self.current_error {
+            metrics.flag(
+                "charger_error",
+                "1 for the charger's current error code.",
+                &[("code", error.code())],
+                true,
+            );
+        }
+
+        metrics.render()
+    }
+
+    /// One line summary of the charger state, next to what we
+    /// commanded it to do.
+    pub fn report(
+        &self,
+        commanded_volts: f32,
+        commanded_amps: f32,
+        now: std::time::Instant,
+    ) -> String {
+        if self.is_stale(now) {
+            return format!(
+                "charger: no telemetry (commanded {:.3}V {:.3}A)",
+                commanded_volts, commanded_amps
+            );
+        }
+
+        let mut flags = vec![
+            String::from(match self.enabled {
+                true => "enabled",
+                false => "disabled",
+            }),
+            String::from(match self.ac_detected {
+                true => "AC detected",
+                false => "no AC",
+            }),
+        ];
+        if self.derating {
+            flags.push(String::from("DERATING"));
+        }
+        if self.hardware_shutdown {
+            flags.push(String::from("HARDWARE SHUTDOWN"));
+        }
+        if let Some(error) = self.current_error {
+            flags.push(format!("error {} {}", error.code(), error.description()));
+        }
+
+        format!(
+            "charger: {:.3}V {:.3}A (commanded {:.3}V {:.3}A), {}, {}, AC {:.1}V, SOC {}%, {:.3}Ah {:.1}Wh returned in {}s",
+            self.output_voltage,
+            self.output_current,
+            commanded_volts,
+            commanded_amps,
+            self.charge_indication_name(),
+            flags.join(", "),
+            self.ac_voltage,
+            self.charger_soc,
+            self.ah_returned,
+            self.wh_returned,
+            self.elapsed_time_s,
+        )
+    }
+}
@@ -0,0 +1,74 @@
+// Following the pack's BATT_chargerControl requests.
+
+use embedded_can::Frame;
+
+use battery::abs_alliance_can_messages;
+use charger::follow;
+
+fn charger_control(enable: bool, volts: f32, amps: f32) -> follow::ChargeRequest {
+    let m = abs_alliance_can_messages::BattChargerControl::new(
+        false, 0, true, 1, 3, enable, volts, amps,
+    )
+    .unwrap();
+    follow::ChargeRequest::from_frame(m.id(), m.data()).unwrap()
+}
+
+#[test]
+fn only_charger_control_is_a_charge_request() {
+    let m = abs_alliance_can_messages::HostBatteryRequest::new(
+        false,
+        false,
+        false,
+        false,
+        false,
+        abs_alliance_can_messages::HostBatteryRequestHostStateRequest::Charge.into(),
+    )
+    .unwrap();
+    assert!(follow::ChargeRequest::from_frame(m.id(), m.data()).is_none());
+}
+
+#[test]
+fn request_is_clamped_to_the_ceilings() {
+    let mut follower = follow::Follower::new(54.0, 20.0);
+    assert_eq!(follower.state(), follow::FollowState::WaitingForRequest);
+    assert!(!follower.is_following());
+    assert_eq!(follower.setpoint(), (0.0, 0.0));
+
+    assert!(follower.handle_request(charger_control(true, 52.835, 12.0)));
+    assert!(follower.is_following());
+    let (volts, amps) = follower.setpoint();
+    assert!((volts - 52.835).abs() < 0.01);
+    assert!((amps - 12.0).abs() < 0.01);
+
+    assert!(follower.handle_request(charger_control(true, 58.0, 36.0)));
+    assert_eq!(follower.setpoint(), (54.0, 20.0));
+
+    // The same request again isn't a change.
+    assert!(!follower.handle_request(charger_control(true, 58.0, 36.0)));
+}
+
+#[test]
+fn disabled_request_stops_charging() {
+    let mut follower = follow::Follower::new(54.0, 20.0);
+    follower.handle_request(charger_control(true, 52.835, 12.0));
+
+    assert!(follower.handle_request(charger_control(false, 52.835, 12.0)));
+    assert_eq!(follower.state(), follow::FollowState::Disabled);
+    assert!(follower.is_following());
+    assert_eq!(follower.setpoint(), (0.0, 0.0));
+}
+
+#[test]
+fn timed_out_request_stops_charging() {
+    let mut follower = follow::Follower::new(54.0, 20.0);
+    follower.handle_request(charger_control(true, 52.835, 12.0));
+
+    assert!(follower.time_out());
+    assert_eq!(follower.state(), follow::FollowState::TimedOut);
+    assert!(!follower.is_following());
+    assert_eq!(follower.setpoint(), (0.0, 0.0));
+
+    // A fresh request picks it back up.
+    assert!(follower.handle_request(charger_control(true, 52.835, 12.0)));
+    assert!(follower.is_following());
+}
@@ -0,0 +1,62 @@
+// Ramping the commanded current to a new setpoint.
+
+use charger::setpoint;
+
+fn secs(secs: f32) -> std::time::Duration {
+    std::time::Duration::from_secs_f32(secs)
+}
+
+fn assert_close(actual: f32, expected: f32) {
+    assert!(
+        (actual - expected).abs() < 0.01,
+        "expected {expected}, got {actual}"
+    );
+}
+
+#[test]
+fn starts_at_the_initial_current() {
+    let mut setpoint = setpoint::Setpoint::new(54.0, 10.0, 20.0, 50, 2.0);
+    assert_close(setpoint.amps(), 10.0);
+    assert!(!setpoint.is_ramping());
+    assert!(!setpoint.step(secs(1.0)));
+}
+
+#[test]
+fn current_ramps_up_and_down() {
+    let mut setpoint = setpoint::Setpoint::new(54.0, 10.0, 20.0, 50, 2.0);
+
+    setpoint.set_amps(15.0);
+    assert_close(setpoint.amps(), 10.0);
+    assert_close(setpoint.target_amps(), 15.0);
+    assert!(setpoint.is_ramping());
+
+    assert!(setpoint.step(secs(1.0)));
+    assert_close(setpoint.amps(), 12.0);
+    assert!(setpoint.step(secs(2.0)));
+    assert_close(setpoint.amps(), 15.0);
+    assert!(!setpoint.is_ramping());
+
+    // Changing our mind halfway down starts from where we got to.
+    setpoint.set_amps(5.0);
+    setpoint.step(secs(2.5));
+    assert_close(setpoint.amps(), 10.0);
+    setpoint.set_amps(12.0);
+    setpoint.step(secs(0.5));
+    assert_close(setpoint.amps(), 11.0);
+}
+
+#[test]
+fn zero_ramp_steps_straight_there() {
+    let mut setpoint = setpoint::Setpoint::new(54.0, 10.0, 20.0, 50, 0.0);
+    setpoint.set_amps(20.0);
+    assert_close(setpoint.amps(), 20.0);
+    assert!(!setpoint.is_ramping());
+
+    // Turning the ramp off finishes one in progress.
+    setpoint.set_ramp(1.0);
+    setpoint.set_amps(0.0);
+    setpoint.step(secs(1.0));
+    assert_close(setpoint.amps(), 19.0);
+    setpoint.set_ramp(0.0);
+    assert_close(setpoint.amps(), 0.0);
+}
@@ -0,0 +1,195 @@
+// Feeds hand-built Delta-Q frames into the charger's telemetry and NMT
+// state and checks what they make of them.
+
+use embedded_can::Frame;
+
+use charger::delta_q_can_messages;
+use charger::error_codes::DeltaQError;
+use charger::nmt;
+use charger::telemetry;
+
+fn assert_close(actual: f32, expected: f32) {
+    assert!(
+        (actual - expected).abs() < 0.01,
+        "expected {expected}, got {actual}"
+    );
+}
+
+fn tpdo3(error: Option<DeltaQError>) -> delta_q_can_messages::DeltaQTpdo30x38a {
+    delta_q_can_messages::DeltaQTpdo30x38a::new(error.map(|e| e.raw()).unwrap_or(0), 62, 241.0)
+        .unwrap()
+}
+
+#[test]
+fn tpdos_update_the_charger_state() {
+    let now = std::time::Instant::now();
+    let mut charger_state = telemetry::ChargerState::default();
+    assert!(charger_state.last_seen.is_none());
+    assert!(charger_state.is_stale(now));
+
+    let m =
+        delta_q_can_messages::DeltaQTpdo10x18a::new(0, 12.5, true, false, true, 1, 1, 51.2, true)
+            .unwrap();
+    let events = charger_state
+        .handle_can_frame(m.id(), m.data(), now)
+        .unwrap();
+    assert!(events.is_empty());
+    assert_close(charger_state.output_current, 12.5);
+    assert_close(charger_state.output_voltage, 51.2);
+    assert!(charger_state.enabled);
+    assert!(charger_state.derating);
+    assert!(!charger_state.hardware_shutdown);
+    assert!(charger_state.ac_detected);
+    assert_eq!(charger_state.charge_indication, 1);
+    assert!(charger_state.last_seen.is_some());
+    assert!(!charger_state.is_stale(now));
+
+    let m = delta_q_can_messages::DeltaQTpdo20x28a::new(640.0, 3600, 12.5).unwrap();
+    charger_state
+        .handle_can_frame(m.id(), m.data(), now)
+        .unwrap();
+    assert_close(charger_state.wh_returned, 640.0);
+    assert_eq!(charger_state.elapsed_time_s, 3600);
+    assert_close(charger_state.ah_returned, 12.5);
+
+    let m = tpdo3(None);
+    charger_state
+        .handle_can_frame(m.id(), m.data(), now)
+        .unwrap();
+    assert_eq!(charger_state.charger_soc, 62);
+    assert_close(charger_state.ac_voltage, 241.0);
+    assert!(charger_state.current_error.is_none());
+}
+
+#[test]
+fn errors_are_raised_and_cleared() {
+    let now = std::time::Instant::now();
+    let mut charger_state = telemetry::ChargerState::default();
+
+    let m = tpdo3(Some(DeltaQError::HeartbeatLost));
+    assert_eq!(
+        charger_state
+            .handle_can_frame(m.id(), m.data(), now)
+            .unwrap(),
+        vec![telemetry::ChargerEvent::ErrorRaised(
+            DeltaQError::HeartbeatLost
+        )]
+    );
+    assert_eq!(
+        charger_state.current_error,
+        Some(DeltaQError::HeartbeatLost)
+    );
+
+    // The charger repeats its error, that's not news.
+    assert!(charger_state
+        .handle_can_frame(m.id(), m.data(), now)
+        .unwrap()
+        .is_empty());
+
+    let m = tpdo3(Some(DeltaQError::TargetVoltageTooHigh));
+    assert_eq!(
+        charger_state
+            .handle_can_frame(m.id(), m.data(), now)
+            .unwrap(),
+        vec![
+            telemetry::ChargerEvent::ErrorCleared(DeltaQError::HeartbeatLost),
+            telemetry::ChargerEvent::ErrorRaised(DeltaQError::TargetVoltageTooHigh),
+        ]
+    );
+
+    let m = tpdo3(None);
+    assert_eq!(
+        charger_state
+            .handle_can_frame(m.id(), m.data(), now)
+            .unwrap(),
+        vec![telemetry::ChargerEvent::ErrorCleared(
+            DeltaQError::TargetVoltageTooHigh
+        )]
+    );
+    assert!(charger_state.current_error.is_none());
+}
+
+#[test]
+fn documented_error_codes_round_trip() {
+    for error in [
+        DeltaQError::ReferenceOutOfRange,
+        DeltaQError::HeartbeatLost,
+        DeltaQError::TargetVoltageTooHigh,
+        DeltaQError::OutputStage,
+    ] {
+        assert_eq!(DeltaQError::from_raw(error.raw()), Some(error));
+    }
+    assert_eq!(DeltaQError::from_raw(0), None);
+    assert_eq!(DeltaQError::HeartbeatLost.code(), "E-0-3-2");
+    assert!(!DeltaQError::HeartbeatLost.is_hardware_fault());
+    assert!(DeltaQError::OutputStage.is_hardware_fault());
+}
+
+#[test]
+fn heartbeat_gives_the_nmt_state() {
+    let now = std::time::Instant::now();
+    let mut nmt = nmt::Nmt::default();
+    assert_eq!(nmt.state(), nmt::NmtState::Unknown);
+
+    let m = delta_q_can_messages::DeltaQHeartbeat0x70a::new(127).unwrap();
+    assert!(nmt.handle_can_frame(m.id(), m.data(), now));
+    assert_eq!(nmt.state(), nmt::NmtState::PreOperational);
+    assert!(!nmt.is_operational());
+
+    let m = delta_q_can_messages::DeltaQHeartbeat0x70a::new(5).unwrap();
+    assert!(nmt.handle_can_frame(m.id(), m.data(), now));
+    assert!(nmt.is_operational());
+
+    // Other frames aren't the heartbeat.
+    let m = tpdo3(None);
+    assert!(!nmt.handle_can_frame(m.id(), m.data(), now));
+    assert!(nmt.is_operational());
+}
+
+#[test]
+fn heartbeat_loss_is_noticed() {
+    let start = std::time::Instant::now();
+    let mut nmt = nmt::Nmt::default();
+
+    // No heartbeat yet isn't a lost heartbeat.
+    nmt.check_timeout(start + nmt::HEARTBEAT_TIMEOUT * 2);
+    assert_eq!(nmt.state(), nmt::NmtState::Unknown);
+
+    let m = delta_q_can_messages::DeltaQHeartbeat0x70a::new(5).unwrap();
+    assert!(nmt.handle_can_frame(m.id(), m.data(), start));
+    nmt.check_timeout(start + nmt::HEARTBEAT_TIMEOUT);
+    assert!(nmt.is_operational());
+
+    nmt.check_timeout(start + nmt::HEARTBEAT_TIMEOUT + std::time::Duration::from_millis(1));
+    assert_eq!(nmt.state(), nmt::NmtState::Lost);
+    assert!(!nmt.is_operational());
+
+    // It comes back with the next heartbeat.
+    let later = start + nmt::HEARTBEAT_TIMEOUT * 3;
+    assert!(nmt.handle_can_frame(m.id(), m.data(), later));
+    assert!(nmt.is_operational());
+}
+
+#[test]
+fn heartbeats_round_trip() {
+    let now = std::time::Instant::now();
+    let mut nmt = nmt::Nmt::default();
+    for state in [
+        nmt::NmtState::PreOperational,
+        nmt::NmtState::Operational,
+        nmt::NmtState::Other(4),
+    ] {
+        let m = nmt::heartbeat(state).unwrap();
+        assert!(nmt.handle_can_frame(m.id(), m.data(), now));
+        assert_eq!(nmt.state(), state);
+    }
+    assert!(nmt::heartbeat(nmt::NmtState::Lost).is_err());
+}
+
+#[test]
+fn nmt_start_addresses_the_charger() {
+    let m = nmt::nmt_start().unwrap();
+    assert_eq!(m.nmt_node(), nmt::CHARGER_NODE_ID);
+    assert_eq!(m.nmt_command_raw(), nmt::NMT_COMMAND_START);
+    assert_eq!(nmt::heartbeat_response().unwrap().data(), &[5]);
+}